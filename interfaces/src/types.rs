@@ -36,6 +36,7 @@ use crate::types::{
 };
 
 pub mod block_data;
+pub mod entity_types;
 
 #[derive(Clone)]
 pub struct PacketData {
@@ -67,6 +68,12 @@ pub struct Chat {
 }
 
 impl Chat {
+    /// The text without formatting
+    pub fn plain(&self) -> String {
+        let extra = self.extra.iter().flatten().map(|section| &section.text);
+        self.text.iter().chain(extra).join("")
+    }
+
     pub fn colorize(self) -> String {
         if let Some(extra) = self.extra {
             extra.into_iter().map(|section| section.colorize()).join("")
@@ -43,7 +43,7 @@ impl Default for Material {
 pub struct RawBlock {
     pub id: u32,
     // pub display_name: String,
-    // pub name: String,
+    pub name: String,
     pub hardness: Option<f64>,
     pub harvest_tools: Option<HashMap<u32, bool>>,
     pub material: Option<Material>,
@@ -62,10 +62,12 @@ pub struct RawBlock {
 #[serde(rename_all = "camelCase")]
 pub struct RawFood {
     pub id: u32,
+    pub name: String,
 }
 
 pub struct Block {
     pub id: u32,
+    pub name: String,
     pub hardness: Option<f64>,
     pub harvest_tools: Vec<u32>,
     pub material: Material,
@@ -75,6 +77,7 @@ impl From<RawBlock> for Block {
     fn from(block: RawBlock) -> Self {
         Self {
             id: block.id,
+            name: block.name,
             hardness: block.hardness,
             harvest_tools: block
                 .harvest_tools
@@ -87,10 +90,16 @@ impl From<RawBlock> for Block {
     }
 }
 
+/// The block model every bot uses, whichever version the server is. Ids are
+/// the 1.12 ones (`BlockState` is `id << 4 | meta`) and protocols of other
+/// versions translate into them
 pub struct BlockData {
     // lookup by id
     block_lookup: HashMap<u32, Block>,
     food_lookup: HashSet<u32>,
+
+    /// ids of block and food names
+    name_lookup: HashMap<String, u32>,
 }
 
 impl Default for BlockData {
@@ -108,6 +117,31 @@ impl BlockData {
         self.food_lookup.contains(&id)
     }
 
+    /// The id of a block or food by its 1.12 name
+    pub fn by_name(&self, name: &str) -> Option<u32> {
+        self.name_lookup.get(name).copied()
+    }
+
+    pub fn new(blocks: Vec<RawBlock>, foods: Vec<RawFood>) -> BlockData {
+        let name_lookup = blocks
+            .iter()
+            .map(|block| (block.name.clone(), block.id))
+            .chain(foods.iter().map(|food| (food.name.clone(), food.id)))
+            .collect();
+
+        let food_lookup: HashSet<_> = foods.into_iter().map(|food| food.id).collect();
+
+        let blocks = blocks.into_iter().map(Block::from);
+
+        let block_lookup = blocks.map(|elem| (elem.id, elem)).collect();
+
+        BlockData {
+            block_lookup,
+            food_lookup,
+            name_lookup,
+        }
+    }
+
     pub fn read() -> Result<BlockData, serde_json::Error> {
         let blocks: Vec<RawBlock> = {
             let reader = OpenOptions::new().read(true).open("blocks.json").unwrap();
@@ -119,15 +153,6 @@ impl BlockData {
             serde_json::from_reader(reader)?
         };
 
-        let food_lookup: HashSet<_> = foods.into_iter().map(|food| food.id).collect();
-
-        let blocks = blocks.into_iter().map(Block::from);
-
-        let block_lookup = blocks.map(|elem| (elem.id, elem)).collect();
-
-        Ok(BlockData {
-            block_lookup,
-            food_lookup,
-        })
+        Ok(BlockData::new(blocks, foods))
     }
 }
@@ -0,0 +1,135 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, fs::OpenOptions};
+
+use serde::{Deserialize, Serialize};
+
+/// prismarine.js does not have a neutral category. These only attack when
+/// provoked
+const NEUTRAL_MOBS: [&str; 6] = [
+    "enderman",
+    "zombie_pigman",
+    "wolf",
+    "polar_bear",
+    "llama",
+    "villager_golem",
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EntityCategory {
+    Hostile,
+    Passive,
+    Neutral,
+    Projectile,
+
+    /// items, vehicles, falling blocks, ...
+    Other,
+}
+
+/// Mobs and objects are spawned with different packets and their ids overlap
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityClass {
+    Mob,
+    Object,
+}
+
+/// Uses prismarine.js entity data. We comment out the fields that we do not
+/// use
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawEntity {
+    pub id: u32,
+    // pub internal_id: u32,
+    pub name: String,
+    // pub display_name: String,
+    // pub width: f64,
+    // pub height: f64,
+    #[serde(rename = "type")]
+    pub class: EntityClass,
+    pub category: Option<String>,
+}
+
+pub struct EntityType {
+    pub id: u32,
+    pub name: String,
+    pub class: EntityClass,
+    pub category: EntityCategory,
+}
+
+impl From<RawEntity> for EntityType {
+    fn from(entity: RawEntity) -> Self {
+        let category = match entity.category.as_deref() {
+            _ if NEUTRAL_MOBS.contains(&entity.name.as_str()) => EntityCategory::Neutral,
+            Some("Hostile mobs") => EntityCategory::Hostile,
+            Some("Passive mobs") => EntityCategory::Passive,
+            Some("Projectiles") => EntityCategory::Projectile,
+            _ => EntityCategory::Other,
+        };
+
+        Self {
+            id: entity.id,
+            name: entity.name,
+            class: entity.class,
+            category,
+        }
+    }
+}
+
+/// The 1.12 mob and object types
+pub struct EntityTypes {
+    // lookup by type and id
+    lookup: HashMap<(EntityClass, u32), EntityType>,
+}
+
+impl Default for EntityTypes {
+    fn default() -> Self {
+        Self::read().unwrap()
+    }
+}
+
+impl EntityTypes {
+    pub fn mob(&self, id: u32) -> Option<&EntityType> {
+        self.lookup.get(&(EntityClass::Mob, id))
+    }
+
+    pub fn object(&self, id: u32) -> Option<&EntityType> {
+        self.lookup.get(&(EntityClass::Object, id))
+    }
+
+    /// The mob or object with the 1.12 name, i.e., `zombie`
+    pub fn by_name(&self, name: &str) -> Option<&EntityType> {
+        self.lookup.values().find(|entity| entity.name == name)
+    }
+
+    pub fn new(entities: Vec<RawEntity>) -> EntityTypes {
+        let lookup = entities
+            .into_iter()
+            .map(EntityType::from)
+            .map(|entity| ((entity.class, entity.id), entity))
+            .collect();
+
+        EntityTypes { lookup }
+    }
+
+    pub fn read() -> Result<EntityTypes, serde_json::Error> {
+        let entities: Vec<RawEntity> = {
+            let reader = OpenOptions::new().read(true).open("entities.json").unwrap();
+            serde_json::from_reader(reader)?
+        };
+
+        Ok(EntityTypes::new(entities))
+    }
+}
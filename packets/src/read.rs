@@ -66,6 +66,11 @@ impl ByteReader {
         self.len() == 0
     }
 
+    /// The bytes which have not been read yet
+    pub fn remaining(&self) -> &[u8] {
+        &self.bytes.get_ref()[self.bytes.position() as usize..]
+    }
+
     pub fn new(vec: Vec<u8>) -> ByteReader {
         let bytes = Cursor::new(vec);
         Self { bytes }
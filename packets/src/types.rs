@@ -89,6 +89,9 @@ impl From<u8> for BitField {
 #[derive(Copy, Clone, Debug)]
 pub struct VarUInt(pub usize);
 
+#[derive(Copy, Clone, Debug)]
+pub struct VarLong(pub i64);
+
 impl From<i32> for VarInt {
     fn from(input: i32) -> Self {
         VarInt(input)
@@ -235,13 +238,13 @@ impl ByteReadable for VarInt {
         let mut size = 0;
         let mut val = 0u32;
         loop {
+            if size >= 5 {
+                panic!("VarInt is longer than 5 bytes");
+            }
             let b: u8 = byte_reader.read();
             let b = b as u32;
             val |= (b & PART) << (size * 7);
             size += 1;
-            if size > 5 {
-                panic!("oop");
-            }
             if (b & 0x80) == 0 {
                 break;
             }
@@ -251,22 +254,61 @@ impl ByteReadable for VarInt {
 }
 
 impl VarInt {
-    pub async fn read_async<R: AsyncRead>(mut reader: Pin<&mut R>) -> VarInt {
+    pub async fn read_async<R: AsyncRead>(mut reader: Pin<&mut R>) -> std::io::Result<VarInt> {
         const PART: u32 = 0x7F;
         let mut size = 0;
         let mut val = 0u32;
         loop {
-            let b = reader.read_u8().await.unwrap() as u32;
+            if size >= 5 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "VarInt is longer than 5 bytes",
+                ));
+            }
+            let b = reader.read_u8().await? as u32;
             val |= (b & PART) << (size * 7);
             size += 1;
-            if size > 5 {
-                panic!("oop");
+            if (b & 0x80) == 0 {
+                break;
             }
+        }
+        Ok(VarInt(val as i32))
+    }
+}
+
+impl ByteWritable for VarLong {
+    fn write_to_bytes(self, writer: &mut ByteWriter) {
+        const PART: u64 = 0x7F;
+        let mut val = self.0 as u64;
+        loop {
+            if (val & !PART) == 0 {
+                writer.write(val as u8);
+                return;
+            }
+            writer.write(((val & PART) | 0x80) as u8);
+            val >>= 7;
+        }
+    }
+}
+
+impl ByteReadable for VarLong {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        const PART: u64 = 0x7F;
+        let mut size = 0;
+        let mut val = 0u64;
+        loop {
+            if size >= 10 {
+                panic!("VarLong is longer than 10 bytes");
+            }
+            let b: u8 = byte_reader.read();
+            let b = b as u64;
+            val |= (b & PART) << (size * 7);
+            size += 1;
             if (b & 0x80) == 0 {
                 break;
             }
         }
-        VarInt(val as i32)
+        VarLong(val as i64)
     }
 }
 
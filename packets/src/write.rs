@@ -86,6 +86,18 @@ impl ByteWritable for u16 {
     }
 }
 
+impl ByteWritable for i32 {
+    fn write_to_bytes(self, writer: &mut ByteWriter) {
+        writer.bytes.put_i32(self);
+    }
+}
+
+impl ByteWritable for u32 {
+    fn write_to_bytes(self, writer: &mut ByteWriter) {
+        writer.bytes.put_u32(self);
+    }
+}
+
 pub trait ByteWritable {
     fn write_to_bytes(self, writer: &mut ByteWriter);
 }
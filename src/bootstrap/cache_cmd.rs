@@ -0,0 +1,298 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The `cache` subcommand
+
+use std::{
+    fs::File,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    bootstrap::{
+        cache_key,
+        config::AuthConfig,
+        mojang::MojangApi,
+        opts::CacheAction,
+        storage::{CacheRecord, CacheStatus, Revalidation, UserCache},
+    },
+    error::Res,
+    HasContext, ResContext,
+};
+
+/// The file format of `cache import` and `cache export`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CacheFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for CacheFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(CacheFormat::Csv),
+            "json" => Ok(CacheFormat::Json),
+            other => Err(format!("unknown format {} (csv or json)", other)),
+        }
+    }
+}
+
+impl CacheFormat {
+    /// The format given or else the one matching the extension of `path`.
+    /// JSON if the extension is not `.csv`
+    fn resolve(format: Option<CacheFormat>, path: &str) -> CacheFormat {
+        format.unwrap_or_else(|| {
+            let csv = Path::new(path)
+                .extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case("csv"));
+
+            if csv {
+                CacheFormat::Csv
+            } else {
+                CacheFormat::Json
+            }
+        })
+    }
+}
+
+/// A short description of how long ago `secs` (since the unix epoch) was
+fn age(secs: u64, now: u64) -> String {
+    if secs == 0 {
+        return "never".to_string();
+    }
+
+    let age = now.saturating_sub(secs);
+    match age {
+        0..=59 => format!("{}s ago", age),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+fn list(cache: &UserCache) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+
+    let records = cache.records();
+    for record in &records {
+        let status = match record.status {
+            CacheStatus::Valid => "valid",
+            CacheStatus::Invalid => "invalid",
+            CacheStatus::Microsoft => "microsoft",
+        };
+
+        if record.status == CacheStatus::Invalid {
+            println!("{:<9} {}", status, record.email);
+        } else {
+            println!(
+                "{:<9} {} {} {} checked {}",
+                status,
+                record.email,
+                record.username,
+                record.uuid,
+                age(record.last_checked, now)
+            );
+        }
+    }
+
+    let invalid = records
+        .iter()
+        .filter(|record| record.status == CacheStatus::Invalid)
+        .count();
+
+    println!(
+        "{} users ({} valid, {} invalid)",
+        records.len(),
+        records.len() - invalid,
+        invalid
+    );
+}
+
+fn export(records: &[CacheRecord], path: &str, format: CacheFormat) -> Res {
+    let file = File::create(path)?;
+    match format {
+        CacheFormat::Json => serde_json::to_writer_pretty(file, records)?,
+        CacheFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(file);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn import(path: &str, format: CacheFormat) -> Res<Vec<CacheRecord>> {
+    let file = File::open(path)?;
+    let records = match format {
+        CacheFormat::Json => serde_json::from_reader(file)?,
+        CacheFormat::Csv => csv::Reader::from_reader(file)
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(records)
+}
+
+/// Runs a `cache` action on the cache at `file`
+pub async fn run(file: &str, action: CacheAction, auth: &AuthConfig) -> ResContext {
+    // importing can create a new cache, which is encrypted like the swarm
+    // would encrypt it
+    let cache = match action {
+        CacheAction::Import { .. } => {
+            let encrypt =
+                auth.encrypt_cache || std::env::var_os(cache_key::PASSPHRASE_VAR).is_some();
+            UserCache::load(
+                file.into(),
+                auth.token_policy(),
+                auth.servers(),
+                encrypt,
+                cache_key::passphrase,
+            )
+        }
+        _ => UserCache::open(file.into()),
+    };
+    let mut cache = cache.context(|| format!("could not open {}", file))?;
+
+    match action {
+        CacheAction::List => list(&cache),
+        CacheAction::Revalidate { emails } => {
+            let emails = if emails.is_empty() {
+                cache.emails()
+            } else {
+                emails
+            };
+
+            let mojang = MojangApi::default().servers(auth.servers());
+            let endpoints = auth.microsoft_endpoints();
+
+            for email in &emails {
+                match cache.revalidate(email, &mojang, endpoints.as_ref()).await {
+                    Ok(Revalidation::NotCached) => println!("{} is not cached", email),
+                    Ok(Revalidation::Valid) => println!("{} is valid", email),
+                    Ok(Revalidation::Refreshed) => println!("refreshed {}", email),
+                    Ok(Revalidation::Removed) => {
+                        println!(
+                            "removed {}. It is authenticated again on the next run",
+                            email
+                        )
+                    }
+                    Err(e) => println!("could not revalidate {} .. {}", email, e),
+                }
+            }
+
+            cache
+                .save()
+                .context(|| format!("could not save {}", file))?;
+        }
+        CacheAction::Remove { emails } => {
+            for email in &emails {
+                if cache.remove(email) {
+                    println!("removed {}", email);
+                } else {
+                    println!("{} is not cached", email);
+                }
+            }
+            cache
+                .save()
+                .context(|| format!("could not save {}", file))?;
+        }
+        CacheAction::Export { path, format } => {
+            let format = CacheFormat::resolve(format, &path);
+            let records = cache.records();
+            export(&records, &path, format).context(|| format!("could not export to {}", path))?;
+            println!(
+                "exported {} users to {}. It contains passwords and tokens in plaintext",
+                records.len(),
+                path
+            );
+        }
+        CacheAction::Import { path, format } => {
+            let format = CacheFormat::resolve(format, &path);
+            let records = import(&path, format).context(|| format!("could not import {}", path))?;
+            let count = records.len();
+
+            cache.import(records);
+            cache
+                .save()
+                .context(|| format!("could not save {}", file))?;
+            println!("imported {} users", count);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{
+        cache_cmd::{age, export, import, CacheFormat},
+        storage::{CacheRecord, CacheStatus},
+    };
+
+    #[test]
+    fn test_export_import() {
+        let records = vec![
+            CacheRecord {
+                email: "a@example.com".to_string(),
+                status: CacheStatus::Valid,
+                username: "Alice".to_string(),
+                uuid: "0123".to_string(),
+                password: "pass".to_string(),
+                access_id: "token".to_string(),
+                client_id: "client".to_string(),
+                last_checked: 100,
+                refresh_token: String::new(),
+                expires_at: 0,
+            },
+            CacheRecord {
+                email: "b@example.com".to_string(),
+                status: CacheStatus::Invalid,
+                username: String::new(),
+                uuid: String::new(),
+                password: "wrong".to_string(),
+                access_id: String::new(),
+                client_id: String::new(),
+                last_checked: 0,
+                refresh_token: String::new(),
+                expires_at: 0,
+            },
+        ];
+
+        for format in [CacheFormat::Csv, CacheFormat::Json] {
+            let path = std::env::temp_dir().join(format!(
+                "swarm-bot-export-{}-{:?}",
+                std::process::id(),
+                format
+            ));
+            let path = path.to_str().unwrap();
+
+            export(&records, path, format).unwrap();
+            assert_eq!(import(path, format).unwrap(), records);
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(CacheFormat::resolve(None, "users.CSV"), CacheFormat::Csv);
+        assert_eq!(CacheFormat::resolve(None, "users.json"), CacheFormat::Json);
+        assert_eq!(age(0, 100), "never");
+        assert_eq!(age(100, 100 + 7200), "2h ago");
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Passphrase encryption of the account cache. An encrypted cache is
+//!
+//! ```text
+//! MAGIC | salt (16) | nonce (12) | AES-256-GCM(bincode)
+//! ```
+//!
+//! The key is derived from the passphrase with PBKDF2-HMAC-SHA256. A plaintext
+//! cache is bincode without a header.
+
+use aes_gcm::{
+    aead::{Aead, NewAead},
+    Aes256Gcm, Key, Nonce,
+};
+use hmac::Hmac;
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::error::{err, Res};
+
+/// The environment variable holding the cache passphrase. We prompt for it if
+/// it is not set.
+pub const PASSPHRASE_VAR: &str = "SWARM_CACHE_PASSPHRASE";
+
+/// The start of an encrypted cache (the `1` is the format version)
+const MAGIC: &[u8] = b"SWARMCACHE1";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// PBKDF2 iterations. Only done once when the cache is loaded
+const ROUNDS: u32 = 200_000;
+
+/// The key a cache is encrypted with along with the salt it was derived with
+pub struct CacheKey {
+    key: [u8; 32],
+    salt: [u8; SALT_LEN],
+}
+
+impl CacheKey {
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> CacheKey {
+        let mut key = [0; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), &salt, ROUNDS, &mut key);
+        CacheKey { key, salt }
+    }
+
+    /// A key with a new random salt
+    pub fn generate(passphrase: &str) -> CacheKey {
+        let mut salt = [0; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::from_slice(&self.key))
+    }
+
+    /// Encrypts `plaintext` with a new nonce
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("AES-GCM encryption can not fail for a cache sized input");
+
+        let mut res = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+        res.extend_from_slice(MAGIC);
+        res.extend_from_slice(&self.salt);
+        res.extend_from_slice(&nonce);
+        res.extend_from_slice(&ciphertext);
+        res
+    }
+
+    /// Decrypts a cache written by [`CacheKey::seal`]. Returns the key so the
+    /// cache can be saved with it again.
+    pub fn open(passphrase: &str, bytes: &[u8]) -> Res<(CacheKey, Vec<u8>)> {
+        let body = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| err("the cache is not encrypted"))?;
+
+        if body.len() < SALT_LEN + NONCE_LEN {
+            return Err(err("the encrypted cache is truncated"));
+        }
+
+        let (salt, body) = body.split_at(SALT_LEN);
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+        let key = Self::derive(passphrase, salt.try_into().unwrap());
+        let plaintext = key
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| err("wrong passphrase or corrupted cache"))?;
+
+        Ok((key, plaintext))
+    }
+}
+
+/// Whether a cache file is encrypted
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// The cache passphrase from [`PASSPHRASE_VAR`] or else a prompt
+pub fn passphrase() -> Res<String> {
+    let passphrase = match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => rpassword::prompt_password_stdout("cache passphrase: ")?,
+    };
+
+    if passphrase.is_empty() {
+        return Err(err("the cache passphrase can not be empty"));
+    }
+
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::cache_key::{is_encrypted, CacheKey};
+
+    #[test]
+    fn test_seal_open() {
+        let key = CacheKey::generate("hunter2");
+        let sealed = key.seal(b"users");
+
+        assert!(is_encrypted(&sealed));
+        assert!(!is_encrypted(b"users"));
+
+        let (_, plaintext) = CacheKey::open("hunter2", &sealed).unwrap();
+        assert_eq!(plaintext, b"users");
+
+        assert!(CacheKey::open("hunter3", &sealed).is_err());
+        assert!(CacheKey::open("hunter2", &sealed[..20]).is_err());
+    }
+}
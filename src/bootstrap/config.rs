@@ -0,0 +1,573 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The TOML config file. Every value has a default, a value in the file
+//! overrides the default and a CLI flag overrides the file.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::{value::Table, Value};
+
+use crate::{
+    bootstrap::{
+        mojang::{MicrosoftEndpoints, YggdrasilServers},
+        opts::Opts,
+        storage::TokenPolicy,
+    },
+    client::pathfind::context::PathConfig,
+    error::Res,
+    HasContext, ResContext,
+};
+
+/// The config file used if `--config` is not given. It is optional.
+pub const DEFAULT_PATH: &str = "swarm.toml";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub proxies: ProxyConfig,
+    pub runner: RunnerConfig,
+
+    /// the [`BotConfig`] of every bot. Kept as TOML so groups can override
+    /// single values
+    bot: Table,
+    group: Vec<Group>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: u16,
+
+    /// the protocol version. Detected with a server list ping if not given
+    pub version: Option<u32>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 25565,
+            version: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub users_file: String,
+    pub offline: bool,
+    pub offline_prefix: String,
+    pub microsoft: bool,
+    pub ms_client_id: Option<String>,
+    pub ms_oauth_url: String,
+    pub xbox_url: String,
+    pub xsts_url: String,
+    pub mc_services_url: String,
+    pub auth_server: String,
+    pub session_server: String,
+    pub yggdrasil_api: Option<String>,
+
+    /// encrypt cache.db with a passphrase
+    pub encrypt_cache: bool,
+
+    /// cached tokens checked more recently than this are trusted
+    pub token_validate_after_secs: u64,
+
+    /// cached tokens checked longer ago than this are refreshed
+    pub token_refresh_after_secs: u64,
+
+    /// Microsoft tokens which expire within this many seconds are refreshed
+    pub token_expiry_margin_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            users_file: "users.csv".to_string(),
+            offline: false,
+            offline_prefix: "Bot".to_string(),
+            microsoft: false,
+            ms_client_id: None,
+            ms_oauth_url: MicrosoftEndpoints::OAUTH.to_string(),
+            xbox_url: MicrosoftEndpoints::XBOX.to_string(),
+            xsts_url: MicrosoftEndpoints::XSTS.to_string(),
+            mc_services_url: MicrosoftEndpoints::MINECRAFT.to_string(),
+            auth_server: YggdrasilServers::AUTH.to_string(),
+            session_server: YggdrasilServers::SESSION.to_string(),
+            yggdrasil_api: None,
+            encrypt_cache: false,
+            token_validate_after_secs: TokenPolicy::default().validate_after,
+            token_refresh_after_secs: TokenPolicy::default().refresh_after,
+            token_expiry_margin_secs: TokenPolicy::default().expiry_margin,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// The Yggdrasil servers. An authlib-injector API root overrides the auth
+    /// and session server
+    pub fn servers(&self) -> YggdrasilServers {
+        match &self.yggdrasil_api {
+            Some(api_root) => YggdrasilServers::authlib_injector(api_root),
+            None => YggdrasilServers {
+                auth: self.auth_server.clone(),
+                session: self.session_server.clone(),
+            },
+        }
+    }
+
+    /// When cached tokens are checked with the auth server
+    pub fn token_policy(&self) -> TokenPolicy {
+        TokenPolicy {
+            validate_after: self.token_validate_after_secs,
+            refresh_after: self.token_refresh_after_secs,
+            expiry_margin: self.token_expiry_margin_secs,
+        }
+    }
+
+    /// The Microsoft endpoints. `None` without a client id
+    pub fn microsoft_endpoints(&self) -> Option<MicrosoftEndpoints> {
+        Some(MicrosoftEndpoints {
+            client_id: self.ms_client_id.clone()?,
+            oauth: self.ms_oauth_url.clone(),
+            xbox: self.xbox_url.clone(),
+            xsts: self.xsts_url.clone(),
+            minecraft: self.mc_services_url.clone(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    pub enabled: bool,
+    pub file: String,
+    pub max_bots: Option<usize>,
+    pub max_failures: u32,
+    pub quarantine_secs: u64,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: "proxies.csv".to_string(),
+            max_bots: None,
+            max_failures: 3,
+            quarantine_secs: 300,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunnerConfig {
+    pub count: usize,
+    pub delay_ms: u64,
+    pub ws_port: u16,
+    pub reconnect_retries: u32,
+    pub reconnect_delay_ms: u64,
+    pub reconnect_max_delay_ms: u64,
+    pub login_min_delay_ms: u64,
+    pub login_max_delay_ms: u64,
+    pub max_logins_in_flight: usize,
+
+    /// the radius of the region each bot mines
+    pub mine_region_radius: i32,
+
+    /// the directory the packets of each bot are captured to
+    pub capture: Option<String>,
+
+    /// the memory in MiB the chunks of each dimension can use. Unlimited if not
+    /// set
+    pub chunk_memory_mb: Option<usize>,
+
+    /// keep only the block types of chunks over the memory cap before
+    /// dropping them
+    pub chunk_downgrade: bool,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            delay_ms: 500,
+            ws_port: 8080,
+            reconnect_retries: 5,
+            reconnect_delay_ms: 5000,
+            reconnect_max_delay_ms: 300_000,
+            login_min_delay_ms: 50,
+            login_max_delay_ms: 30_000,
+            max_logins_in_flight: 8,
+            mine_region_radius: 3,
+            capture: None,
+            chunk_memory_mb: None,
+            chunk_downgrade: true,
+        }
+    }
+}
+
+/// Values which can differ between bots
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    /// the bot eats below this food level
+    pub food_threshold: u8,
+
+    /// the distance from which the bot attacks entities
+    pub attack_distance: f64,
+    pub path: PathConfig,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            food_threshold: 10,
+            attack_distance: 3.0,
+            path: PathConfig::default(),
+        }
+    }
+}
+
+/// Overrides [`BotConfig`] values for some bots
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Group {
+    name: String,
+
+    /// emails or usernames in the group
+    #[serde(default)]
+    users: Vec<String>,
+
+    /// usernames starting with this are in the group
+    prefix: Option<String>,
+
+    #[serde(default)]
+    bot: Table,
+}
+
+impl Group {
+    fn contains(&self, email: &str, username: &str) -> bool {
+        let listed = self
+            .users
+            .iter()
+            .any(|user| user == email || user == username);
+
+        let prefixed = self
+            .prefix
+            .as_ref()
+            .map_or(false, |prefix| username.starts_with(prefix));
+
+        listed || prefixed
+    }
+}
+
+/// Resolves the [`BotConfig`] of a bot from the `[bot]` table and the groups
+/// it is in. Later groups override earlier ones.
+#[derive(Debug, Clone, Default)]
+pub struct BotConfigs {
+    base: Table,
+    groups: Vec<Group>,
+}
+
+/// Overrides the values in `base` with the values in `over`. Nested tables
+/// are merged instead of replaced.
+fn merge(base: &mut Table, over: &Table) {
+    for (key, value) in over {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => merge(base, over),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+impl BotConfigs {
+    pub fn resolve(&self, email: &str, username: &str) -> Res<BotConfig> {
+        let mut table = self.base.clone();
+        for group in &self.groups {
+            if group.contains(email, username) {
+                merge(&mut table, &group.bot);
+            }
+        }
+        Ok(Value::Table(table).try_into()?)
+    }
+
+    /// Checks the `[bot]` table and every group can be used
+    fn validate(&self) -> ResContext {
+        Value::Table(self.base.clone())
+            .try_into::<BotConfig>()
+            .context_str("invalid [bot] config")?;
+
+        for group in &self.groups {
+            let mut table = self.base.clone();
+            merge(&mut table, &group.bot);
+            Value::Table(table)
+                .try_into::<BotConfig>()
+                .context(|| format!("invalid config for group {}", group.name))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces `target` if the flag was given
+fn set<T>(target: &mut T, flag: Option<T>) {
+    if let Some(value) = flag {
+        *target = value;
+    }
+}
+
+/// The value of a `--flag`/`--no-flag` pair. `None` if neither was given
+fn switch(on: bool, off: bool) -> Option<bool> {
+    (on || off).then_some(on)
+}
+
+impl Config {
+    /// Loads the config at `path`. Without a path [`DEFAULT_PATH`] is used if
+    /// it exists, otherwise every value is the default.
+    pub fn load(path: Option<&str>) -> ResContext<Config> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).exists() => DEFAULT_PATH,
+            None => return Ok(Config::default()),
+        };
+
+        let contents =
+            std::fs::read_to_string(path).context(|| format!("could not read config {}", path))?;
+
+        let config: Config =
+            toml::from_str(&contents).context(|| format!("invalid config {}", path))?;
+
+        config.bot_configs().validate()?;
+
+        Ok(config)
+    }
+
+    pub fn bot_configs(&self) -> BotConfigs {
+        BotConfigs {
+            base: self.bot.clone(),
+            groups: self.group.clone(),
+        }
+    }
+
+    /// Overrides the config with the flags given on the command line
+    pub fn apply(&mut self, opts: Opts) {
+        let Opts {
+            command: _,
+            config: _,
+            host,
+            load: _,
+            count,
+            proxy,
+            no_proxy,
+            port,
+            ws_port,
+            delay_ms,
+            users_file,
+            proxies_file,
+            version,
+            offline,
+            no_offline,
+            offline_prefix,
+            microsoft,
+            no_microsoft,
+            ms_client_id,
+            ms_oauth_url,
+            xbox_url,
+            xsts_url,
+            mc_services_url,
+            auth_server,
+            session_server,
+            yggdrasil_api,
+            encrypt_cache,
+            no_encrypt_cache,
+            token_validate_after_secs,
+            token_refresh_after_secs,
+            token_expiry_margin_secs,
+            proxy_max_bots,
+            proxy_max_failures,
+            proxy_quarantine_secs,
+            reconnect_retries,
+            reconnect_delay_ms,
+            reconnect_max_delay_ms,
+            login_min_delay_ms,
+            login_max_delay_ms,
+            max_logins_in_flight,
+            capture,
+            chunk_memory_mb,
+        } = opts;
+
+        let Config {
+            server,
+            auth,
+            proxies,
+            runner,
+            ..
+        } = self;
+
+        if host.is_some() {
+            server.host = host;
+        }
+        set(&mut server.port, port);
+        if version.is_some() {
+            server.version = version;
+        }
+
+        set(&mut auth.users_file, users_file);
+        set(&mut auth.offline, switch(offline, no_offline));
+        set(&mut auth.offline_prefix, offline_prefix);
+        set(&mut auth.microsoft, switch(microsoft, no_microsoft));
+        if ms_client_id.is_some() {
+            auth.ms_client_id = ms_client_id;
+        }
+        set(&mut auth.ms_oauth_url, ms_oauth_url);
+        set(&mut auth.xbox_url, xbox_url);
+        set(&mut auth.xsts_url, xsts_url);
+        set(&mut auth.mc_services_url, mc_services_url);
+        set(&mut auth.auth_server, auth_server);
+        set(&mut auth.session_server, session_server);
+        if yggdrasil_api.is_some() {
+            auth.yggdrasil_api = yggdrasil_api;
+        }
+        set(
+            &mut auth.encrypt_cache,
+            switch(encrypt_cache, no_encrypt_cache),
+        );
+        set(
+            &mut auth.token_validate_after_secs,
+            token_validate_after_secs,
+        );
+        set(&mut auth.token_refresh_after_secs, token_refresh_after_secs);
+        set(&mut auth.token_expiry_margin_secs, token_expiry_margin_secs);
+
+        set(&mut proxies.enabled, switch(proxy, no_proxy));
+        set(&mut proxies.file, proxies_file);
+        if proxy_max_bots.is_some() {
+            proxies.max_bots = proxy_max_bots;
+        }
+        set(&mut proxies.max_failures, proxy_max_failures);
+        set(&mut proxies.quarantine_secs, proxy_quarantine_secs);
+
+        set(&mut runner.count, count);
+        set(&mut runner.delay_ms, delay_ms);
+        set(&mut runner.ws_port, ws_port);
+        set(&mut runner.reconnect_retries, reconnect_retries);
+        set(&mut runner.reconnect_delay_ms, reconnect_delay_ms);
+        set(&mut runner.reconnect_max_delay_ms, reconnect_max_delay_ms);
+        set(&mut runner.login_min_delay_ms, login_min_delay_ms);
+        set(&mut runner.login_max_delay_ms, login_max_delay_ms);
+        set(&mut runner.max_logins_in_flight, max_logins_in_flight);
+        if capture.is_some() {
+            runner.capture = capture;
+        }
+        if chunk_memory_mb.is_some() {
+            runner.chunk_memory_mb = chunk_memory_mb;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::bootstrap::{config::Config, opts::Opts};
+
+    #[test]
+    fn test_switches() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [auth]
+            offline = true
+            encrypt_cache = true
+            token_expiry_margin_secs = 60
+
+            [proxies]
+            enabled = true
+            "#,
+        )
+        .unwrap();
+
+        let opts = Opts::parse_from(["swarm-bot", "--no-offline", "--microsoft", "-p"]);
+        config.apply(opts);
+
+        assert!(!config.auth.offline);
+        assert!(config.auth.microsoft);
+        assert!(config.proxies.enabled);
+
+        // not given, so the config file is kept
+        assert!(config.auth.encrypt_cache);
+        assert_eq!(config.auth.token_policy().expiry_margin, 60);
+
+        let opts = Opts::parse_from(["swarm-bot", "--token-expiry-margin-secs", "600"]);
+        config.apply(opts);
+        assert_eq!(config.auth.token_policy().expiry_margin, 600);
+
+        // the last of a pair wins
+        let opts = Opts::parse_from(["swarm-bot", "--proxy", "--no-proxy"]);
+        config.apply(opts);
+        assert!(!config.proxies.enabled);
+    }
+
+    #[test]
+    fn test_groups() {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            host = "localhost"
+
+            [bot]
+            food_threshold = 12
+
+            [bot.path.costs]
+            mine_unrelated = 10.0
+
+            [[group]]
+            name = "fighters"
+            prefix = "Fighter"
+            bot = { attack_distance = 2.5, path = { costs = { fall = 5.0 } } }
+
+            [[group]]
+            name = "hungry"
+            users = ["hungry@example.com"]
+            bot = { food_threshold = 18 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.host.as_deref(), Some("localhost"));
+        assert_eq!(config.server.port, 25565);
+
+        let bots = config.bot_configs();
+        bots.validate().unwrap();
+
+        let fighter = bots.resolve("a@example.com", "Fighter1").unwrap();
+        assert_eq!(fighter.food_threshold, 12);
+        assert_eq!(fighter.attack_distance, 2.5);
+        assert_eq!(fighter.path.costs.fall, 5.0);
+        assert_eq!(fighter.path.costs.mine_unrelated, 10.0);
+
+        let hungry = bots.resolve("hungry@example.com", "Someone").unwrap();
+        assert_eq!(hungry.food_threshold, 18);
+        assert_eq!(hungry.attack_distance, 3.0);
+        assert_eq!(hungry.path.costs.fall, 1.0);
+    }
+}
@@ -14,11 +14,12 @@
 
 use std::fs::File;
 
+use csv::StringRecord;
 use serde::de::DeserializeOwned;
 
 use crate::{
-    bootstrap::{CSVUser, Proxy},
-    error::Res,
+    bootstrap::{CSVUser, Proxy, ProxyScheme},
+    error::{Error, Res},
 };
 
 fn read_csv<T: DeserializeOwned>(file: File) -> Res<Vec<T>> {
@@ -38,6 +39,69 @@ pub fn read_users(file: File) -> Res<Vec<CSVUser>> {
     read_csv(file)
 }
 
+/// Reads `[scheme:]host:port[:user[:pass]]` proxies. The scheme is `socks5`,
+/// `socks4` or `http` and defaults to `socks5`.
 pub fn read_proxies(file: File) -> Res<Vec<Proxy>> {
-    read_csv(file)
+    csv::ReaderBuilder::new()
+        .delimiter(b':')
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file)
+        .records()
+        .map(|record| parse_proxy(&record?))
+        .collect()
+}
+
+fn parse_proxy(record: &StringRecord) -> Res<Proxy> {
+    let fields: Vec<_> = record.iter().collect();
+
+    let (scheme, fields) = match fields.split_first() {
+        Some((first, rest)) => match first.parse() {
+            Ok(scheme) => (scheme, rest),
+            Err(_) => (ProxyScheme::Socks5, &fields[..]),
+        },
+        None => (ProxyScheme::Socks5, &fields[..]),
+    };
+
+    let invalid = || Error::Simple(format!("invalid proxy {}", fields.join(":")));
+
+    let (host, port, user, pass) = match *fields {
+        [host, port] => (host, port, "", ""),
+        [host, port, user] => (host, port, user, ""),
+        [host, port, user, pass] => (host, port, user, pass),
+        _ => return Err(invalid()),
+    };
+
+    Ok(Proxy {
+        scheme,
+        host: host.to_string(),
+        port: port.parse().map_err(|_| invalid())?,
+        user: user.to_string(),
+        pass: pass.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::StringRecord;
+
+    use crate::bootstrap::{csv::parse_proxy, ProxyScheme};
+
+    #[test]
+    fn test_parse_proxy() {
+        let legacy = parse_proxy(&StringRecord::from(vec!["1.2.3.4", "1080", "u", "p"])).unwrap();
+        assert_eq!(legacy.scheme, ProxyScheme::Socks5);
+        assert_eq!(legacy.user, "u");
+
+        let http = parse_proxy(&StringRecord::from(vec!["http", "1.2.3.4", "8080"])).unwrap();
+        assert_eq!(http.scheme, ProxyScheme::Http);
+        assert_eq!(http.port, 8080);
+        assert!(!http.has_auth());
+
+        let socks4 = parse_proxy(&StringRecord::from(vec!["SOCKS4", "1.2.3.4", "1080", "id"]));
+        assert_eq!(socks4.unwrap().scheme, ProxyScheme::Socks4);
+
+        assert!(parse_proxy(&StringRecord::from(vec!["ftp", "1.2.3.4", "21"])).is_err());
+        assert!(parse_proxy(&StringRecord::from(vec!["1.2.3.4", "port"])).is_err());
+    }
 }
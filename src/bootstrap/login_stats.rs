@@ -0,0 +1,104 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    rc::Rc,
+};
+
+use crate::error::Error;
+
+/// The login stats shared between the connection stream and the runner
+pub type LoginReport = Rc<RefCell<LoginStats>>;
+
+/// Aggregate results of connecting and logging in bots
+#[derive(Default, Debug)]
+pub struct LoginStats {
+    pub succeeded: usize,
+
+    /// failed connections or logins by [`Error::category`]
+    pub failed: BTreeMap<String, usize>,
+
+    /// bots queued to log in again
+    pub retried: usize,
+
+    /// whether anything changed since the last [`LoginStats::take_changed`]
+    changed: bool,
+}
+
+impl LoginStats {
+    pub fn shared() -> LoginReport {
+        Rc::new(RefCell::new(LoginStats::default()))
+    }
+
+    pub fn success(&mut self) {
+        self.succeeded += 1;
+        self.changed = true;
+    }
+
+    pub fn failure(&mut self, error: &Error) {
+        *self.failed.entry(error.category()).or_default() += 1;
+        self.changed = true;
+    }
+
+    pub fn retry(&mut self) {
+        self.retried += 1;
+        self.changed = true;
+    }
+
+    /// Whether the stats changed since this was last called
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+}
+
+impl Display for LoginStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let failed: usize = self.failed.values().sum();
+        f.write_fmt(format_args!(
+            "logins: {} succeeded, {} failed, {} retried",
+            self.succeeded, failed, self.retried
+        ))?;
+
+        for (reason, count) in &self.failed {
+            f.write_fmt(format_args!("\n  {} x {}", count, reason))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bootstrap::login_stats::LoginStats, error::Error};
+
+    #[test]
+    fn test_login_stats() {
+        let mut stats = LoginStats::default();
+        stats.success();
+        stats.failure(&Error::Disconnected("banned".to_string()));
+        stats.failure(&Error::Disconnected("banned".to_string()));
+        stats.retry();
+
+        assert!(stats.take_changed());
+        assert!(!stats.take_changed());
+
+        assert_eq!(
+            stats.to_string(),
+            "logins: 1 succeeded, 2 failed, 1 retried\n  2 x kicked banned"
+        );
+    }
+}
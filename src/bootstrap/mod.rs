@@ -14,24 +14,35 @@
 
 use serde::Deserialize;
 use tokio::{
-    net::{
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        TcpStream,
-    },
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
     sync::mpsc::Receiver,
 };
-use tokio_socks::tcp::Socks5Stream;
 
-use crate::bootstrap::{
-    mojang::MojangApi,
-    storage::{BotData, ValidUser},
+use std::{str::FromStr, time::Instant};
+
+use crate::{
+    bootstrap::{
+        login_stats::LoginReport,
+        mojang::MojangApi,
+        proxy_pool::{Proxies, ProxyFailure},
+        storage::{BotData, ValidUser},
+    },
+    client::throttle::{LoginThrottle, Throttle},
+    error::{err, Error, Res},
 };
 
+pub mod cache_cmd;
+pub mod cache_key;
+pub mod config;
 pub mod csv;
 pub mod dns;
+pub mod login_stats;
 pub mod mojang;
+pub mod offline;
 pub mod opts;
+pub mod proxy_pool;
 pub mod storage;
+pub mod tunnel;
 
 #[derive(Clone, Debug)]
 pub struct Address {
@@ -50,57 +61,122 @@ pub struct Connection {
     pub user: ValidUser,
     pub address: Address,
     pub mojang: MojangApi,
+    pub proxy: Option<Proxy>,
     pub read: OwnedReadHalf,
     pub write: OwnedWriteHalf,
 }
 
+/// How many proxies a bot tries before giving up
+const CONNECT_ATTEMPTS: usize = 3;
+
 impl Connection {
-    /// Generates connections given BotData and an address
+    /// Connects the bot, moving it to another proxy in the pool if its proxy
+    /// fails
+    pub async fn establish(bot: BotData, address: Address, proxies: &Proxies) -> Res<Connection> {
+        let BotData {
+            mut proxy,
+            user,
+            mut mojang,
+        } = bot;
+
+        // proxies this bot could not connect through
+        let mut failed_proxies = Vec::new();
+
+        for _ in 0..CONNECT_ATTEMPTS {
+            let assigned = proxies
+                .borrow_mut()
+                .acquire(proxy.as_ref(), &failed_proxies)?;
+
+            // the auth client should use the same IP we connect from
+            if assigned.as_ref().map(Proxy::address) != proxy.as_ref().map(Proxy::address) {
+                match mojang.with_proxy(assigned.as_ref()) {
+                    Ok(moved) => mojang = moved,
+                    Err(e) => println!("keeping the auth client of {} .. {}", user.username, e),
+                }
+                proxy = assigned;
+            }
+
+            let start = Instant::now();
+            match tunnel::connect(&address, proxy.as_ref()).await {
+                Ok(conn) => {
+                    if let Some(proxy) = &proxy {
+                        proxies.borrow_mut().succeeded(proxy, start.elapsed());
+                    }
+
+                    let (read, write) = conn.into_split();
+                    return Ok(Connection {
+                        user,
+                        address,
+                        mojang,
+                        proxy,
+                        read,
+                        write,
+                    });
+                }
+                Err(e) => match &proxy {
+                    Some(failed) => {
+                        println!(
+                            "could not connect {} through {} .. {}",
+                            user.username,
+                            failed.address(),
+                            e
+                        );
+                        proxies.borrow_mut().failed(failed, ProxyFailure::from(&e));
+                        failed_proxies.push(failed.clone());
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+
+        Err(err("ran out of connection attempts"))
+    }
+
+    /// Generates connections given BotData and an address. Each connection
+    /// holds a login slot of `throttle`. A summary of the proxy pool is printed
+    /// once every bot has been connected.
     pub fn stream(
         server_address: Address,
         mut users: tokio::sync::mpsc::Receiver<BotData>,
+        proxies: Proxies,
+        stats: LoginReport,
+        throttle: Throttle,
     ) -> Receiver<Connection> {
         let (tx, rx) = tokio::sync::mpsc::channel(1);
         tokio::task::spawn_local(async move {
+            let mut handles = Vec::new();
+
             while let Some(user) = users.recv().await {
+                // limits and paces the logins in progress
+                LoginThrottle::acquire(&throttle).await;
+
                 let tx = tx.clone();
+                let throttle = throttle.clone();
                 let address = server_address.clone();
-                tokio::task::spawn_local(async move {
-                    let BotData {
-                        proxy,
-                        user,
-                        mojang,
-                    } = user;
-
-                    let target = String::from(&address);
-
-                    let conn = match proxy {
-                        Some(proxy) => {
-                            let conn = Socks5Stream::connect_with_password(
-                                proxy.address().as_str(),
-                                target.as_str(),
-                                &proxy.user,
-                                &proxy.pass,
-                            )
-                            .await
-                            .unwrap();
-                            conn.into_inner()
+                let proxies = proxies.clone();
+                let stats = stats.clone();
+                let handle = tokio::task::spawn_local(async move {
+                    let username = user.user.username.clone();
+                    match Connection::establish(user, address, &proxies).await {
+                        Ok(conn) => {
+                            // the runner stopped
+                            let _ = tx.send(conn).await;
                         }
-                        None => TcpStream::connect(target.as_str()).await.unwrap(),
-                    };
-
-                    let (read, write) = conn.into_split();
-                    tx.send(Connection {
-                        user,
-                        address,
-                        mojang,
-                        read,
-                        write,
-                    })
-                    .await
-                    .unwrap();
+                        Err(e) => {
+                            println!("could not connect {} .. {}", username, e);
+                            throttle.borrow_mut().finish();
+                            stats.borrow_mut().failure(&e);
+                        }
+                    }
                 });
+                handles.push(handle);
             }
+
+            for handle in handles {
+                let _ = handle.await;
+            }
+
+            proxies.borrow().print_summary();
         });
 
         rx
@@ -113,8 +189,32 @@ pub struct CSVUser {
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// How a proxy is spoken to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProxyScheme {
+    Socks5,
+    Socks4,
+    Http,
+}
+
+impl FromStr for ProxyScheme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "socks5" => Ok(ProxyScheme::Socks5),
+            "socks4" | "socks4a" => Ok(ProxyScheme::Socks4),
+            "http" => Ok(ProxyScheme::Http),
+            _ => Err(Error::Simple(format!("unknown proxy scheme {}", s))),
+        }
+    }
+}
+
+/// A proxy from `proxies.csv`. `user` and `pass` are empty if the proxy does
+/// not need authentication.
+#[derive(Debug, Clone)]
 pub struct Proxy {
+    pub scheme: ProxyScheme,
     pub host: String,
     pub port: u32,
     pub user: String,
@@ -125,4 +225,8 @@ impl Proxy {
     pub fn address(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    pub fn has_auth(&self) -> bool {
+        !self.user.is_empty()
+    }
 }
@@ -16,23 +16,79 @@ use num_bigint::BigInt;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sha1::Sha1;
-use std::{convert::TryFrom, default::default};
+use std::{convert::TryFrom, default::default, time::Duration};
 
 use swarm_bot_packets::types::UUID;
 
 use crate::{
-    bootstrap::Proxy,
-    error::{MojangErr, Res},
+    bootstrap::{Proxy, ProxyScheme},
+    error::{err, MojangErr, Res},
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MojangApi {
     client: reqwest::Client,
+    servers: YggdrasilServers,
 }
 
 impl Default for MojangApi {
     fn default() -> Self {
-        MojangApi { client: default() }
+        MojangApi {
+            client: default(),
+            servers: default(),
+        }
+    }
+}
+
+/// The base URLs of a Yggdrasil-compatible auth server. These default to
+/// Mojang's but can point to a self-hosted server or a local mock.
+#[derive(Clone, Debug)]
+pub struct YggdrasilServers {
+    /// i.e., `https://authserver.mojang.com`. Used to authenticate, refresh and
+    /// validate
+    pub auth: String,
+
+    /// i.e., `https://sessionserver.mojang.com`. Used to join servers
+    pub session: String,
+}
+
+impl YggdrasilServers {
+    pub const AUTH: &'static str = "https://authserver.mojang.com";
+    pub const SESSION: &'static str = "https://sessionserver.mojang.com";
+
+    /// The servers of an [authlib-injector](https://github.com/yushijinhun/authlib-injector)
+    /// API root, i.e., `https://example.com/api/yggdrasil`
+    pub fn authlib_injector(api_root: &str) -> Self {
+        let api_root = api_root.trim_end_matches('/');
+        YggdrasilServers {
+            auth: format!("{}/authserver", api_root),
+            session: format!("{}/sessionserver", api_root),
+        }
+    }
+
+    fn authenticate(&self) -> String {
+        format!("{}/authenticate", self.auth)
+    }
+
+    fn refresh(&self) -> String {
+        format!("{}/refresh", self.auth)
+    }
+
+    fn validate(&self) -> String {
+        format!("{}/validate", self.auth)
+    }
+
+    fn join(&self) -> String {
+        format!("{}/session/minecraft/join", self.session)
+    }
+}
+
+impl Default for YggdrasilServers {
+    fn default() -> Self {
+        YggdrasilServers {
+            auth: Self::AUTH.to_string(),
+            session: Self::SESSION.to_string(),
+        }
     }
 }
 
@@ -41,15 +97,26 @@ impl TryFrom<&Proxy> for MojangApi {
 
     fn try_from(proxy: &Proxy) -> Result<Self, Self::Error> {
         let address = proxy.address();
-        let user = &proxy.user;
-        let pass = &proxy.pass;
-        let full_address = format!("socks5://{}", address);
 
-        let proxy = reqwest::Proxy::https(full_address)?.basic_auth(user, pass);
+        let full_address = match proxy.scheme {
+            ProxyScheme::Socks5 => format!("socks5://{}", address),
+            ProxyScheme::Http => format!("http://{}", address),
+            ProxyScheme::Socks4 => {
+                return Err(err("SOCKS4 proxies can not be used for auth requests"));
+            }
+        };
+
+        let mut reqwest_proxy = reqwest::Proxy::all(full_address)?;
+        if proxy.has_auth() {
+            reqwest_proxy = reqwest_proxy.basic_auth(&proxy.user, &proxy.pass);
+        }
 
-        let client = reqwest::Client::builder().proxy(proxy).build()?;
+        let client = reqwest::Client::builder().proxy(reqwest_proxy).build()?;
 
-        Ok(MojangApi { client })
+        Ok(MojangApi {
+            client,
+            servers: default(),
+        })
     }
 }
 
@@ -101,7 +168,121 @@ pub struct AuthResponse {
     pub uuid: UUID,
 }
 
+/// How users are authenticated
+#[derive(Clone, Debug)]
+pub enum AuthMethod {
+    /// legacy Yggdrasil email/password
+    Mojang,
+
+    /// Microsoft OAuth → Xbox Live → XSTS → Minecraft services
+    Microsoft(MicrosoftEndpoints),
+}
+
+/// The base URLs used for Microsoft authentication. These can be pointed at a
+/// local stand-in HTTP server for testing.
+#[derive(Clone, Debug)]
+pub struct MicrosoftEndpoints {
+    /// the Azure application (client) id used for OAuth
+    pub client_id: String,
+
+    /// i.e., `https://login.microsoftonline.com/consumers/oauth2/v2.0`
+    pub oauth: String,
+
+    /// i.e., `https://user.auth.xboxlive.com`
+    pub xbox: String,
+
+    /// i.e., `https://xsts.auth.xboxlive.com`
+    pub xsts: String,
+
+    /// i.e., `https://api.minecraftservices.com`
+    pub minecraft: String,
+}
+
+impl MicrosoftEndpoints {
+    pub const OAUTH: &'static str = "https://login.microsoftonline.com/consumers/oauth2/v2.0";
+    pub const XBOX: &'static str = "https://user.auth.xboxlive.com";
+    pub const XSTS: &'static str = "https://xsts.auth.xboxlive.com";
+    pub const MINECRAFT: &'static str = "https://api.minecraftservices.com";
+
+    const SCOPE: &'static str = "XboxLive.signin offline_access";
+}
+
+/// The result of the full Microsoft → Minecraft token chain
+pub struct MicrosoftAuthResponse {
+    /// the Minecraft access token used to join servers
+    pub access_token: String,
+
+    /// the Microsoft refresh token. Used to skip the device code login next
+    /// time
+    pub refresh_token: String,
+
+    /// seconds until [`MicrosoftAuthResponse::access_token`] expires
+    pub expires_in: u64,
+
+    pub username: String,
+    pub uuid: UUID,
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    interval: u64,
+    expires_in: u64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthToken {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthError {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct XboxClaim {
+    uhs: String,
+}
+
+#[derive(Deserialize)]
+struct XboxClaims {
+    xui: Vec<XboxClaim>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XboxToken {
+    token: String,
+    display_claims: XboxClaims,
+}
+
+#[derive(Deserialize)]
+struct MinecraftToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct MinecraftProfile {
+    id: String,
+    name: String,
+}
+
 impl MojangApi {
+    /// Uses `servers` instead of Mojang's for Yggdrasil requests
+    pub fn servers(mut self, servers: YggdrasilServers) -> Self {
+        self.servers = servers;
+        self
+    }
+
+    /// A client with the same servers which connects through `proxy`
+    pub fn with_proxy(&self, proxy: Option<&Proxy>) -> Res<MojangApi> {
+        Ok(MojangApi::try_from(proxy)?.servers(self.servers.clone()))
+    }
+
     pub async fn authenticate(&self, email: &str, password: &str) -> Res<AuthResponse> {
         let payload = json!({
             "agent": {
@@ -117,7 +298,7 @@ impl MojangApi {
 
         let res = self
             .client
-            .post("https://authserver.mojang.com/authenticate")
+            .post(self.servers.authenticate())
             .body(payload)
             .send()
             .await?;
@@ -151,7 +332,7 @@ impl MojangApi {
 
         let res = self
             .client
-            .post("https://authserver.mojang.com/refresh")
+            .post(self.servers.refresh())
             .body(payload)
             .send()
             .await?;
@@ -176,7 +357,7 @@ impl MojangApi {
 
         let res = self
             .client
-            .post("https://authserver.mojang.com/validate")
+            .post(self.servers.validate())
             .body(payload)
             .send()
             .await?;
@@ -185,6 +366,191 @@ impl MojangApi {
         Ok(status == 204)
     }
 
+    /// Logs in a Microsoft account with the device code flow. The user is
+    /// prompted (on stdout) to enter a code in their browser.
+    pub async fn microsoft_device_login(
+        &self,
+        email: &str,
+        endpoints: &MicrosoftEndpoints,
+    ) -> Res<MicrosoftAuthResponse> {
+        let res = self
+            .client
+            .post(format!("{}/devicecode", endpoints.oauth))
+            .form(&[
+                ("client_id", endpoints.client_id.as_str()),
+                ("scope", MicrosoftEndpoints::SCOPE),
+            ])
+            .send()
+            .await?;
+
+        let res = check_status(res, "device code", 200).await?;
+        let code: DeviceCode = res.json().await?;
+
+        println!("[{}] {}", email, code.message);
+
+        let mut interval = code.interval.max(1);
+        let mut waited = 0;
+
+        let token = loop {
+            if waited >= code.expires_in {
+                return Err(MojangErr::DeviceCodeExpired.into());
+            }
+
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            waited += interval;
+
+            let res = self
+                .client
+                .post(format!("{}/token", endpoints.oauth))
+                .form(&[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                    ("client_id", endpoints.client_id.as_str()),
+                    ("device_code", code.device_code.as_str()),
+                ])
+                .send()
+                .await?;
+
+            if res.status() == 200 {
+                break res.json::<OAuthToken>().await?;
+            }
+
+            let status = res.status();
+            let text = res.text().await?;
+
+            match serde_json::from_str::<OAuthError>(&text) {
+                Ok(err) if err.error == "authorization_pending" => {}
+                Ok(err) if err.error == "slow_down" => interval += 5,
+                _ => {
+                    return Err(MojangErr::Microsoft {
+                        step: "device code token",
+                        error_code: status,
+                        info: Some(text),
+                    }
+                    .into())
+                }
+            }
+        };
+
+        self.minecraft_from_oauth(token, endpoints).await
+    }
+
+    /// Obtains a new Minecraft access token from a cached Microsoft refresh
+    /// token without user interaction
+    pub async fn microsoft_refresh(
+        &self,
+        refresh_token: &str,
+        endpoints: &MicrosoftEndpoints,
+    ) -> Res<MicrosoftAuthResponse> {
+        let res = self
+            .client
+            .post(format!("{}/token", endpoints.oauth))
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", endpoints.client_id.as_str()),
+                ("scope", MicrosoftEndpoints::SCOPE),
+                ("refresh_token", refresh_token),
+            ])
+            .send()
+            .await?;
+
+        let res = check_status(res, "refresh token", 200).await?;
+        let token: OAuthToken = res.json().await?;
+        self.minecraft_from_oauth(token, endpoints).await
+    }
+
+    /// OAuth → Xbox Live → XSTS → Minecraft services → Minecraft profile
+    async fn minecraft_from_oauth(
+        &self,
+        token: OAuthToken,
+        endpoints: &MicrosoftEndpoints,
+    ) -> Res<MicrosoftAuthResponse> {
+        let OAuthToken {
+            access_token,
+            refresh_token,
+        } = token;
+
+        let xbox = json!({
+            "Properties": {
+                "AuthMethod": "RPS",
+                "SiteName": "user.auth.xboxlive.com",
+                "RpsTicket": format!("d={}", access_token)
+            },
+            "RelyingParty": "http://auth.xboxlive.com",
+            "TokenType": "JWT"
+        });
+
+        let res = self
+            .client
+            .post(format!("{}/user/authenticate", endpoints.xbox))
+            .json(&xbox)
+            .send()
+            .await?;
+
+        let res = check_status(res, "xbox live", 200).await?;
+        let xbox: XboxToken = res.json().await?;
+
+        let xsts = json!({
+            "Properties": {
+                "SandboxId": "RETAIL",
+                "UserTokens": [xbox.token]
+            },
+            "RelyingParty": "rp://api.minecraftservices.com/",
+            "TokenType": "JWT"
+        });
+
+        let res = self
+            .client
+            .post(format!("{}/xsts/authorize", endpoints.xsts))
+            .json(&xsts)
+            .send()
+            .await?;
+
+        let res = check_status(res, "xsts", 200).await?;
+        let xsts: XboxToken = res.json().await?;
+
+        let user_hash = xsts
+            .display_claims
+            .xui
+            .first()
+            .map(|claim| claim.uhs.as_str())
+            .unwrap_or_default();
+
+        let minecraft =
+            json!({ "identityToken": format!("XBL3.0 x={};{}", user_hash, xsts.token) });
+
+        let res = self
+            .client
+            .post(format!(
+                "{}/authentication/login_with_xbox",
+                endpoints.minecraft
+            ))
+            .json(&minecraft)
+            .send()
+            .await?;
+
+        let res = check_status(res, "minecraft login", 200).await?;
+        let minecraft: MinecraftToken = res.json().await?;
+
+        let res = self
+            .client
+            .get(format!("{}/minecraft/profile", endpoints.minecraft))
+            .bearer_auth(&minecraft.access_token)
+            .send()
+            .await?;
+
+        // a 404 means the account does not own Minecraft
+        let res = check_status(res, "minecraft profile", 200).await?;
+        let profile: MinecraftProfile = res.json().await?;
+
+        Ok(MicrosoftAuthResponse {
+            access_token: minecraft.access_token,
+            refresh_token,
+            expires_in: minecraft.expires_in,
+            username: profile.name,
+            uuid: UUID::from(&profile.id),
+        })
+    }
+
     pub async fn join(&self, uuid: UUID, server_hash: &str, access_token: &str) -> Res<()> {
         let uuid_str = uuid.to_string();
 
@@ -198,7 +564,7 @@ impl MojangApi {
 
         let res = self
             .client
-            .post("https://sessionserver.mojang.com/session/minecraft/join")
+            .post(self.servers.join())
             .body(payload)
             .send()
             .await?;
@@ -220,11 +586,31 @@ impl MojangApi {
     }
 }
 
+/// Returns an error describing the failed Microsoft authentication step if the
+/// status is not `expected`
+async fn check_status(
+    res: reqwest::Response,
+    step: &'static str,
+    expected: u16,
+) -> Res<reqwest::Response> {
+    let status = res.status();
+    if status == expected {
+        return Ok(res);
+    }
+
+    Err(MojangErr::Microsoft {
+        step,
+        error_code: status,
+        info: res.text().await.ok(),
+    }
+    .into())
+}
+
 #[cfg(test)]
 mod tests {
     use sha1::Sha1;
 
-    use crate::bootstrap::mojang::hexdigest;
+    use crate::bootstrap::mojang::{hexdigest, YggdrasilServers};
 
     fn sha1(input: &[u8]) -> String {
         let mut sha1 = Sha1::new();
@@ -238,4 +624,17 @@ mod tests {
         assert_eq!(sha1(b"simon"), "88e16a1019277b15d58faf0541e11910eb756f6");
         assert_eq!(sha1(b"Notch"), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
     }
+
+    #[test]
+    fn test_authlib_injector() {
+        let servers = YggdrasilServers::authlib_injector("https://example.com/api/yggdrasil/");
+        assert_eq!(
+            servers.authenticate(),
+            "https://example.com/api/yggdrasil/authserver/authenticate"
+        );
+        assert_eq!(
+            servers.join(),
+            "https://example.com/api/yggdrasil/sessionserver/session/minecraft/join"
+        );
+    }
 }
@@ -0,0 +1,112 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use swarm_bot_packets::types::UUID;
+use tokio::sync::mpsc::Receiver;
+
+use crate::bootstrap::{
+    mojang::MojangApi,
+    proxy_pool::Proxies,
+    storage::{BotData, ValidUser},
+};
+
+/// The maximum length of a Minecraft username
+const MAX_USERNAME_LEN: usize = 16;
+
+/// The UUID an offline-mode (online-mode=false) server assigns to a username.
+/// This is a version 3 (MD5) UUID of `OfflinePlayer:{username}`, the same as
+/// Java's `UUID.nameUUIDFromBytes`.
+pub fn offline_uuid(username: &str) -> UUID {
+    let digest = md5::compute(format!("OfflinePlayer:{}", username));
+    let mut bytes = digest.0;
+
+    // set the version to 3
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+
+    // set the variant to IETF
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    UUID(u128::from_be_bytes(bytes))
+}
+
+/// Generates the username of the bot at `idx`
+fn username(prefix: &str, idx: usize) -> String {
+    let suffix = idx.to_string();
+    let max_prefix = MAX_USERNAME_LEN.saturating_sub(suffix.len());
+    let prefix: String = prefix.chars().take(max_prefix).collect();
+    format!("{}{}", prefix, suffix)
+}
+
+/// A user which does not need to be authenticated with Mojang
+pub fn offline_user(username: String) -> ValidUser {
+    let uuid = offline_uuid(&username);
+    ValidUser {
+        email: username.clone(),
+        username,
+        password: String::new(),
+        last_checked: 0,
+        uuid: uuid.to_string(),
+        access_id: String::new(),
+        client_id: String::new(),
+    }
+}
+
+/// Generates `count` offline users named `{prefix}{idx}`. This does not touch
+/// `users.csv`, the user cache, or Mojang.
+pub fn obtain_users(prefix: &str, count: usize, proxies: Proxies) -> Receiver<BotData> {
+    let (tx, rx) = tokio::sync::mpsc::channel(32);
+
+    let users: Vec<_> = (0..count).map(|idx| username(prefix, idx)).collect();
+
+    tokio::task::spawn_local(async move {
+        for username in users {
+            let proxy = proxies.borrow_mut().pick();
+            // offline users never contact the auth servers
+            let mojang = MojangApi::default();
+
+            let user = offline_user(username);
+
+            let data = BotData {
+                user,
+                proxy,
+                mojang,
+            };
+
+            if tx.send(data).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::offline::{offline_uuid, username};
+
+    #[test]
+    fn test_offline_uuid() {
+        assert_eq!(
+            offline_uuid("Notch").to_string(),
+            "b50ad385829d3141a2167e7d7539ba7f"
+        );
+    }
+
+    #[test]
+    fn test_username() {
+        assert_eq!(username("Bot", 12), "Bot12");
+        assert_eq!(username("AVeryLongPrefixName", 123), "AVeryLongPref123");
+    }
+}
@@ -12,39 +12,239 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
+use crate::bootstrap::cache_cmd::CacheFormat;
+
+/// Flags override the config file, which overrides the defaults. Switches
+/// have a `--no-` form to turn off what the config file turns on
 #[derive(Parser, Debug)]
 #[clap(version = "1.0", author = "Andrew Gazelka")]
 pub struct Opts {
-    pub host: String,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// The TOML config file. swarm.toml is used if it exists
+    #[clap(long)]
+    pub config: Option<String>,
+
+    /// The server to join. Required unless set in the config file
+    pub host: Option<String>,
 
     #[clap(long)]
     pub load: bool,
 
-    #[clap(short, long, default_value = "1")]
-    pub count: usize,
+    #[clap(short, long)]
+    pub count: Option<usize>,
 
-    #[clap(short)]
+    /// Connect through the proxies in the proxies file
+    #[clap(short, long, overrides_with = "no_proxy")]
     pub proxy: bool,
 
-    #[clap(long, default_value = "25565")]
-    pub port: u16,
+    #[clap(long, overrides_with = "proxy")]
+    pub no_proxy: bool,
+
+    #[clap(long)]
+    pub port: Option<u16>,
+
+    #[clap(long)]
+    pub ws_port: Option<u16>,
+
+    /// The delay between logins we start with. It adapts to how fast the
+    /// server lets us log in
+    #[clap(short, long)]
+    pub delay_ms: Option<u64>,
+
+    #[clap(long)]
+    pub users_file: Option<String>,
+
+    #[clap(long)]
+    pub proxies_file: Option<String>,
+
+    /// The protocol version. Detected with a server list ping if not given
+    #[clap(short, long)]
+    pub version: Option<u32>,
+
+    /// Log in to an offline-mode (online-mode=false) server with generated
+    /// usernames. users.csv and Mojang are not used
+    #[clap(long, overrides_with = "no_offline")]
+    pub offline: bool,
+
+    #[clap(long, overrides_with = "offline")]
+    pub no_offline: bool,
+
+    /// The prefix of generated offline usernames. The bot index is appended
+    #[clap(long)]
+    pub offline_prefix: Option<String>,
+
+    /// Authenticate users with Microsoft accounts (device code login) instead
+    /// of legacy Mojang accounts. Requires --ms-client-id
+    #[clap(long, overrides_with = "no_microsoft")]
+    pub microsoft: bool,
+
+    #[clap(long, overrides_with = "microsoft")]
+    pub no_microsoft: bool,
+
+    /// The Azure application (client) id used for Microsoft OAuth
+    #[clap(long)]
+    pub ms_client_id: Option<String>,
+
+    #[clap(long)]
+    pub ms_oauth_url: Option<String>,
+
+    #[clap(long)]
+    pub xbox_url: Option<String>,
+
+    #[clap(long)]
+    pub xsts_url: Option<String>,
+
+    #[clap(long)]
+    pub mc_services_url: Option<String>,
+
+    /// The Yggdrasil auth server used to authenticate, refresh and validate
+    /// Mojang accounts
+    #[clap(long)]
+    pub auth_server: Option<String>,
+
+    /// The Yggdrasil session server used to join online-mode servers
+    #[clap(long)]
+    pub session_server: Option<String>,
+
+    /// An authlib-injector API root, i.e., `https://example.com/api/yggdrasil`.
+    /// Overrides --auth-server and --session-server
+    #[clap(long)]
+    pub yggdrasil_api: Option<String>,
+
+    /// Encrypt cache.db with a passphrase from SWARM_CACHE_PASSPHRASE (or a
+    /// prompt). An existing plaintext cache is encrypted in place
+    #[clap(long, overrides_with = "no_encrypt_cache")]
+    pub encrypt_cache: bool,
+
+    #[clap(long, overrides_with = "encrypt_cache")]
+    pub no_encrypt_cache: bool,
+
+    /// Cached tokens checked more recently than this are used without asking
+    /// the auth server
+    #[clap(long)]
+    pub token_validate_after_secs: Option<u64>,
+
+    /// Cached tokens checked longer ago than this are refreshed
+    #[clap(long)]
+    pub token_refresh_after_secs: Option<u64>,
+
+    /// Microsoft tokens which expire within this many seconds are refreshed
+    #[clap(long)]
+    pub token_expiry_margin_secs: Option<u64>,
+
+    /// The maximum number of bots connected through one proxy
+    #[clap(long)]
+    pub proxy_max_bots: Option<usize>,
+
+    /// Consecutive connection failures before a proxy is quarantined
+    #[clap(long)]
+    pub proxy_max_failures: Option<u32>,
+
+    /// How many seconds a quarantined proxy is not used
+    #[clap(long)]
+    pub proxy_quarantine_secs: Option<u64>,
+
+    /// How many times in a row a disconnected bot is logged in again. 0
+    /// disables reconnecting
+    #[clap(long)]
+    pub reconnect_retries: Option<u32>,
+
+    /// The delay before the first reconnect. It doubles every attempt
+    #[clap(long)]
+    pub reconnect_delay_ms: Option<u64>,
+
+    #[clap(long)]
+    pub reconnect_max_delay_ms: Option<u64>,
+
+    /// The shortest delay between logins
+    #[clap(long)]
+    pub login_min_delay_ms: Option<u64>,
+
+    /// The longest delay between logins when the server throttles us
+    #[clap(long)]
+    pub login_max_delay_ms: Option<u64>,
+
+    /// Logins which can be in progress at once. 0 is unlimited
+    #[clap(long)]
+    pub max_logins_in_flight: Option<usize>,
+
+    /// Capture the packets each bot receives to a file in this directory.
+    /// Captures can be replayed with the replay subcommand
+    #[clap(long)]
+    pub capture: Option<String>,
+
+    /// The memory in MiB the chunks of each dimension can use. The least
+    /// recently loaded chunks are downgraded and then dropped when it is
+    /// exceeded
+    #[clap(long)]
+    pub chunk_memory_mb: Option<usize>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Server list ping. Prints the MOTD, version, player count and latency
+    Ping {
+        host: String,
+
+        #[clap(long, default_value = "25565")]
+        port: u16,
+    },
+
+    /// Inspect and edit the account cache
+    Cache {
+        /// The cache file
+        #[clap(long, default_value = "cache.db")]
+        file: String,
+
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Run a bot offline on a capture made with --capture and print its state
+    /// at the end
+    Replay { file: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// List cached users with their username, UUID and when they were last
+    /// checked
+    List,
+
+    /// Check the tokens of these users (all if none are given) with the auth
+    /// server now and refresh them if needed. Users which cannot be refreshed
+    /// are removed so they are authenticated again
+    Revalidate { emails: Vec<String> },
 
-    #[clap(long, default_value = "8080")]
-    pub ws_port: u16,
+    /// Remove users from the cache
+    Remove {
+        #[clap(required = true)]
+        emails: Vec<String>,
+    },
 
-    #[clap(short, long, default_value = "500")]
-    pub delay_ms: u64,
+    /// Export the cache as CSV or JSON. Passwords and tokens are written in
+    /// plaintext
+    Export {
+        path: String,
 
-    #[clap(long, default_value = "users.csv")]
-    pub users_file: String,
+        /// csv or json. Detected from the extension if not given
+        #[clap(long)]
+        format: Option<CacheFormat>,
+    },
 
-    #[clap(long, default_value = "proxies.csv")]
-    pub proxies_file: String,
+    /// Import users from an export. Cached users with the same email are
+    /// replaced
+    Import {
+        path: String,
 
-    #[clap(short, long, default_value = "340")]
-    pub version: usize,
+        /// csv or json. Detected from the extension if not given
+        #[clap(long)]
+        format: Option<CacheFormat>,
+    },
 }
 
 impl Opts {
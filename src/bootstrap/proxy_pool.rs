@@ -0,0 +1,379 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bootstrap::Proxy,
+    error::{err, Error, ProxyErr, Res},
+};
+
+/// The proxy pool shared between the user loader and the connection stream
+pub type Proxies = Rc<RefCell<ProxyPool>>;
+
+/// How proxies are shared and when they are taken out of rotation
+#[derive(Copy, Clone, Debug)]
+pub struct ProxyLimits {
+    /// the maximum number of bots connected through one proxy. `None` is
+    /// unlimited
+    pub max_bots: Option<usize>,
+
+    /// consecutive failures before a proxy is quarantined
+    pub max_failures: u32,
+
+    /// how long a quarantined proxy is not used
+    pub quarantine: Duration,
+}
+
+impl Default for ProxyLimits {
+    fn default() -> Self {
+        Self {
+            max_bots: None,
+            max_failures: 3,
+            quarantine: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Why connecting through a proxy failed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProxyFailure {
+    /// the proxy could not be reached or could not reach the server
+    Connect,
+
+    /// the proxy rejected our credentials
+    Auth,
+}
+
+impl From<&Error> for ProxyFailure {
+    fn from(error: &Error) -> Self {
+        use tokio_socks::Error as Socks;
+        match error {
+            Error::Socks5(
+                Socks::PasswordAuthFailure(_)
+                | Socks::AuthorizationRequired
+                | Socks::NoAcceptableAuthMethods
+                | Socks::InvalidAuthValues(_),
+            )
+            | Error::Proxy(ProxyErr::Auth(_)) => ProxyFailure::Auth,
+            _ => ProxyFailure::Connect,
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+struct ProxyStats {
+    /// bots currently assigned to this proxy
+    bots: usize,
+    connects: u32,
+    connect_failures: u32,
+    auth_failures: u32,
+
+    /// failures since the last successful connection
+    consecutive_failures: u32,
+
+    /// the sum of the latency of all successful connections
+    total_latency: Duration,
+    quarantined_until: Option<Instant>,
+}
+
+impl ProxyStats {
+    fn quarantined(&self, now: Instant) -> bool {
+        self.quarantined_until.map_or(false, |until| now < until)
+    }
+
+    fn average_latency(&self) -> Option<Duration> {
+        (self.connects > 0).then(|| self.total_latency / self.connects)
+    }
+}
+
+#[derive(Debug)]
+struct ProxyEntry {
+    proxy: Proxy,
+    stats: ProxyStats,
+}
+
+/// Hands out proxies to bots. An empty pool connects directly.
+#[derive(Debug)]
+pub struct ProxyPool {
+    entries: Vec<ProxyEntry>,
+    limits: ProxyLimits,
+
+    /// the index the next round robin search starts at
+    next: usize,
+}
+
+impl ProxyPool {
+    pub fn new(proxies: Vec<Proxy>, limits: ProxyLimits) -> ProxyPool {
+        let entries = proxies
+            .into_iter()
+            .map(|proxy| ProxyEntry {
+                proxy,
+                stats: ProxyStats::default(),
+            })
+            .collect();
+
+        ProxyPool {
+            entries,
+            limits,
+            next: 0,
+        }
+    }
+
+    pub fn shared(self) -> Proxies {
+        Rc::new(RefCell::new(self))
+    }
+
+    fn index_of(&self, proxy: &Proxy) -> Option<usize> {
+        let address = proxy.address();
+        self.entries
+            .iter()
+            .position(|entry| entry.proxy.address() == address)
+    }
+
+    fn usable(&self, idx: usize, now: Instant) -> bool {
+        let stats = &self.entries[idx].stats;
+        let full = self.limits.max_bots.map_or(false, |max| stats.bots >= max);
+        !full && !stats.quarantined(now)
+    }
+
+    /// The next proxy in the rotation which is not quarantined. This does not
+    /// reserve a slot and is used for auth requests. If every proxy is
+    /// quarantined the next one is returned anyway.
+    pub fn pick(&mut self) -> Option<Proxy> {
+        let len = self.entries.len();
+        if len == 0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let idx = (0..len)
+            .map(|offset| (self.next + offset) % len)
+            .find(|&idx| !self.entries[idx].stats.quarantined(now))
+            .unwrap_or(self.next % len);
+
+        self.next = idx + 1;
+        Some(self.entries[idx].proxy.clone())
+    }
+
+    /// Reserves a slot for a bot. `preferred` is used if it has room and is not
+    /// quarantined, otherwise the next usable proxy is. Proxies in `exclude`
+    /// (i.e., ones the bot just failed to connect through) are never picked.
+    /// `Ok(None)` means connecting directly.
+    pub fn acquire(&mut self, preferred: Option<&Proxy>, exclude: &[Proxy]) -> Res<Option<Proxy>> {
+        let len = self.entries.len();
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let now = Instant::now();
+
+        let excluded = |entry: &ProxyEntry| {
+            let address = entry.proxy.address();
+            exclude.iter().any(|proxy| proxy.address() == address)
+        };
+        let candidate = |idx: usize| self.usable(idx, now) && !excluded(&self.entries[idx]);
+
+        let preferred = preferred
+            .and_then(|proxy| self.index_of(proxy))
+            .filter(|&idx| candidate(idx));
+
+        let idx = match preferred {
+            Some(idx) => idx,
+            None => {
+                let idx = (0..len)
+                    .map(|offset| (self.next + offset) % len)
+                    .find(|&idx| candidate(idx))
+                    .ok_or_else(|| err("no proxy is available (all are full or quarantined)"))?;
+                self.next = idx + 1;
+                idx
+            }
+        };
+
+        let entry = &mut self.entries[idx];
+        entry.stats.bots += 1;
+        Ok(Some(entry.proxy.clone()))
+    }
+
+    /// Records a successful connection through `proxy`. The slot stays
+    /// reserved.
+    pub fn succeeded(&mut self, proxy: &Proxy, latency: Duration) {
+        if let Some(idx) = self.index_of(proxy) {
+            let stats = &mut self.entries[idx].stats;
+            stats.connects += 1;
+            stats.consecutive_failures = 0;
+            stats.total_latency += latency;
+        }
+    }
+
+    /// Releases the slot of a bot which no longer uses `proxy`, i.e., when it
+    /// disconnects or its login fails
+    pub fn release(&mut self, proxy: &Proxy) {
+        if let Some(idx) = self.index_of(proxy) {
+            let stats = &mut self.entries[idx].stats;
+            stats.bots = stats.bots.saturating_sub(1);
+        }
+    }
+
+    /// Records a failed connection through `proxy` and releases its slot. The
+    /// proxy is quarantined after too many failures in a row.
+    pub fn failed(&mut self, proxy: &Proxy, failure: ProxyFailure) {
+        self.release(proxy);
+
+        let limits = self.limits;
+        if let Some(idx) = self.index_of(proxy) {
+            let stats = &mut self.entries[idx].stats;
+            stats.consecutive_failures += 1;
+
+            match failure {
+                ProxyFailure::Connect => stats.connect_failures += 1,
+                ProxyFailure::Auth => stats.auth_failures += 1,
+            }
+
+            // wrong credentials will not fix themselves
+            let quarantine =
+                failure == ProxyFailure::Auth || stats.consecutive_failures >= limits.max_failures;
+
+            if quarantine {
+                println!(
+                    "quarantining proxy {} for {}s",
+                    proxy.address(),
+                    limits.quarantine.as_secs()
+                );
+                stats.quarantined_until = Some(Instant::now() + limits.quarantine);
+                stats.consecutive_failures = 0;
+            }
+        }
+    }
+
+    /// Prints the stats of every proxy
+    pub fn print_summary(&self) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+
+        println!("proxy summary");
+        println!(
+            "{:<24} {:>5} {:>8} {:>13} {:>10} {:>12} {:>12}",
+            "proxy",
+            "bots",
+            "connects",
+            "connect fails",
+            "auth fails",
+            "avg latency",
+            "quarantined"
+        );
+        for ProxyEntry { proxy, stats } in &self.entries {
+            let latency = stats
+                .average_latency()
+                .map(|latency| format!("{}ms", latency.as_millis()))
+                .unwrap_or_else(|| "-".to_string());
+
+            println!(
+                "{:<24} {:>5} {:>8} {:>13} {:>10} {:>12} {:>12}",
+                proxy.address(),
+                stats.bots,
+                stats.connects,
+                stats.connect_failures,
+                stats.auth_failures,
+                latency,
+                stats.quarantined(now)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bootstrap::{
+        proxy_pool::{ProxyFailure, ProxyLimits, ProxyPool},
+        Proxy, ProxyScheme,
+    };
+
+    fn proxy(port: u32) -> Proxy {
+        Proxy {
+            scheme: ProxyScheme::Socks5,
+            host: "127.0.0.1".to_string(),
+            port,
+            user: String::new(),
+            pass: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_pool() {
+        let limits = ProxyLimits {
+            max_bots: Some(1),
+            max_failures: 2,
+            quarantine: Duration::from_secs(60),
+        };
+        let mut pool = ProxyPool::new(vec![proxy(1), proxy(2)], limits);
+
+        // the cap moves the second bot to the other proxy
+        let first = pool.acquire(Some(&proxy(1)), &[]).unwrap().unwrap();
+        let second = pool.acquire(Some(&proxy(1)), &[]).unwrap().unwrap();
+        assert_eq!(first.port, 1);
+        assert_eq!(second.port, 2);
+        assert!(pool.acquire(None, &[]).is_err());
+
+        // a failure frees the slot
+        pool.failed(&first, ProxyFailure::Connect);
+        assert_eq!(pool.acquire(None, &[]).unwrap().unwrap().port, 1);
+
+        // the second failure in a row quarantines it
+        pool.failed(&first, ProxyFailure::Connect);
+        pool.failed(&second, ProxyFailure::Auth);
+        assert!(pool.acquire(None, &[]).is_err());
+    }
+
+    #[test]
+    fn test_release_and_exclude() {
+        let limits = ProxyLimits {
+            max_bots: Some(1),
+            ..ProxyLimits::default()
+        };
+        let mut pool = ProxyPool::new(vec![proxy(1), proxy(2)], limits);
+
+        // a retry does not go back to the proxy which just failed
+        let first = pool.acquire(Some(&proxy(1)), &[]).unwrap().unwrap();
+        pool.failed(&first, ProxyFailure::Connect);
+        let retry = pool
+            .acquire(Some(&first), &[first.clone()])
+            .unwrap()
+            .unwrap();
+        assert_eq!(retry.port, 2);
+
+        // a disconnected bot frees its slot
+        let other = pool.acquire(None, &[]).unwrap().unwrap();
+        assert_eq!(other.port, 1);
+        assert!(pool.acquire(None, &[]).is_err());
+        pool.release(&retry);
+        assert_eq!(pool.acquire(None, &[]).unwrap().unwrap().port, 2);
+    }
+
+    #[test]
+    fn test_direct() {
+        let mut pool = ProxyPool::new(Vec::new(), ProxyLimits::default());
+        assert!(pool.acquire(None, &[]).unwrap().is_none());
+        assert!(pool.pick().is_none());
+    }
+}
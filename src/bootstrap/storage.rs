@@ -13,20 +13,35 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use bincode::{config::Configuration, Decode, Encode};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     convert::TryFrom,
+    default::default,
     fs::{File, OpenOptions},
-    io::{Read, Write},
+    io::Write,
     path::PathBuf,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    oneshot,
+};
 
 use crate::{
     bootstrap,
-    bootstrap::{mojang::MojangApi, CSVUser, Proxy},
+    bootstrap::{
+        cache_key,
+        cache_key::CacheKey,
+        mojang::{
+            AuthMethod, MicrosoftAuthResponse, MicrosoftEndpoints, MojangApi, YggdrasilServers,
+        },
+        offline,
+        proxy_pool::Proxies,
+        CSVUser, Proxy,
+    },
+    error::{err, Res},
     HasContext, ResContext,
 };
 
@@ -35,10 +50,17 @@ struct Root {
     users: Vec<User>,
 }
 
+/// [`Root`] without owning the users so the cache can be saved in place
+#[derive(Encode)]
+struct RootRef<'a> {
+    users: Vec<&'a User>,
+}
+
 #[derive(Encode, Decode, Debug)]
 enum User {
     Valid(ValidUser),
     Invalid(InvalidUser),
+    Microsoft(MicrosoftUser),
 }
 
 #[derive(Encode, Decode, Debug)]
@@ -58,11 +80,167 @@ pub struct ValidUser {
     pub client_id: String,
 }
 
+/// A user authenticated with a Microsoft account. The refresh token lets us
+/// obtain a new Minecraft access token without the device code login.
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct MicrosoftUser {
+    pub user: ValidUser,
+    pub refresh_token: String,
+
+    /// when the Minecraft access token expires (seconds since the unix epoch)
+    pub expires_at: u64,
+}
+
+impl MicrosoftUser {
+    fn new(email: &str, auth: MicrosoftAuthResponse) -> MicrosoftUser {
+        let now = time();
+        MicrosoftUser {
+            user: ValidUser {
+                email: email.to_string(),
+                username: auth.username,
+                password: String::new(),
+                last_checked: now,
+                uuid: auth.uuid.to_string(),
+                access_id: auth.access_token,
+                client_id: String::new(),
+            },
+            refresh_token: auth.refresh_token,
+            expires_at: now + auth.expires_in,
+        }
+    }
+}
+
 impl User {
     fn email(&self) -> &String {
         match self {
             User::Valid(ValidUser { email, .. }) => email,
             User::Invalid(InvalidUser { email, .. }) => email,
+            User::Microsoft(MicrosoftUser {
+                user: ValidUser { email, .. },
+                ..
+            }) => email,
+        }
+    }
+}
+
+/// What a cached user is
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    Valid,
+    Invalid,
+    Microsoft,
+}
+
+/// A cached user as listed, exported and imported by the `cache` subcommand.
+/// Values a user does not have are empty (or 0).
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct CacheRecord {
+    pub email: String,
+    pub status: CacheStatus,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub uuid: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub access_id: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub last_checked: u64,
+    #[serde(default)]
+    pub refresh_token: String,
+    #[serde(default)]
+    pub expires_at: u64,
+}
+
+impl From<&User> for CacheRecord {
+    fn from(user: &User) -> Self {
+        let (status, valid, refresh_token, expires_at) = match user {
+            User::Valid(valid) => (CacheStatus::Valid, valid.clone(), String::new(), 0),
+            User::Invalid(InvalidUser { email, password }) => {
+                let valid = ValidUser {
+                    email: email.clone(),
+                    username: String::new(),
+                    password: password.clone(),
+                    last_checked: 0,
+                    uuid: String::new(),
+                    access_id: String::new(),
+                    client_id: String::new(),
+                };
+                (CacheStatus::Invalid, valid, String::new(), 0)
+            }
+            User::Microsoft(microsoft) => (
+                CacheStatus::Microsoft,
+                microsoft.user.clone(),
+                microsoft.refresh_token.clone(),
+                microsoft.expires_at,
+            ),
+        };
+
+        let ValidUser {
+            email,
+            username,
+            password,
+            last_checked,
+            uuid,
+            access_id,
+            client_id,
+        } = valid;
+
+        CacheRecord {
+            email,
+            status,
+            username,
+            uuid,
+            password,
+            access_id,
+            client_id,
+            last_checked,
+            refresh_token,
+            expires_at,
+        }
+    }
+}
+
+impl From<CacheRecord> for User {
+    fn from(record: CacheRecord) -> Self {
+        let CacheRecord {
+            email,
+            status,
+            username,
+            uuid,
+            password,
+            access_id,
+            client_id,
+            last_checked,
+            refresh_token,
+            expires_at,
+        } = record;
+
+        let valid = ValidUser {
+            email,
+            username,
+            password,
+            last_checked,
+            uuid,
+            access_id,
+            client_id,
+        };
+
+        match status {
+            CacheStatus::Valid => User::Valid(valid),
+            CacheStatus::Invalid => User::Invalid(InvalidUser {
+                email: valid.email,
+                password: valid.password,
+            }),
+            CacheStatus::Microsoft => User::Microsoft(MicrosoftUser {
+                user: valid,
+                refresh_token,
+                expires_at,
+            }),
         }
     }
 }
@@ -70,12 +248,87 @@ impl User {
 pub struct UserCache {
     file_path: PathBuf,
     cache: HashMap<String, User>,
+    policy: TokenPolicy,
+    servers: YggdrasilServers,
+
+    /// the key the cache is encrypted with. `None` for a plaintext cache
+    key: Option<CacheKey>,
+}
+
+/// When cached access tokens are checked with the auth server. All ages are
+/// the seconds since a user was last checked (`last_checked`).
+#[derive(Copy, Clone, Debug)]
+pub struct TokenPolicy {
+    /// tokens checked more recently than this are used without a request
+    pub validate_after: u64,
+
+    /// tokens checked longer ago than this are refreshed without validating
+    /// them first
+    pub refresh_after: u64,
+
+    /// Microsoft tokens which expire within this many seconds are refreshed
+    pub expiry_margin: u64,
+}
+
+impl Default for TokenPolicy {
+    fn default() -> Self {
+        Self {
+            validate_after: 3600,
+            refresh_after: 3600 * 24,
+            expiry_margin: 300,
+        }
+    }
+}
+
+/// What to do with a cached access token
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum TokenCheck {
+    /// use it as-is
+    Trust,
+
+    /// ask the auth server if it is still valid
+    Validate,
+
+    /// obtain a new token
+    Refresh,
+}
+
+impl TokenPolicy {
+    fn check(&self, last_checked: u64, now: u64) -> TokenCheck {
+        let age = now.saturating_sub(last_checked);
+        if age < self.validate_after {
+            TokenCheck::Trust
+        } else if age < self.refresh_after {
+            TokenCheck::Validate
+        } else {
+            TokenCheck::Refresh
+        }
+    }
+
+    fn expired(&self, expires_at: u64, now: u64) -> bool {
+        now + self.expiry_margin >= expires_at
+    }
+}
+
+/// What [`UserCache::revalidate`] did with a user
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Revalidation {
+    NotCached,
+
+    /// the cached tokens are valid
+    Valid,
+
+    /// the cached tokens were replaced with new ones
+    Refreshed,
+
+    /// the user is authenticated again on the next run
+    Removed,
 }
 
 /// A bot data holds the "Mojang" object used in cache to verify that the user
 /// is valid along with data about what the proxy address is and the valid user
 /// information
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BotData {
     pub user: ValidUser,
     pub proxy: Option<Proxy>,
@@ -84,36 +337,84 @@ pub struct BotData {
 
 impl BotData {
     pub fn load(
-        proxy: bool,
         users_file: &str,
-        proxies_file: &str,
         count: usize,
-    ) -> ResContext<Receiver<BotData>> {
+        auth: AuthMethod,
+        servers: YggdrasilServers,
+        proxies: Proxies,
+        policy: TokenPolicy,
+        encrypt_cache: bool,
+    ) -> ResContext<(Receiver<BotData>, Revalidator)> {
         let csv_file = File::open(&users_file)
             .context(|| format!("could not open users file {}", users_file))?;
 
         let csv_users =
             bootstrap::csv::read_users(csv_file).context_str("could not open users file")?;
 
-        let proxies = match proxy {
-            true => {
-                let proxies_file = File::open(&proxies_file)
-                    .context(|| format!("could not open proxies file {}", proxies_file))?;
-                bootstrap::csv::read_proxies(proxies_file)
-                    .context_str("could not open proxies file")?
-                    .into_iter()
-                    .map(Some)
-                    .collect()
-            }
-            false => {
-                vec![None]
-            }
-        };
+        let cache = UserCache::load(
+            "cache.db".into(),
+            policy,
+            servers,
+            encrypt_cache,
+            cache_key::passphrase,
+        )
+        .context_str("could not load cache.db")?;
+
+        Ok(cache.obtain_users(count, csv_users, proxies, auth))
+    }
+
+    /// Generates `count` offline-mode users named `{prefix}{idx}`. Neither
+    /// `users.csv` nor Mojang are used.
+    pub fn offline(prefix: &str, count: usize, proxies: Proxies) -> Receiver<BotData> {
+        offline::obtain_users(prefix, count, proxies)
+    }
+}
 
-        let cache = UserCache::load("cache.db".into());
+/// Asks the cache a user came from to check its tokens again
+type RevalidateRequest = (String, oneshot::Sender<Option<ValidUser>>);
+
+/// Checks the tokens of bots which reconnect with the [`TokenPolicy`] of the
+/// cache they were loaded from. Their tokens can expire while they are
+/// connected
+#[derive(Clone, Debug)]
+pub struct Revalidator(Sender<RevalidateRequest>);
+
+impl Revalidator {
+    /// `user` with checked (and possibly refreshed) tokens
+    pub async fn revalidate(&self, user: &ValidUser) -> Res<ValidUser> {
+        let (reply, rx) = oneshot::channel();
+        self.0
+            .send((user.email.clone(), reply))
+            .await
+            .map_err(|_| err("the user cache is closed"))?;
+
+        rx.await
+            .ok()
+            .flatten()
+            .ok_or_else(|| err("could not authenticate again"))
+    }
+}
 
-        Ok(cache.obtain_users(count, csv_users, proxies))
+/// Loads the proxies in `proxies_file`. No proxies are used if `proxy` is
+/// false.
+pub fn load_proxies(proxy: bool, proxies_file: &str) -> ResContext<Vec<Proxy>> {
+    if !proxy {
+        return Ok(Vec::new());
     }
+
+    let proxies_file = File::open(&proxies_file)
+        .context(|| format!("could not open proxies file {}", proxies_file))?;
+    bootstrap::csv::read_proxies(proxies_file).context_str("could not open proxies file")
+}
+
+/// The auth client for `email`. Auth requests are sent directly if they
+/// cannot go through `proxy`, i.e., a SOCKS4 proxy
+fn client(email: &str, proxy: Option<&Proxy>, servers: YggdrasilServers) -> MojangApi {
+    let mojang = MojangApi::try_from(proxy).unwrap_or_else(|e| {
+        println!("authenticating {} without a proxy .. {}", email, e);
+        MojangApi::default()
+    });
+    mojang.servers(servers)
 }
 
 fn time() -> u64 {
@@ -125,37 +426,199 @@ fn time() -> u64 {
 }
 
 impl UserCache {
-    pub fn load(file_path: PathBuf) -> UserCache {
-        let exists = std::fs::try_exists(&file_path).unwrap();
-        if !exists {
-            UserCache {
-                file_path,
-                cache: HashMap::new(),
+    /// Loads the cache at `file_path`. An encrypted cache is decrypted with
+    /// the passphrase, which is only asked for if it is needed. If `encrypt` is
+    /// set a new cache is encrypted and a plaintext cache is encrypted in
+    /// place.
+    pub fn load(
+        file_path: PathBuf,
+        policy: TokenPolicy,
+        servers: YggdrasilServers,
+        encrypt: bool,
+        passphrase: impl Fn() -> Res<String>,
+    ) -> Res<UserCache> {
+        let mut cache = UserCache {
+            file_path,
+            cache: HashMap::new(),
+            policy,
+            servers,
+            key: None,
+        };
+
+        if !std::fs::try_exists(&cache.file_path)? {
+            if encrypt {
+                cache.key = Some(CacheKey::generate(&passphrase()?));
             }
-        } else {
-            let file = File::open(&file_path).unwrap();
-            let bytes: Result<Vec<_>, _> = file.bytes().collect();
-            let bytes = bytes.unwrap();
-            let (Root { users }, _) =
-                bincode::decode_from_slice(&bytes, Configuration::standard()).unwrap();
-
-            let cache: HashMap<_, _> = users
-                .into_iter()
-                .map(|user| (user.email().clone(), user))
-                .collect();
-            UserCache { file_path, cache }
+            return Ok(cache);
+        }
+
+        let mut bytes = std::fs::read(&cache.file_path)?;
+        let mut migrate = false;
+
+        if cache_key::is_encrypted(&bytes) {
+            let (key, plaintext) = CacheKey::open(&passphrase()?, &bytes)?;
+            cache.key = Some(key);
+            bytes = plaintext;
+        } else if encrypt {
+            cache.key = Some(CacheKey::generate(&passphrase()?));
+            migrate = true;
+        }
+
+        let (Root { users }, _) = bincode::decode_from_slice(&bytes, Configuration::standard())
+            .map_err(|e| err(&format!("invalid cache: {}", e)))?;
+
+        cache.cache = users
+            .into_iter()
+            .map(|user| (user.email().clone(), user))
+            .collect();
+
+        if migrate {
+            cache.save()?;
+            println!("encrypted {}", cache.file_path.display());
+        }
+
+        Ok(cache)
+    }
+
+    /// Opens an existing cache to inspect or edit it
+    pub fn open(file_path: PathBuf) -> Res<UserCache> {
+        if !std::fs::try_exists(&file_path)? {
+            return Err(err(&format!("{} does not exist", file_path.display())));
+        }
+        Self::load(
+            file_path,
+            default(),
+            default(),
+            false,
+            cache_key::passphrase,
+        )
+    }
+
+    /// Every cached user sorted by email
+    pub fn records(&self) -> Vec<CacheRecord> {
+        let mut records: Vec<_> = self.cache.values().map(CacheRecord::from).collect();
+        records.sort_unstable_by(|a, b| a.email.cmp(&b.email));
+        records
+    }
+
+    /// Removes a user. Returns whether it was cached
+    pub fn remove(&mut self, email: &str) -> bool {
+        self.cache.remove(email).is_some()
+    }
+
+    /// Checks a user with the auth server now instead of trusting its cached
+    /// tokens. Tokens which are not valid anymore are refreshed. A user whose
+    /// tokens cannot be refreshed (or which is cached as invalid) is removed
+    /// so it is authenticated again on the next run.
+    pub async fn revalidate(
+        &mut self,
+        email: &str,
+        mojang: &MojangApi,
+        endpoints: Option<&MicrosoftEndpoints>,
+    ) -> Res<Revalidation> {
+        match self.cache.get_mut(email) {
+            None => Ok(Revalidation::NotCached),
+            Some(User::Invalid(_)) => {
+                self.remove(email);
+                Ok(Revalidation::Removed)
+            }
+            Some(User::Valid(valid)) => {
+                if mojang.validate(&valid.access_id, &valid.client_id).await? {
+                    valid.last_checked = time();
+                    return Ok(Revalidation::Valid);
+                }
+
+                match mojang.refresh(&valid.access_id, &valid.client_id).await {
+                    Ok(auth) => {
+                        valid.access_id = auth.access_token;
+                        valid.username = auth.username;
+                        valid.uuid = auth.uuid.to_string();
+                        valid.client_id = auth.client_token;
+                        valid.last_checked = time();
+                        Ok(Revalidation::Refreshed)
+                    }
+                    Err(e) => {
+                        println!("failed refreshing {} .. {}", email, e);
+                        self.remove(email);
+                        Ok(Revalidation::Removed)
+                    }
+                }
+            }
+            Some(User::Microsoft(microsoft)) => {
+                // the Minecraft token of a Microsoft account can only be checked by
+                // refreshing it
+                let endpoints = endpoints.ok_or_else(|| {
+                    err("a Microsoft account needs --ms-client-id to be revalidated")
+                })?;
+                let auth = mojang
+                    .microsoft_refresh(&microsoft.refresh_token, endpoints)
+                    .await?;
+                *microsoft = MicrosoftUser::new(email, auth);
+                Ok(Revalidation::Refreshed)
+            }
+        }
+    }
+
+    /// The emails of every cached user
+    pub fn emails(&self) -> Vec<String> {
+        self.cache.keys().cloned().collect()
+    }
+
+    /// Adds users, replacing cached users with the same email
+    pub fn import(&mut self, records: Vec<CacheRecord>) {
+        for record in records {
+            self.cache.insert(record.email.clone(), User::from(record));
+        }
+    }
+
+    /// Writes the cache to disk. This is done after every change so refreshed
+    /// tokens are not lost if we are stopped before all users are obtained.
+    /// The cache is written next to the old one and then moved over it, so
+    /// being stopped while saving does not destroy it.
+    pub fn save(&self) -> Res {
+        let root = RootRef {
+            users: self.cache.values().collect(),
+        };
+
+        let mut data = bincode::encode_to_vec(&root, Configuration::standard())
+            .map_err(|e| err(&format!("could not encode cache: {}", e)))?;
+        if let Some(key) = &self.key {
+            data = key.seal(&data);
+        }
+
+        let mut temp = self.file_path.clone().into_os_string();
+        temp.push(".tmp");
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+
+        std::fs::rename(&temp, &self.file_path)?;
+        Ok(())
+    }
+
+    /// Saves the cache, only logging if it fails
+    fn try_save(&self) {
+        if let Err(e) = self.save() {
+            println!("could not save {} .. {}", self.file_path.display(), e);
         }
     }
 
     async fn get_or_put(
         &mut self,
         user: &CSVUser,
-        iter: &mut impl Iterator<Item = Option<Proxy>>,
+        proxies: &Proxies,
     ) -> Option<(MojangApi, Option<Proxy>, ValidUser)> {
+        let policy = self.policy;
+        let servers = self.servers.clone();
         match self.cache.get_mut(&user.email) {
             None => {
-                let proxy = iter.next().unwrap();
-                let mojang = MojangApi::try_from(proxy.as_ref()).unwrap();
+                let proxy = proxies.borrow_mut().pick();
+                let mojang = client(&user.email, proxy.as_ref(), servers);
                 match mojang.authenticate(&user.email, &user.password).await {
                     Ok(res) => {
                         let valid_user = ValidUser {
@@ -189,65 +652,71 @@ impl UserCache {
             Some(cached) => {
                 match cached {
                     User::Valid(valid) => {
-                        let proxy = iter.next().unwrap();
-                        let mojang = MojangApi::try_from(proxy.as_ref()).unwrap();
-
-                        // if verified in last day don't even check to verify
-                        if time() - valid.last_checked < 3600 * 24 {
-                            return Some((mojang, proxy, valid.clone()));
+                        let proxy = proxies.borrow_mut().pick();
+                        let mojang = client(&user.email, proxy.as_ref(), servers);
+
+                        match policy.check(valid.last_checked, time()) {
+                            TokenCheck::Trust => return Some((mojang, proxy, valid.clone())),
+                            TokenCheck::Validate => {
+                                match mojang.validate(&valid.access_id, &valid.client_id).await {
+                                    Ok(true) => {
+                                        valid.last_checked = time();
+                                        return Some((mojang, proxy, valid.clone()));
+                                    }
+                                    Ok(false) => println!("failed validating {}", user.email),
+                                    Err(e) => {
+                                        println!("could not validate {} .. {}", user.email, e)
+                                    }
+                                }
+                            }
+                            TokenCheck::Refresh => {
+                                println!("refreshing auth tokens for {} due to time", user.email);
+                            }
                         }
 
-                        println!("refreshing auth tokens for {} due to time", user.email);
-
-                        let is_valid = mojang
-                            .validate(&valid.access_id, &valid.client_id)
-                            .await
-                            .unwrap();
-
-                        if !is_valid {
-                            println!("failed validating {}", user.email);
-                            match mojang.refresh(&valid.access_id, &valid.client_id).await {
-                                Ok(auth) => {
-                                    valid.access_id = auth.access_token;
-                                    valid.username = auth.username;
-                                    valid.uuid = auth.uuid.to_string();
-                                    valid.client_id = auth.client_token;
-                                    valid.last_checked = time();
-                                    return Some((mojang, proxy, valid.clone()));
-                                }
+                        match mojang.refresh(&valid.access_id, &valid.client_id).await {
+                            Ok(auth) => {
+                                valid.access_id = auth.access_token;
+                                valid.username = auth.username;
+                                valid.uuid = auth.uuid.to_string();
+                                valid.client_id = auth.client_token;
+                                valid.last_checked = time();
+                                return Some((mojang, proxy, valid.clone()));
+                            }
+
+                            // we could not refresh -> try to authenticate
+                            Err(e) => {
+                                println!("failed refreshing {} .. {}", user.email, e);
+                                match mojang.authenticate(&valid.email, &valid.password).await {
+                                    Ok(auth) => {
+                                        valid.access_id = auth.access_token;
+                                        valid.username = auth.username;
+                                        valid.uuid = auth.uuid.to_string();
+                                        valid.client_id = auth.client_token;
+                                        valid.last_checked = time();
+                                        return Some((mojang, proxy, valid.clone()));
+                                    }
 
-                                // we could not refresh -> try to authenticate
-                                Err(e) => {
-                                    println!("failed refreshing {} .. {}", user.email, e);
-                                    match mojang.authenticate(&valid.email, &valid.password).await {
-                                        Ok(auth) => {
-                                            valid.access_id = auth.access_token;
-                                            valid.username = auth.username;
-                                            valid.uuid = auth.uuid.to_string();
-                                            valid.client_id = auth.client_token;
-                                            valid.last_checked = time();
-                                            return Some((mojang, proxy, valid.clone()));
-                                        }
-
-                                        // we cannot do anything more -> change to invalid
-                                        Err(e) => {
-                                            println!(
-                                                "failed authenticating {} .. {}",
-                                                user.email, e
-                                            );
-                                            *cached = User::Invalid(InvalidUser {
-                                                email: valid.email.clone(),
-                                                password: valid.password.clone(),
-                                            })
-                                        }
+                                    // we cannot do anything more -> change to invalid
+                                    Err(e) => {
+                                        println!("failed authenticating {} .. {}", user.email, e);
+                                        *cached = User::Invalid(InvalidUser {
+                                            email: valid.email.clone(),
+                                            password: valid.password.clone(),
+                                        })
                                     }
                                 }
                             }
-                        } else {
-                            return Some((mojang, proxy, valid.clone()));
                         }
                     }
                     User::Invalid(_invalid) => {}
+                    User::Microsoft(_) => {
+                        println!(
+                            "user {} is cached as a Microsoft account. Use Microsoft authentication",
+                            user.email
+                        );
+                        return None;
+                    }
                 }
 
                 println!("user {} is cached as invalid. If this user **is** valid, delete cache.db and re-run", user.email);
@@ -256,31 +725,116 @@ impl UserCache {
         }
     }
 
+    async fn get_or_put_microsoft(
+        &mut self,
+        user: &CSVUser,
+        proxies: &Proxies,
+        endpoints: &MicrosoftEndpoints,
+    ) -> Option<(MojangApi, Option<Proxy>, ValidUser)> {
+        let proxy = proxies.borrow_mut().pick();
+        let mojang = client(&user.email, proxy.as_ref(), self.servers.clone());
+
+        if let Some(User::Microsoft(cached)) = self.cache.get(&user.email) {
+            if !self.policy.expired(cached.expires_at, time()) {
+                return Some((mojang, proxy, cached.user.clone()));
+            }
+
+            println!("refreshing Microsoft tokens for {} due to time", user.email);
+
+            match mojang
+                .microsoft_refresh(&cached.refresh_token, endpoints)
+                .await
+            {
+                Ok(auth) => {
+                    let microsoft = MicrosoftUser::new(&user.email, auth);
+                    let valid = microsoft.user.clone();
+                    self.cache
+                        .insert(user.email.clone(), User::Microsoft(microsoft));
+                    return Some((mojang, proxy, valid));
+                }
+
+                // the refresh token could have been revoked -> log in again
+                Err(e) => println!("failed refreshing {} .. {}", user.email, e),
+            }
+        }
+
+        match mojang.microsoft_device_login(&user.email, endpoints).await {
+            Ok(auth) => {
+                let microsoft = MicrosoftUser::new(&user.email, auth);
+                let valid = microsoft.user.clone();
+                self.cache
+                    .insert(user.email.clone(), User::Microsoft(microsoft));
+                Some((mojang, proxy, valid))
+            }
+
+            // we do not cache as invalid as the user may have just not entered the code
+            Err(e) => {
+                println!(
+                    "failed Microsoft authentication for {} .. {}",
+                    user.email, e
+                );
+                None
+            }
+        }
+    }
+
+    /// The user `user` with valid tokens and the auth client they were checked
+    /// with
+    async fn obtain(
+        &mut self,
+        user: &CSVUser,
+        proxies: &Proxies,
+        auth: &AuthMethod,
+    ) -> Option<(MojangApi, Option<Proxy>, ValidUser)> {
+        let res = match auth {
+            AuthMethod::Mojang => self.get_or_put(user, proxies).await,
+            AuthMethod::Microsoft(endpoints) => {
+                self.get_or_put_microsoft(user, proxies, endpoints).await
+            }
+        };
+
+        // persist refreshed tokens right away
+        self.try_save();
+        res
+    }
+
+    /// Sends up to `count` valid users. Afterwards the tokens of users which
+    /// reconnect are checked with the [`Revalidator`]
     pub fn obtain_users(
         mut self,
         count: usize,
         users: Vec<CSVUser>,
-        proxies: Vec<Option<Proxy>>,
-    ) -> Receiver<BotData> {
-        let mut proxies = proxies.into_iter().cycle();
-
+        proxies: Proxies,
+        auth: AuthMethod,
+    ) -> (Receiver<BotData>, Revalidator) {
         let (tx, rx) = tokio::sync::mpsc::channel(32);
+        let (revalidate_tx, mut revalidate_rx) =
+            tokio::sync::mpsc::channel::<RevalidateRequest>(32);
 
         tokio::task::spawn_local(async move {
+            let by_email: HashMap<_, _> = users
+                .iter()
+                .map(|user| (user.email.clone(), user.clone()))
+                .collect();
+
             let mut local_count = 0;
 
             'user_loop: for csv_user in users.into_iter() {
-                if let Some((mojang, proxy, user)) = self.get_or_put(&csv_user, &mut proxies).await
-                {
+                let res = self.obtain(&csv_user, &proxies, &auth).await;
+
+                if let Some((mojang, proxy, user)) = res {
                     local_count += 1;
                     println!("valid user {}", user.email);
-                    tx.send(BotData {
+                    let data = BotData {
                         user,
                         proxy,
                         mojang,
-                    })
-                    .await
-                    .unwrap();
+                    };
+
+                    // nothing is connecting bots anymore
+                    if tx.send(data).await.is_err() {
+                        break 'user_loop;
+                    }
                 } else {
                     println!("invalid user {}", csv_user.email);
                 }
@@ -290,22 +844,145 @@ impl UserCache {
                 }
             }
 
-            let mut file = OpenOptions::new()
-                .create(true)
-                .append(false)
-                .write(true)
-                .open(&self.file_path)
-                .unwrap();
+            self.try_save();
+
+            while let Some((email, reply)) = revalidate_rx.recv().await {
+                let user = match by_email.get(&email) {
+                    Some(csv_user) => self.obtain(csv_user, &proxies, &auth).await,
+                    None => None,
+                };
+                let _ = reply.send(user.map(|(.., user)| user));
+            }
+        });
+
+        (rx, Revalidator(revalidate_tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::default;
+
+    use bincode::config::Configuration;
+
+    use tokio::{runtime::Runtime, task::LocalSet};
+
+    use crate::{
+        bootstrap::{
+            cache_key,
+            mojang::AuthMethod,
+            proxy_pool::{ProxyLimits, ProxyPool},
+            storage::{
+                time, InvalidUser, RootRef, TokenCheck, TokenPolicy, User, UserCache, ValidUser,
+            },
+            CSVUser,
+        },
+        error::Res,
+    };
+
+    #[test]
+    fn test_token_policy() {
+        let policy = TokenPolicy::default();
+        let now = 1_000_000;
+
+        assert_eq!(policy.check(now - 10, now), TokenCheck::Trust);
+        assert_eq!(policy.check(now - 3600 * 2, now), TokenCheck::Validate);
+        assert_eq!(policy.check(now - 3600 * 48, now), TokenCheck::Refresh);
+
+        // a cache written by a machine with a clock ahead of ours
+        assert_eq!(policy.check(now + 10, now), TokenCheck::Trust);
+
+        assert!(!policy.expired(now + 3600, now));
+        assert!(policy.expired(now + 60, now));
+        assert!(policy.expired(now - 60, now));
+    }
+
+    #[test]
+    fn test_encrypt_migration() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-cache-{}.db", std::process::id()));
+        let passphrase = || Ok("hunter2".to_string());
+
+        let user = User::Invalid(InvalidUser {
+            email: "a@example.com".to_string(),
+            password: "secret".to_string(),
+        });
+        let root = RootRef { users: vec![&user] };
+        let plaintext = bincode::encode_to_vec(&root, Configuration::standard()).unwrap();
+        std::fs::write(&path, plaintext).unwrap();
+
+        // a plaintext cache is encrypted in place
+        let load = |encrypt, passphrase: fn() -> Res<String>| {
+            UserCache::load(
+                path.clone(),
+                TokenPolicy::default(),
+                default(),
+                encrypt,
+                passphrase,
+            )
+        };
+        let cache = load(true, passphrase).unwrap();
+        assert_eq!(cache.cache.len(), 1);
+        assert!(cache_key::is_encrypted(&std::fs::read(&path).unwrap()));
 
-            let users = self.cache.drain().map(|(_, v)| v).collect();
+        // and stays encrypted without --encrypt-cache
+        let cache = load(false, passphrase).unwrap();
+        assert!(cache.cache.contains_key("a@example.com"));
 
-            let root = Root { users };
+        assert!(load(false, || Ok("hunter3".to_string())).is_err());
 
-            let data = bincode::encode_to_vec(&root, Configuration::standard()).unwrap();
-            file.write_all(&data).unwrap();
-            file.flush().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_revalidator() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-users-{}.db", std::process::id()));
+        let mut cache = UserCache::load(
+            path.clone(),
+            TokenPolicy::default(),
+            default(),
+            false,
+            || Ok(String::new()),
+        )
+        .unwrap();
+
+        // checked just now, so the tokens are trusted without a request
+        let user = ValidUser {
+            email: "a@example.com".to_string(),
+            username: "a".to_string(),
+            password: "secret".to_string(),
+            last_checked: time(),
+            uuid: "00000000000000000000000000000001".to_string(),
+            access_id: "access".to_string(),
+            client_id: "client".to_string(),
+        };
+        cache
+            .cache
+            .insert(user.email.clone(), User::Valid(user.clone()));
+
+        let csv_user = CSVUser {
+            email: user.email.clone(),
+            password: user.password,
+        };
+        let proxies = ProxyPool::new(Vec::new(), ProxyLimits::default()).shared();
+
+        let rt = Runtime::new().unwrap();
+        LocalSet::new().block_on(&rt, async move {
+            let (mut bots, revalidator) =
+                cache.obtain_users(1, vec![csv_user], proxies, AuthMethod::Mojang);
+            let bot = bots.recv().await.unwrap();
+            assert_eq!(bot.user.access_id, "access");
+
+            let revalidated = revalidator.revalidate(&bot.user).await.unwrap();
+            assert_eq!(revalidated.access_id, "access");
+
+            // only users loaded from the users file can be checked again
+            let unknown = ValidUser {
+                email: "b@example.com".to_string(),
+                ..bot.user
+            };
+            assert!(revalidator.revalidate(&unknown).await.is_err());
         });
 
-        rx
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,208 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{net::Ipv4Addr, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::{
+    bootstrap::{Address, Proxy, ProxyScheme},
+    error::{err, ProxyErr, Res},
+};
+
+/// The longest HTTP CONNECT response header we accept
+const MAX_HTTP_HEADER: usize = 8192;
+
+/// How long connecting and the proxy handshake can take. A proxy which drops
+/// our packets would otherwise hold the login slot until the OS gives up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Opens a TCP stream to `target` directly or tunneled through `proxy`
+pub async fn connect(target: &Address, proxy: Option<&Proxy>) -> Res<TcpStream> {
+    connect_within(target, proxy, CONNECT_TIMEOUT).await
+}
+
+/// [`connect`] which fails after `timeout`
+async fn connect_within(
+    target: &Address,
+    proxy: Option<&Proxy>,
+    timeout: Duration,
+) -> Res<TcpStream> {
+    tokio::time::timeout(timeout, tunnel(target, proxy))
+        .await
+        .unwrap_or_else(|_| Err(err("timed out connecting")))
+}
+
+async fn tunnel(target: &Address, proxy: Option<&Proxy>) -> Res<TcpStream> {
+    let proxy = match proxy {
+        None => return Ok(TcpStream::connect(String::from(target)).await?),
+        Some(proxy) => proxy,
+    };
+
+    match proxy.scheme {
+        ProxyScheme::Socks5 => socks5(proxy, target).await,
+        ProxyScheme::Socks4 => socks4(proxy, target).await,
+        ProxyScheme::Http => http_connect(proxy, target).await,
+    }
+}
+
+async fn socks5(proxy: &Proxy, target: &Address) -> Res<TcpStream> {
+    let address = proxy.address();
+    let target = String::from(target);
+
+    let stream = if proxy.has_auth() {
+        Socks5Stream::connect_with_password(
+            address.as_str(),
+            target.as_str(),
+            &proxy.user,
+            &proxy.pass,
+        )
+        .await?
+    } else {
+        Socks5Stream::connect(address.as_str(), target.as_str()).await?
+    };
+
+    Ok(stream.into_inner())
+}
+
+/// SOCKS4a. The proxy resolves hostnames. The user (if any) is sent as the
+/// user id.
+async fn socks4(proxy: &Proxy, target: &Address) -> Res<TcpStream> {
+    let mut stream = TcpStream::connect(proxy.address()).await?;
+
+    let ip = target.host.parse::<Ipv4Addr>().ok();
+
+    let mut request = vec![0x04, 0x01];
+    request.extend_from_slice(&target.port.to_be_bytes());
+
+    // 0.0.0.x asks the proxy to resolve the hostname after the user id
+    request.extend_from_slice(&ip.unwrap_or_else(|| Ipv4Addr::new(0, 0, 0, 1)).octets());
+    request.extend_from_slice(proxy.user.as_bytes());
+    request.push(0);
+
+    if ip.is_none() {
+        request.extend_from_slice(target.host.as_bytes());
+        request.push(0);
+    }
+
+    stream.write_all(&request).await?;
+
+    let mut reply = [0_u8; 8];
+    stream.read_exact(&mut reply).await?;
+
+    match reply[1] {
+        0x5a => Ok(stream),
+        0x5c | 0x5d => Err(ProxyErr::Auth(format!("SOCKS4 identd rejected #{}", reply[1])).into()),
+        code => Err(ProxyErr::Refused(format!("SOCKS4 request rejected #{}", code)).into()),
+    }
+}
+
+async fn http_connect(proxy: &Proxy, target: &Address) -> Res<TcpStream> {
+    let mut stream = TcpStream::connect(proxy.address()).await?;
+
+    let target = String::from(target);
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if proxy.has_auth() {
+        let credentials = base64::encode(format!("{}:{}", proxy.user, proxy.pass));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+
+    stream.write_all(request.as_bytes()).await?;
+
+    // read byte by byte so we do not consume anything the server sends after
+    // the tunnel is established
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() > MAX_HTTP_HEADER {
+            return Err(ProxyErr::Refused("HTTP CONNECT response too long".to_string()).into());
+        }
+        header.push(stream.read_u8().await?);
+    }
+
+    let header = String::from_utf8_lossy(&header);
+    let status_line = header.lines().next().unwrap_or_default();
+    let status = http_status(status_line)
+        .ok_or_else(|| ProxyErr::Refused(format!("invalid HTTP response {}", status_line)))?;
+
+    match status {
+        200..=299 => Ok(stream),
+        407 => Err(ProxyErr::Auth(status_line.to_string()).into()),
+        _ => Err(ProxyErr::Refused(status_line.to_string()).into()),
+    }
+}
+
+/// The status code of a status line, i.e., `HTTP/1.1 200 OK`
+fn http_status(status_line: &str) -> Option<u16> {
+    let mut parts = status_line.split_whitespace();
+    parts
+        .next()
+        .filter(|version| version.starts_with("HTTP/"))?;
+    parts.next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{net::TcpListener, runtime::Runtime};
+
+    use crate::bootstrap::{
+        proxy_pool::ProxyFailure,
+        tunnel::{connect_within, http_status},
+        Address, Proxy, ProxyScheme,
+    };
+
+    #[test]
+    fn test_http_status() {
+        assert_eq!(
+            http_status("HTTP/1.1 200 Connection established"),
+            Some(200)
+        );
+        assert_eq!(
+            http_status("HTTP/1.0 407 Proxy Authentication Required"),
+            Some(407)
+        );
+        assert_eq!(http_status("SSH-2.0-OpenSSH"), None);
+    }
+
+    #[test]
+    fn test_timeout() {
+        Runtime::new().unwrap().block_on(async {
+            // accepts the connection but never answers the handshake
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let _accept = tokio::spawn(async move { listener.accept().await });
+
+            let proxy = Proxy {
+                scheme: ProxyScheme::Socks4,
+                host: "127.0.0.1".to_string(),
+                port: port as u32,
+                user: String::new(),
+                pass: String::new(),
+            };
+            let target = Address {
+                host: "127.0.0.1".to_string(),
+                port: 25565,
+            };
+
+            let res = connect_within(&target, Some(&proxy), Duration::from_millis(100)).await;
+            assert_eq!(ProxyFailure::from(&res.unwrap_err()), ProxyFailure::Connect);
+        });
+    }
+}
@@ -14,23 +14,26 @@
 
 use std::{
     fmt::{Display, Formatter},
-    num::ParseIntError,
-    time::Instant,
+    num::{ParseFloatError, ParseIntError},
 };
 
 use float_ord::FloatOrd;
-use interfaces::types::BlockLocation;
+use interfaces::types::{BlockKind, BlockLocation};
 use itertools::Itertools;
 
 use crate::{
     client::{
+        commands::BotCommand,
         state::{global::GlobalState, local::LocalState},
         tasks::{
-            compound::CompoundTask, eat::EatTask, fall_bucket::FallBucketTask, mine::MineTask,
+            collect_items::CollectItems, compound::CompoundTask, eat::EatTask,
+            fall_bucket::FallBucketTask, lazy_stream::LazyStream, mine::MineTask,
             navigate::BlockTravelTask, Task, TaskTrait,
         },
+        timing::Budget,
     },
     protocol::{EventQueue, Face, InterfaceOut},
+    storage::entities::EntityKind,
     types::Displacement,
 };
 
@@ -56,6 +59,13 @@ pub struct Bot<Queue: EventQueue, Out: InterfaceOut> {
 }
 
 impl<Queue: EventQueue, Out: InterfaceOut> Bot<Queue, Out> {
+    /// Gives the bot the task of a command. The command is captured if the
+    /// packets of the bot are
+    pub fn command(&mut self, command: &BotCommand) {
+        self.queue.record_command(command);
+        self.actions.schedule(command.task(&self.state));
+    }
+
     pub fn run_sync(&mut self, global: &mut GlobalState) {
         match self.actions.task.as_mut() {
             None => {}
@@ -65,10 +75,10 @@ impl<Queue: EventQueue, Out: InterfaceOut> Bot<Queue, Out> {
                 }
             }
         }
-        let actions = self
-            .state
-            .physics
-            .tick(&mut global.blocks, &self.state.inventory);
+        let actions = self.state.physics.tick(
+            &mut global.blocks[self.state.world()],
+            &self.state.inventory,
+        );
         let physics = &self.state.physics;
         self.out
             .teleport_and_look(physics.location(), physics.direction(), physics.on_ground());
@@ -97,11 +107,17 @@ impl<Queue: EventQueue, Out: InterfaceOut> Bot<Queue, Out> {
     }
 }
 
+/// How far `collect` looks for items if no radius is given
+const DEFAULT_COLLECT_RADIUS: f64 = 32.0;
+
 #[derive(Error, Debug)]
 pub enum ProcessError {
     #[error(transparent)]
     Parse(#[from] ParseIntError),
 
+    #[error(transparent)]
+    ParseFloat(#[from] ParseFloatError),
+
     #[error(transparent)]
     Count(#[from] WrongArgCount),
 }
@@ -202,6 +218,30 @@ pub fn process_command(
             compound.add(mine).add(fall);
             actions.schedule(compound);
         }
+        "collect" => {
+            let (radius, kind) = match args {
+                [] => (DEFAULT_COLLECT_RADIUS, None),
+                [radius] => (radius.parse()?, None),
+                [radius, id] => (radius.parse()?, Some(BlockKind(id.parse()?))),
+                _ => return Err(WrongArgCount::new(2).into()),
+            };
+            actions.schedule(LazyStream::from(CollectItems::new(kind, radius)));
+        }
+        "intruders" => {
+            // players near us which are not bots
+            let entities = &global.entities[local.world()];
+            let location = local.physics.location();
+            for (_, player) in entities.players_not_in(&global.swarm) {
+                if let EntityKind::Player { uuid } = player.kind {
+                    let name = global
+                        .players
+                        .by_uuid(uuid)
+                        .map_or("?", |p| p.name.as_str());
+                    let dist = player.location.dist2(location).sqrt();
+                    msg!("{} at {} ({:.0} blocks away)", name, player.location, dist);
+                }
+            }
+        }
         "drop" => {
             local.inventory.drop_hotbar(out);
         }
@@ -247,7 +287,10 @@ pub fn process_command(
                     msg!("on ground {}", local.physics.on_ground());
                     let below_loc =
                         BlockLocation::from(local.physics.location() - Displacement::EPSILON_Y);
-                    msg!("below kind {:?}", global.blocks.get_block_kind(below_loc));
+                    msg!(
+                        "below kind {:?}",
+                        global.blocks[local.world()].get_block_kind(below_loc)
+                    );
                     msg!("inventory slots {:?}", local.inventory.hotbar());
                 }
             }
@@ -259,7 +302,10 @@ pub fn process_command(
                 let z = c.parse()?;
                 let location = BlockLocation::new(x, y, z);
 
-                msg!("The block is {:?}", global.blocks.get_block(location));
+                msg!(
+                    "The block is {:?}",
+                    global.blocks[local.world()].get_block(location)
+                );
             }
         }
         "place" => {
@@ -303,9 +349,9 @@ pub fn run_threaded(
     local: &mut LocalState,
     actions: &mut ActionState,
     global: &GlobalState,
-    end_by: Instant,
+    budget: Budget,
 ) {
     if let Some(task) = actions.task.as_mut() {
-        task.expensive(end_by, local, global);
+        task.expensive(budget, local, global);
     }
 }
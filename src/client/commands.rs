@@ -14,13 +14,31 @@
 
 use std::sync::mpsc::Receiver;
 
-use futures::StreamExt;
-use interfaces::types::{BlockLocation, BlockLocation2D};
+use futures::{SinkExt, StreamExt};
+use interfaces::types::{BlockKind, BlockLocation, BlockLocation2D};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::net::TcpListener;
+use swarm_bot_packets::types::UUID;
+use tokio::{net::TcpListener, sync::oneshot};
+use tokio_tungstenite::tungstenite::Message;
 
-use crate::error::Res;
+use crate::{
+    client::{
+        state::{
+            global::{
+                mine_alloc::MinePreference,
+                world_players::{Gamemode, Player, Property},
+                GlobalState,
+            },
+            local::LocalState,
+        },
+        tasks::{
+            attack_entity::AttackEntity, collect_items::CollectItems, lazy_stream::LazyStream,
+            mine_region::MineRegion, navigate::BlockTravelTask, Task,
+        },
+    },
+    error::Res,
+};
 
 pub struct CommandReceiver {
     pub pending: Receiver<CommandData>,
@@ -69,10 +87,92 @@ pub struct Attack {
     pub name: String,
 }
 
+/// Pick up the dropped items within `radius` blocks of each bot. Only items
+/// with id `kind` if it is given
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Collect {
+    pub kind: Option<u32>,
+    pub radius: f64,
+}
+
+/// List the players in the tab list. The reply is a JSON array of
+/// [`PlayerEntry`] sent back over the same websocket
+pub struct Players {
+    pub reply: oneshot::Sender<String>,
+}
+
+/// A player in the reply to [`Players`]
+#[derive(Serialize)]
+pub struct PlayerEntry<'a> {
+    pub uuid: String,
+    pub name: &'a str,
+    pub display_name: Option<&'a str>,
+    pub gamemode: Option<Gamemode>,
+
+    /// milliseconds
+    pub ping: i32,
+    pub properties: &'a [Property],
+
+    /// if the player is one of our bots
+    pub bot: bool,
+}
+
+impl PlayerEntry<'a> {
+    pub fn new(player: &'a Player, bot: bool) -> Self {
+        Self {
+            uuid: UUID(player.uuid).to_string(),
+            name: &player.name,
+            display_name: player.display_name.as_deref(),
+            gamemode: player.gamemode,
+            ping: player.ping,
+            properties: &player.properties,
+            bot,
+        }
+    }
+}
+
+/// What a command makes one bot do. Captures record these so a replay gives
+/// the bot the same tasks
+#[derive(Serialize, Deserialize, Debug)]
+pub enum BotCommand {
+    /// mine the region the swarm was given
+    Mine(Selection2D),
+    GoTo(BlockLocation),
+    Attack {
+        entity_id: u32,
+    },
+    Collect(Collect),
+}
+
+impl BotCommand {
+    /// Changes the global state. Done once for all bots given the command
+    pub fn apply_global(&self, global: &mut GlobalState) {
+        if let BotCommand::Mine(Selection2D { from, to }) = self {
+            global.mine.mine(*from, *to, Some(MinePreference::FromDist));
+        }
+    }
+
+    /// The task a bot does for the command
+    pub fn task(&self, local: &LocalState) -> Task {
+        match self {
+            BotCommand::Mine(_) => LazyStream::from(MineRegion).into(),
+            BotCommand::GoTo(location) => BlockTravelTask::new(*location, local).into(),
+            BotCommand::Attack { entity_id } => {
+                LazyStream::from(AttackEntity::new(*entity_id)).into()
+            }
+            BotCommand::Collect(Collect { kind, radius }) => {
+                LazyStream::from(CollectItems::new(kind.map(BlockKind), *radius)).into()
+            }
+        }
+    }
+}
+
 pub enum CommandData {
     Mine(Mine),
     GoTo(GoTo),
     Attack(Attack),
+    Collect(Collect),
+    Players(Players),
 }
 
 fn process(path: &str, value: Value) -> Option<CommandData> {
@@ -86,6 +186,7 @@ fn process(path: &str, value: Value) -> Option<CommandData> {
         "mine" => Some(CommandData::Mine(parse!())),
         "goto" => Some(CommandData::GoTo(parse!())),
         "attack" => Some(CommandData::Attack(parse!())),
+        "collect" => Some(CommandData::Collect(parse!())),
 
         path => {
             println!("invalid {}", path);
@@ -128,6 +229,21 @@ impl CommandReceiver {
                             _ => panic!("invalid path"),
                         };
 
+                        // queries are answered by the game loop
+                        if path == "players" {
+                            let (reply, rx) = oneshot::channel();
+                            tx.send(CommandData::Players(Players { reply })).unwrap();
+
+                            let text = match rx.await {
+                                Ok(text) => text,
+                                Err(_) => continue 'wloop,
+                            };
+                            if ws.send(Message::Text(text)).await.is_err() {
+                                break 'wloop;
+                            }
+                            continue 'wloop;
+                        }
+
                         let command = process(&path, v).expect("invalid command");
                         tx.send(command).unwrap();
                     }
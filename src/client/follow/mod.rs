@@ -278,7 +278,7 @@ mod tests {
                 global::GlobalState,
                 local::{inventory::PlayerInventory, LocalState},
             },
-            timing::Increment,
+            timing::{Budget, Increment},
         },
         schematic::Schematic,
     };
@@ -295,12 +295,12 @@ mod tests {
         let mut local_state = LocalState::mock();
         let mut global_state = GlobalState::init();
 
-        global_state.blocks.paste(&course);
+        global_state.blocks[local_state.world()].paste(&course);
 
         let start = BlockLocation::new(-162, 82, -357);
         let end = BlockLocation::new(-152, 80, -338);
 
-        let world = &global_state.blocks;
+        let world = &global_state.blocks[local_state.world()];
         let start_below = world.get_block(start.below()).unwrap().as_real().id();
         let end_below = world.get_block(end.below()).unwrap().as_real().id();
 
@@ -311,7 +311,7 @@ mod tests {
         let mut problem = TravelProblem::navigate_block(start, end);
 
         let increment = problem.iterate_until(
-            Instant::now() + Duration::from_secs(10),
+            Budget::Until(Instant::now() + Duration::from_secs(10)),
             &mut local_state,
             &global_state,
         );
@@ -328,9 +328,10 @@ mod tests {
         local_state.physics.teleport(start.center_bottom());
 
         while let FollowResult::InProgress = follower.follow(&mut local_state, &mut global_state) {
-            local_state
-                .physics
-                .tick(&mut global_state.blocks, &PlayerInventory::default());
+            local_state.physics.tick(
+                &mut global_state.blocks[local_state.world()],
+                &PlayerInventory::default(),
+            );
             assert!(
                 local_state.physics.location().y > 79.0,
                 "the player fell... location was {}",
@@ -356,7 +357,7 @@ mod tests {
         let start = BlockLocation::new(0, 1, 0);
         let end = BlockLocation::new(950, 1, 950);
 
-        let world = &mut global_state.blocks;
+        let world = &mut global_state.blocks[local_state.world()];
         world.set_random_floor();
 
         let mut problem = TravelProblem::navigate_block(start, end);
@@ -365,7 +366,7 @@ mod tests {
         problem.set_max_millis(u128::MAX);
 
         let increment = problem.iterate_until(
-            Instant::now() + Duration::from_secs(20),
+            Budget::Until(Instant::now() + Duration::from_secs(20)),
             &mut local_state,
             &global_state,
         );
@@ -387,9 +388,10 @@ mod tests {
         local_state.physics.teleport(start.center_bottom());
 
         while let FollowResult::InProgress = follower.follow(&mut local_state, &mut global_state) {
-            local_state
-                .physics
-                .tick(&mut global_state.blocks, &local_state.inventory);
+            local_state.physics.tick(
+                &mut global_state.blocks[local_state.world()],
+                &local_state.inventory,
+            );
             assert!(
                 local_state.physics.location().y >= 0.0,
                 "the player fell... location was {} front was {:?} left {}",
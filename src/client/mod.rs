@@ -13,12 +13,15 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod bot;
-mod commands;
+pub mod commands;
 mod follow;
 pub mod pathfind;
 pub mod physics;
 pub mod processor;
+pub mod reconnect;
+pub mod replay;
 pub mod runner;
 pub mod state;
 mod tasks;
+pub mod throttle;
 mod timing;
@@ -13,11 +13,13 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use interfaces::types::{BlockLocation, BlockState};
+use serde::Deserialize;
 use std::hash::{Hash, Hasher};
 
 use crate::{client::pathfind::incremental::Node, storage::blocks::WorldBlocks};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Costs {
     pub block_walk: f64,
     pub block_parkour: f64,
@@ -30,6 +32,24 @@ pub struct Costs {
     pub fall: f64,
 }
 
+impl Default for Costs {
+    fn default() -> Self {
+        Self {
+            block_walk: 1.0,
+            block_parkour: 1.5,
+            mine_unrelated: 20.0,
+            ascend: 1.0,
+            no_breathe_mult: 3.0,
+            fall: 1.0,
+            place_unrelated: 20.0,
+            mine_required: 1.0,
+            place_required: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct PathConfig {
     pub costs: Costs,
     pub parkour: bool,
@@ -38,17 +58,7 @@ pub struct PathConfig {
 impl Default for PathConfig {
     fn default() -> Self {
         Self {
-            costs: Costs {
-                block_walk: 1.0,
-                block_parkour: 1.5,
-                mine_unrelated: 20.0,
-                ascend: 1.0,
-                no_breathe_mult: 3.0,
-                fall: 1.0,
-                place_unrelated: 20.0,
-                mine_required: 1.0,
-                place_required: 1.0,
-            },
+            costs: Costs::default(),
             parkour: true,
         }
     }
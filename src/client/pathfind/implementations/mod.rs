@@ -13,7 +13,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use interfaces::types::{BlockLocation, BlockState};
-use std::{collections::HashMap, time::Instant};
+use std::collections::HashMap;
 
 use crate::client::{
     pathfind::{
@@ -23,7 +23,7 @@ use crate::client::{
         traits::{GoalCheck, Heuristic, Progression, Progressor},
     },
     state::{global::GlobalState, local::LocalState},
-    timing::Increment,
+    timing::{Budget, Increment},
 };
 
 pub mod novehicle;
@@ -32,7 +32,7 @@ pub trait Problem: Send + Sync {
     type Node: Node;
     fn iterate_until(
         &mut self,
-        time: Instant,
+        budget: Budget,
         local: &mut LocalState,
         global: &GlobalState,
     ) -> Increment<PathResult<<Self::Node as Node>::Record>>;
@@ -82,17 +82,17 @@ impl<H: Heuristic<MoveNode> + Send + Sync, G: GoalCheck<MoveNode> + Send + Sync>
 
     fn iterate_until(
         &mut self,
-        end_at: Instant,
-        _: &mut LocalState,
+        budget: Budget,
+        local: &mut LocalState,
         global: &GlobalState,
     ) -> Increment<PathResult<MoveRecord>> {
         let ctx = GlobalContext {
-            path_config: &global.travel_config,
-            world: &global.blocks,
+            path_config: &local.config.path,
+            world: &global.blocks[local.world()],
         };
         let progressor = GenericProgressor { ctx };
         self.a_star
-            .iterate_until(end_at, &self.heuristic, &progressor, &self.goal_checker)
+            .iterate_until(budget, &self.heuristic, &progressor, &self.goal_checker)
     }
 
     fn recalc(&mut self, context: Self::Node) {
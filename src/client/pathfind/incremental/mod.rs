@@ -24,7 +24,7 @@ use crate::client::{
         traits::{GoalCheck, Heuristic, Progression, Progressor},
         MinHeapNode,
     },
-    timing::Increment,
+    timing::{Budget, Increment},
 };
 
 /// credit baritone
@@ -217,21 +217,19 @@ impl<T: Node> AStar<T> {
 
     pub fn iterate_until(
         &mut self,
-        end_at: Instant,
+        budget: Budget,
         heuristic: &impl Heuristic<T>,
         progressor: &impl Progressor<T>,
         goal_check: &impl GoalCheck<T>,
     ) -> Increment<PathResult<T::Record>> {
         let iter_start = Instant::now();
+        let mut iterations = 0;
 
         loop {
-            let now = Instant::now();
-
-            if now >= end_at {
-                let iter_duration = now.duration_since(iter_start);
+            if budget.exhausted(iterations) {
                 let state = self.state.as_mut().unwrap();
                 let dur = &mut state.total_duration_ms;
-                *dur += iter_duration.as_millis();
+                *dur += budget.spent(iter_start).as_millis();
                 return if *dur > state.max_duration_ms {
                     println!("reached maxed duration");
                     return self.select_best();
@@ -240,6 +238,7 @@ impl<T: Node> AStar<T> {
                 };
             }
 
+            iterations += 1;
             match self.iterate(heuristic, progressor, goal_check) {
                 Increment::Finished(res) => {
                     return Increment::Finished(res);
@@ -16,14 +16,17 @@ use crate::{
     client::{
         bot::{process_command, ActionState},
         state::{
-            global::{world_players::Player, GlobalState},
+            global::{world_players::PlayerUpdate, GlobalState},
             local::{inventory::ItemStack, LocalState},
         },
         tasks::eat::EatTask,
     },
     protocol::InterfaceOut,
-    storage::{chunk::ChunkColumn, entities::EntityKind},
-    types::{Chat, Dimension, Location, LocationOrigin, PlayerMessage},
+    storage::{
+        chunk::ChunkColumn,
+        entities::{Effect, EntityKind, EntityMetadata, EquipmentSlot},
+    },
+    types::{Chat, Dimension, Displacement, Location, LocationOrigin, PlayerMessage},
 };
 use interfaces::types::{BlockLocation, BlockState, ChunkLocation};
 
@@ -34,15 +37,32 @@ pub trait InterfaceIn {
     fn on_death(&mut self);
     fn on_update_health(&mut self, health: f32, food: u8);
     fn on_dimension_change(&mut self, dimension: Dimension);
+
+    /// The client has to forget the world, i.e., on a respawn or when a proxy
+    /// moves us to another server. The chunks and entities are sent again
+    fn on_world_reset(&mut self);
+
+    /// A proxy moved us to another server. Called after the world reset
+    fn on_server_switch(&mut self);
     fn on_join(&mut self);
     fn on_move(&mut self, location: Location);
     fn on_recv_chunk(&mut self, location: ChunkLocation, column: ChunkColumn, new: bool);
+    fn on_unload_chunk(&mut self, location: ChunkLocation);
     fn on_entity_move(&mut self, id: u32, location: LocationOrigin);
     fn on_block_change(&mut self, location: BlockLocation, state: BlockState);
     fn on_entity_destroy(&mut self, id: u32);
     fn on_entity_spawn(&mut self, id: u32, location: Location, kind: EntityKind);
-    fn on_player_join(&mut self, uuid: u128, name: String);
-    fn on_player_leave(&mut self, uuid: u128);
+    fn on_entity_metadata(&mut self, id: u32, metadata: EntityMetadata);
+
+    /// An item or XP orb was picked up by any entity
+    fn on_entity_collected(&mut self, id: u32);
+    fn on_entity_equipment(&mut self, id: u32, slot: EquipmentSlot, item: Option<ItemStack>);
+
+    /// `velocity` is in blocks per tick
+    fn on_entity_velocity(&mut self, id: u32, velocity: Displacement);
+    fn on_entity_effect(&mut self, id: u32, effect: Effect);
+    fn on_entity_effect_removed(&mut self, id: u32, effect_id: u8);
+    fn on_player_list(&mut self, uuid: u128, update: PlayerUpdate);
     fn on_disconnect(&mut self, reason: &str);
     fn on_socket_close(&mut self);
 }
@@ -118,7 +138,7 @@ impl<'a, I: InterfaceOut> InterfaceIn for SimpleInterfaceIn<'a, I> {
         println!("updated health {} food is {}", health, food);
 
         // we should probably eat something
-        if food < 10 {
+        if food < self.local.config.food_threshold {
             // if we could switch to food
             if self
                 .local
@@ -131,9 +151,31 @@ impl<'a, I: InterfaceOut> InterfaceIn for SimpleInterfaceIn<'a, I> {
     }
 
     fn on_dimension_change(&mut self, dimension: Dimension) {
+        let previous = self.local.world();
+        if previous.dimension != dimension {
+            // the server does not unload the chunks and entities of the
+            // dimension we left
+            self.global.blocks[previous].unload_bot(self.local.bot_id);
+            self.global.entities[previous].remove_bot(self.local.bot_id);
+        }
         self.local.dimension = dimension;
     }
 
+    fn on_world_reset(&mut self) {
+        // other bots can still have the chunks and entities loaded
+        self.global.remove_bot(self.local.bot_id);
+
+        // paths and targets are from the old world
+        self.actions.clear();
+    }
+
+    fn on_server_switch(&mut self) {
+        // the bots still on the old server keep its chunks and entities
+        self.local.backend = self
+            .global
+            .switch_backend(self.local.bot_id, self.local.backend);
+    }
+
     fn on_join(&mut self) {
         // always start with slot 0
         self.out.change_slot(0);
@@ -145,45 +187,78 @@ impl<'a, I: InterfaceOut> InterfaceIn for SimpleInterfaceIn<'a, I> {
     }
 
     fn on_recv_chunk(&mut self, location: ChunkLocation, column: ChunkColumn, new: bool) {
+        let bot_id = self.local.bot_id;
         if new {
-            self.global.blocks.add_column(location, column);
+            self.global.blocks[self.local.world()].load_column(location, bot_id, column);
         } else {
-            self.global.blocks.modify_column(location, column);
+            self.global.blocks[self.local.world()].load_modified_column(location, bot_id, column);
         }
     }
 
+    fn on_unload_chunk(&mut self, location: ChunkLocation) {
+        self.global.blocks[self.local.world()].unload_column(location, self.local.bot_id);
+    }
+
     fn on_entity_move(&mut self, id: u32, location: LocationOrigin) {
-        self.global
-            .entities
-            .update_entity(id, self.local.bot_id, location);
+        self.global.entities[self.local.world()].update_entity(id, self.local.bot_id, location);
     }
 
     fn on_block_change(&mut self, location: BlockLocation, state: BlockState) {
-        self.global.blocks.set_block(location, state);
+        self.global.blocks[self.local.world()].set_block(location, state);
     }
 
     fn on_entity_destroy(&mut self, id: u32) {
-        self.global.entities.remove_entity(id, self.local.bot_id);
+        self.global.entities[self.local.world()].remove_entity(id, self.local.bot_id);
     }
 
     fn on_entity_spawn(&mut self, id: u32, location: Location, kind: EntityKind) {
-        self.global
-            .entities
-            .put_entity(id, self.local.bot_id, location, kind);
+        self.global.entities[self.local.world()].put_entity(id, self.local.bot_id, location, kind);
+    }
+
+    fn on_entity_metadata(&mut self, id: u32, metadata: EntityMetadata) {
+        self.global.entities[self.local.world()].update_metadata(id, self.local.bot_id, metadata);
+    }
+
+    fn on_entity_collected(&mut self, id: u32) {
+        self.global.entities[self.local.world()].collect(id);
+    }
+
+    fn on_entity_equipment(&mut self, id: u32, slot: EquipmentSlot, item: Option<ItemStack>) {
+        self.global.entities[self.local.world()].update_equipment(
+            id,
+            self.local.bot_id,
+            slot,
+            item,
+        );
     }
 
-    fn on_player_join(&mut self, uuid: u128, name: String) {
-        self.global.players.add(Player { name, uuid });
+    fn on_entity_velocity(&mut self, id: u32, velocity: Displacement) {
+        self.global.entities[self.local.world()].update_velocity(id, self.local.bot_id, velocity);
     }
 
-    fn on_player_leave(&mut self, uuid: u128) {
-        self.global.players.remove(uuid);
+    fn on_entity_effect(&mut self, id: u32, effect: Effect) {
+        self.global.entities[self.local.world()].add_effect(id, self.local.bot_id, effect);
+    }
+
+    fn on_entity_effect_removed(&mut self, id: u32, effect_id: u8) {
+        self.global.entities[self.local.world()].remove_effect(id, self.local.bot_id, effect_id);
+    }
+
+    fn on_player_list(&mut self, uuid: u128, update: PlayerUpdate) {
+        self.global.players.update(uuid, update);
     }
 
     fn on_disconnect(&mut self, reason: &str) {
         println!("disconnecting because {}", reason);
         self.local.disconnected = true;
+        self.local.disconnect_reason = Some(reason.to_string());
     }
 
-    fn on_socket_close(&mut self) {}
+    fn on_socket_close(&mut self) {
+        if !self.local.disconnected {
+            println!("{} lost connection", self.local.info.username);
+            self.local.disconnected = true;
+            self.local.disconnect_reason = Some("socket closed".to_string());
+        }
+    }
 }
@@ -0,0 +1,148 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+use crate::bootstrap::{storage::BotData, Address, Connection};
+
+/// Disconnect reasons (lowercase) after which we never reconnect
+const PERMANENT: &[&str] = &[
+    "banned",
+    "blacklisted",
+    "not whitelisted",
+    "not white-listed",
+    "logged in from another location",
+];
+
+/// How disconnected bots are logged in again
+#[derive(Copy, Clone, Debug)]
+pub struct ReconnectPolicy {
+    /// reconnects in a row before giving up. 0 disables reconnecting
+    pub max_retries: u32,
+
+    /// the delay before the first reconnect. Doubled every attempt
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+
+    /// a bot connected for this long starts over at the first attempt
+    pub stable_after: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(300),
+            stable_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Whether a disconnect for `reason` will happen again no matter how long
+    /// we wait, i.e., a ban. Everything else (a restart, "logged in too fast")
+    /// is transient.
+    pub fn permanent(reason: &str) -> bool {
+        let reason = reason.to_lowercase();
+        PERMANENT.iter().any(|permanent| reason.contains(permanent))
+    }
+
+    /// How long to wait before reconnect `attempt` (starting at 0). `None`
+    /// means we give up.
+    pub fn delay(&self, reason: &str, attempt: u32) -> Option<Duration> {
+        if Self::permanent(reason) || attempt >= self.max_retries {
+            return None;
+        }
+
+        let factor = 1_u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        let delay = self.base_delay.saturating_mul(factor);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// What is needed to log a bot in again
+pub struct Relogin {
+    pub data: BotData,
+    pub address: Address,
+
+    /// reconnects in a row
+    pub attempts: u32,
+
+    /// when the bot last finished logging in
+    pub logged_in: Option<Instant>,
+}
+
+impl Relogin {
+    pub fn new(connection: &Connection) -> Relogin {
+        Relogin {
+            data: BotData {
+                user: connection.user.clone(),
+                proxy: connection.proxy.clone(),
+                mojang: connection.mojang.clone(),
+            },
+            address: connection.address.clone(),
+            attempts: 0,
+            logged_in: None,
+        }
+    }
+
+    /// Called when the bot disconnects. Resets the attempts if it was
+    /// connected long enough.
+    pub fn disconnected(&mut self, policy: &ReconnectPolicy) {
+        let stable = self
+            .logged_in
+            .take()
+            .map_or(false, |at| at.elapsed() >= policy.stable_after);
+
+        if stable {
+            self.attempts = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::client::reconnect::ReconnectPolicy;
+
+    #[test]
+    fn test_delay() {
+        let policy = ReconnectPolicy {
+            max_retries: 4,
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(30),
+            stable_after: Duration::from_secs(60),
+        };
+
+        let throttled = r#"{"text":"Connection throttled! Please wait before reconnecting."}"#;
+        assert_eq!(policy.delay(throttled, 0), Some(Duration::from_secs(5)));
+        assert_eq!(
+            policy.delay("Server closed", 1),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            policy.delay("Server closed", 3),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(policy.delay("Server closed", 4), None);
+
+        assert_eq!(policy.delay("You are banned from this server!", 0), None);
+        assert_eq!(
+            policy.delay("You are not white-listed on this server!", 0),
+            None
+        );
+    }
+}
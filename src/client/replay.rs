@@ -0,0 +1,103 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runs a bot offline on a capture of its packets
+
+use std::{default::default, path::Path};
+
+use crate::{
+    bootstrap::config::BotConfig,
+    client::{
+        bot::{run_threaded, Bot},
+        processor::SimpleInterfaceIn,
+        state::{global::GlobalState, local::LocalState},
+        timing::Budget,
+    },
+    error::{err, Res},
+    protocol::{
+        capture::{Replay, ReplayQueue},
+        v340, v754, EventQueue, Minecraft,
+    },
+};
+
+/// The pathfinding iterations of each tick. Fixed instead of the time the
+/// runner uses so every replay does the same work
+const TICK_ITERATIONS: u32 = 5_000;
+
+/// Replays the capture at `path` and prints the state of the bot at the end
+pub fn run(path: &Path) -> Res {
+    let replay = Replay::read(path)?;
+
+    match replay.protocol {
+        340 => run_protocol::<v340::Protocol>(replay),
+        754 => run_protocol::<v754::Protocol>(replay),
+        protocol => {
+            let msg = format!("protocol {} is not supported", protocol);
+            Err(err(&msg))
+        }
+    }
+}
+
+fn run_protocol<T: Minecraft>(replay: Replay) -> Res {
+    let Replay {
+        protocol,
+        info,
+        records,
+    } = replay;
+    let (queue, out) = ReplayQueue::<T>::new(protocol, records)?;
+
+    let mut bot = Bot {
+        state: LocalState::new(0, info, BotConfig::default()),
+        actions: default(),
+        queue,
+        out,
+    };
+
+    let mut global = GlobalState::init();
+
+    while !bot.queue.finished() && !bot.state.disconnected {
+        tick(&mut bot, &mut global);
+    }
+
+    let state = &bot.state;
+    println!("replayed {} ticks", state.ticks);
+    println!("location: {}", state.physics.location());
+    println!("health: {} food: {}", state.health, state.food);
+    println!("dimension: {}", state.dimension);
+    if let Some(reason) = &state.disconnect_reason {
+        println!("disconnected: {}", reason);
+    }
+
+    Ok(())
+}
+
+/// One iteration of the game loop of the runner for a single bot
+fn tick<T: Minecraft>(bot: &mut Bot<ReplayQueue<T>, T::Interface>, global: &mut GlobalState) {
+    // the runner processes commands before the packets
+    for command in bot.queue.commands() {
+        command.apply_global(global);
+        bot.command(&command);
+    }
+
+    let mut processor =
+        SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, global, &mut bot.out);
+    bot.queue.flush(&mut processor);
+
+    bot.run_sync(global);
+
+    let (state, actions) = (&mut bot.state, &mut bot.actions);
+    let global = &*global;
+    let budget = Budget::Iterations(TICK_ITERATIONS);
+    rayon::scope(|s| run_threaded(s, state, actions, global, budget));
+}
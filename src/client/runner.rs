@@ -14,7 +14,9 @@
 
 use std::{
     cell::RefCell,
+    collections::HashMap,
     default::default,
+    path::PathBuf,
     rc::Rc,
     sync::Arc,
     time::{Duration, Instant},
@@ -23,27 +25,42 @@ use std::{
 use tokio::sync::Notify;
 
 use crate::{
-    bootstrap::Connection,
+    bootstrap::{
+        config::{BotConfig, BotConfigs},
+        login_stats::LoginReport,
+        proxy_pool::Proxies,
+        storage::Revalidator,
+        Connection,
+    },
     client::{
         bot::{run_threaded, ActionState, Bot},
-        commands::{CommandData, CommandReceiver, Selection2D},
+        commands::{BotCommand, CommandData, CommandReceiver, PlayerEntry},
         processor::SimpleInterfaceIn,
+        reconnect::{ReconnectPolicy, Relogin},
         state::{
-            global::{mine_alloc::MinePreference, GlobalState},
+            global::{mine_alloc::MineAlloc, GlobalState},
             local::LocalState,
         },
-        tasks::{
-            attack_entity::AttackEntity, lazy_stream::LazyStream, mine_region::MineRegion,
-            navigate::BlockTravelTask,
-        },
+        throttle::{LoginThrottle, Throttle},
+        timing::Budget,
     },
 };
 
 use crate::{
-    error::{Res, ResBox},
-    protocol::{EventQueue, Login, Minecraft},
+    error::{err, Res, ResBox},
+    protocol::{capture::Capture, EventQueue, Login, Minecraft},
+    storage::{
+        blocks::{MemoryCap, WorldBlocks},
+        dimensions::Worlds,
+    },
 };
 
+/// The minimum time between printing login stats
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a login can take before the bot is logged in again
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 struct SyncGlobal(*const GlobalState);
 
 impl SyncGlobal {
@@ -62,12 +79,141 @@ unsafe impl Sync for SyncLocal {}
 
 unsafe impl Send for SyncLocal {}
 
-pub type Logins<T> = Rc<RefCell<Vec<Login<<T as Minecraft>::Queue, <T as Minecraft>::Interface>>>>;
+pub type Logins<T> = Rc<
+    RefCell<
+        Vec<(
+            Login<<T as Minecraft>::Queue, <T as Minecraft>::Interface>,
+            Relogin,
+        )>,
+    >,
+>;
+
+/// Logs bots in and logs them in again when they disconnect
+struct LoginPipeline<T: Minecraft> {
+    /// logins that are about to be established
+    logins: Logins<T>,
+    proxies: Proxies,
+    reconnect: ReconnectPolicy,
+    stats: LoginReport,
+    throttle: Throttle,
+    revalidator: Option<Revalidator>,
+}
+
+impl<T: Minecraft> Clone for LoginPipeline<T> {
+    fn clone(&self) -> Self {
+        Self {
+            logins: self.logins.clone(),
+            proxies: self.proxies.clone(),
+            reconnect: self.reconnect,
+            stats: self.stats.clone(),
+            throttle: self.throttle.clone(),
+            revalidator: self.revalidator.clone(),
+        }
+    }
+}
+
+impl<T: Minecraft + 'static> LoginPipeline<T> {
+    /// Logs in a connection. The bot is re-queued if this fails. The caller
+    /// must have acquired a login slot with [`LoginThrottle::acquire`]
+    async fn login(&self, connection: Connection, mut relogin: Relogin) {
+        println!("Starting login of {}", connection.user.username);
+        let username = connection.user.username.clone();
+        let proxy = connection.proxy.clone();
+
+        // a server which never answers would otherwise hold the slot forever
+        let res = tokio::time::timeout(LOGIN_TIMEOUT, T::login(connection))
+            .await
+            .unwrap_or_else(|_| Err(err("timed out logging in")));
+        self.throttle.borrow_mut().finish();
+
+        match res {
+            Ok(login) => {
+                println!("Finished logging in {}", username);
+                relogin.logged_in = Some(Instant::now());
+                self.stats.borrow_mut().success();
+                self.throttle.borrow_mut().success();
+                self.logins.borrow_mut().push((login, relogin));
+            }
+            Err(err) => {
+                println!("Error logging in {} -- {}", username, err);
+                self.stats.borrow_mut().failure(&err);
+                if let Some(proxy) = &proxy {
+                    self.proxies.borrow_mut().release(proxy);
+                }
+
+                let reason = err.to_string();
+                self.throttle.borrow_mut().disconnected(&reason);
+                self.requeue(relogin, reason);
+            }
+        }
+    }
+
+    /// Logs the bot in again after a backoff unless `reason` is permanent or
+    /// it has been retried too often
+    fn requeue(&self, mut relogin: Relogin, reason: String) {
+        let username = relogin.data.user.username.clone();
+
+        let delay = match self.reconnect.delay(&reason, relogin.attempts) {
+            Some(delay) => delay,
+            None => {
+                println!(
+                    "not reconnecting {} after {} attempts .. {}",
+                    username, relogin.attempts, reason
+                );
+                return;
+            }
+        };
+
+        relogin.attempts += 1;
+        self.stats.borrow_mut().retry();
+        println!(
+            "reconnecting {} in {}ms (attempt {})",
+            username,
+            delay.as_millis(),
+            relogin.attempts
+        );
+
+        let pipeline = self.clone();
+        tokio::task::spawn_local(async move {
+            tokio::time::sleep(delay).await;
+
+            // the tokens could have expired while the bot was connected
+            if let Some(revalidator) = &pipeline.revalidator {
+                match revalidator.revalidate(&relogin.data.user).await {
+                    Ok(user) => relogin.data.user = user,
+                    Err(err) => {
+                        pipeline.stats.borrow_mut().failure(&err);
+                        pipeline.requeue(relogin, err.to_string());
+                        return;
+                    }
+                }
+            }
+
+            LoginThrottle::acquire(&pipeline.throttle).await;
+
+            let data = relogin.data.clone();
+            let address = relogin.address.clone();
+            match Connection::establish(data, address, &pipeline.proxies).await {
+                Ok(connection) => pipeline.login(connection, relogin).await,
+                Err(err) => {
+                    pipeline.throttle.borrow_mut().finish();
+                    pipeline.stats.borrow_mut().failure(&err);
+                    pipeline.requeue(relogin, err.to_string());
+                }
+            }
+        });
+    }
+}
 
 /// Runs the game loop and holds all bots.
 pub struct Runner<T: Minecraft> {
-    /// logins that are about to be established
-    pending_logins: Logins<T>,
+    pipeline: LoginPipeline<T>,
+
+    /// how to log in each bot again, by bot id
+    relogins: HashMap<u32, Relogin>,
+
+    /// the config of each bot
+    bot_configs: BotConfigs,
 
     /// the global state of the program containing chunks and global config
     global_state: GlobalState,
@@ -79,13 +225,36 @@ pub struct Runner<T: Minecraft> {
 
     /// An id counter that increases for each bot. Used as a unique identifier.
     id_on: u32,
+
+    /// when the login stats were last printed
+    last_report: Instant,
+
+    /// the directory the packets of each bot are captured to
+    capture: Option<PathBuf>,
 }
 
 /// Runner launch options
 pub struct RunnerOptions {
-    /// The amount of milliseconds to wait between logging in successive users
-    pub delay_ms: u64,
+    /// How fast users are logged in. Shared with [`Connection::stream`]
+    pub throttle: Throttle,
     pub ws_port: u16,
+    pub proxies: Proxies,
+    pub reconnect: ReconnectPolicy,
+    pub stats: LoginReport,
+
+    /// checks the tokens of bots before they reconnect. `None` for offline
+    /// bots
+    pub revalidator: Option<Revalidator>,
+    pub bot_configs: BotConfigs,
+
+    /// the radius of the region each bot mines
+    pub mine_region_radius: i32,
+
+    /// capture the packets of each bot to a file in this directory
+    pub capture: Option<PathBuf>,
+
+    /// the memory the chunks of each dimension can use
+    pub chunk_memory: Option<MemoryCap>,
 }
 
 impl<T: Minecraft + 'static> Runner<T> {
@@ -106,51 +275,65 @@ impl<T: Minecraft + 'static> Runner<T> {
         opts: RunnerOptions,
     ) -> Res<Runner<T>> {
         let RunnerOptions {
-            delay_ms: delay_millis,
+            throttle,
             ws_port,
+            proxies,
+            reconnect,
+            stats,
+            revalidator,
+            bot_configs,
+            mine_region_radius,
+            capture,
+            chunk_memory,
         } = opts;
 
+        if let Some(dir) = &capture {
+            std::fs::create_dir_all(dir)?;
+        }
+
         let commands = CommandReceiver::init(ws_port).await?;
 
-        let pending_logins = Rc::new(RefCell::new(Vec::new()));
+        let pipeline = LoginPipeline::<T> {
+            logins: Rc::new(RefCell::new(Vec::new())),
+            proxies,
+            reconnect,
+            stats,
+            throttle,
+            revalidator,
+        };
 
         {
-            let pending_logins = pending_logins.clone();
+            let pipeline = pipeline.clone();
 
             // login task for all users
             tokio::task::spawn_local(async move {
+                // each connection already holds a login slot
                 while let Some(connection) = connections.recv().await {
-                    let logins = pending_logins.clone();
+                    let pipeline = pipeline.clone();
 
                     // login task for an individual user
                     tokio::task::spawn_local(async move {
-                        println!("Starting login of {}", connection.user.username);
-                        let username = connection.user.username.clone();
-                        let login = match T::login(connection).await {
-                            Ok(res) => {
-                                println!("Finished logging in {}", username);
-                                res
-                            }
-                            Err(err) => {
-                                println!("Error logging in {} -- {}", username, err);
-                                return;
-                            }
-                        };
-                        logins.borrow_mut().push(login);
+                        let relogin = Relogin::new(&connection);
+                        pipeline.login(connection, relogin).await;
                     });
-
-                    // if we want a delay between logging in
-                    tokio::time::sleep(Duration::from_millis(delay_millis)).await;
                 }
             });
         }
 
         Ok(Runner {
-            pending_logins,
-            global_state: GlobalState::init(),
+            pipeline,
+            relogins: HashMap::new(),
+            bot_configs,
+            global_state: GlobalState {
+                mine: MineAlloc::new(mine_region_radius),
+                blocks: Worlds::new(move || WorldBlocks::with_cap(chunk_memory)),
+                ..GlobalState::init()
+            },
             command_receiver: commands,
             bots: Vec::new(),
             id_on: 0,
+            last_report: Instant::now(),
+            capture,
         })
     }
 
@@ -177,20 +360,70 @@ impl<T: Minecraft + 'static> Runner<T> {
 
     async fn game_iter(&mut self, end_by: Instant) {
         let old_count = self.bots.len();
-        // first step: removing disconnected clients
+        // first step: removing disconnected clients and queueing them to reconnect
         {
-            self.bots.retain(|client| !client.state.disconnected);
+            let (disconnected, connected) = self
+                .bots
+                .drain(..)
+                .partition(|client| client.state.disconnected);
+
+            self.bots = connected;
+
+            for bot in disconnected {
+                self.global_state.swarm.remove(&bot.state.info.uuid.0);
+                self.global_state.remove_bot(bot.state.bot_id);
+                self.global_state
+                    .leave_backend(bot.state.bot_id, bot.state.backend);
+
+                if let Some(mut relogin) = self.relogins.remove(&bot.state.bot_id) {
+                    if let Some(proxy) = &relogin.data.proxy {
+                        self.pipeline.proxies.borrow_mut().release(proxy);
+                    }
+                    relogin.disconnected(&self.pipeline.reconnect);
+                    let reason = bot.state.disconnect_reason.unwrap_or_default();
+                    self.pipeline.throttle.borrow_mut().disconnected(&reason);
+                    self.pipeline.requeue(relogin, reason);
+                }
+            }
         }
 
         // second step: turning pending logins into clients
         {
-            let mut logins = self.pending_logins.borrow_mut();
+            let mut logins = self.pipeline.logins.borrow_mut();
+
+            for (login, relogin) in logins.drain(..) {
+                let Login {
+                    mut queue,
+                    out,
+                    info,
+                } = login;
+
+                let user = &relogin.data.user;
+                let config = self
+                    .bot_configs
+                    .resolve(&user.email, &user.username)
+                    .unwrap_or_else(|e| {
+                        println!("invalid config for {}: {}", user.username, e);
+                        BotConfig::default()
+                    });
+
+                if let Some(dir) = &self.capture {
+                    let path = dir.join(format!("{}-{}.cap", info.username, self.id_on));
+                    match Capture::create(&path, T::PROTOCOL, &info) {
+                        Ok(capture) => queue.capture(capture),
+                        Err(e) => println!("could not capture {}: {}", info.username, e),
+                    }
+                }
 
-            for login in logins.drain(..) {
-                let Login { queue, out, info } = login;
+                self.relogins.insert(self.id_on, relogin);
+
+                self.global_state.swarm.insert(info.uuid.0);
+
+                let state = LocalState::new(self.id_on, info, config);
+                self.global_state.enter_backend(state.bot_id, state.backend);
 
                 let client = Bot {
-                    state: LocalState::new(self.id_on, info),
+                    state,
                     actions: default(),
                     queue,
                     out,
@@ -207,6 +440,15 @@ impl<T: Minecraft + 'static> Runner<T> {
             println!("{} clients", new_count);
         }
 
+        // log login results if they have changed (at most every few seconds)
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            let mut stats = self.pipeline.stats.borrow_mut();
+            if stats.take_changed() {
+                println!("{}", stats);
+                self.last_report = Instant::now();
+            }
+        }
+
         // process pending commands (from forge mod)
         while let Ok(command) = self.command_receiver.pending.try_recv() {
             if let Err(err) = self.process_command(command) {
@@ -262,7 +504,13 @@ impl<T: Minecraft + 'static> Runner<T> {
                         let (state, actions) = unsafe { (&mut *state, &mut *actions) };
 
                         s.spawn(move |inner_scope| {
-                            run_threaded(inner_scope, state, actions, global_state, end_by);
+                            run_threaded(
+                                inner_scope,
+                                state,
+                                actions,
+                                global_state,
+                                Budget::Until(end_by),
+                            );
                         });
                     }
                 });
@@ -283,38 +531,258 @@ impl<T: Minecraft + 'static> Runner<T> {
 
         match command {
             CommandData::Mine(mine) => {
-                let Selection2D { from, to } = mine.sel.normalize();
-                global.mine.mine(from, to, Some(MinePreference::FromDist));
+                let command = BotCommand::Mine(mine.sel.normalize());
+                command.apply_global(global);
 
                 for bot in bots {
-                    bot.actions.schedule(LazyStream::from(MineRegion))
+                    bot.command(&command);
                 }
             }
             CommandData::GoTo(goto) => {
+                let command = BotCommand::GoTo(goto.location);
                 for bot in bots {
-                    bot.actions
-                        .schedule(BlockTravelTask::new(goto.location, &bot.state));
+                    bot.command(&command);
                 }
             }
             CommandData::Attack(attack) => {
-                let player = self
-                    .global_state
+                let player = global
                     .players
                     .by_name(&attack.name)
                     .ok_or("player does not exist")?;
-                let entity_id = self
-                    .global_state
-                    .entities
-                    .by_player_uuid(player.uuid)
-                    .ok_or("could not find entity id for player")?;
 
+                // entity ids are only the same within a world
+                let mut found = false;
+                for bot in bots.iter_mut() {
+                    let world = bot.state.world();
+                    if let Some(entity_id) = global.entities[world].by_player_uuid(player.uuid) {
+                        bot.command(&BotCommand::Attack { entity_id });
+                        found = true;
+                    }
+                }
+
+                if !found {
+                    return Err("could not find entity id for player".into());
+                }
+            }
+            CommandData::Collect(collect) => {
+                let command = BotCommand::Collect(collect);
                 for bot in bots {
-                    let task = LazyStream::from(AttackEntity::new(entity_id));
-                    bot.actions.schedule(task)
+                    bot.command(&command);
                 }
             }
+            CommandData::Players(players) => {
+                let entries: Vec<_> = global
+                    .players
+                    .iter()
+                    .map(|player| PlayerEntry::new(player, global.swarm.contains(&player.uuid)))
+                    .collect();
+                let json = serde_json::to_string(&entries)?;
+
+                // the websocket might have closed already
+                let _ = players.reply.send(json);
+            }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        default::default,
+        time::{Duration, Instant},
+    };
+
+    use interfaces::types::{BlockLocation, BlockState};
+    use tokio::{runtime::Runtime, task::LocalSet};
+
+    use crate::{
+        bootstrap::{
+            login_stats::LoginStats,
+            offline::offline_user,
+            proxy_pool::{ProxyLimits, ProxyPool},
+            storage::BotData,
+            Connection,
+        },
+        client::{
+            commands::{CommandData, GoTo},
+            reconnect::ReconnectPolicy,
+            runner::{Runner, RunnerOptions},
+            throttle::{LoginThrottle, ThrottleOptions},
+            timing::TICK,
+        },
+        protocol::{
+            mock::{MockOptions, MockServer},
+            v340, Minecraft,
+        },
+        storage::blocks::WorldBlocks,
+    };
+
+    const BOTS: [&str; 2] = ["Bot0", "Bot1"];
+
+    /// Runs the game loop until `done` or a few seconds pass
+    async fn run_until<T: Minecraft + 'static>(
+        runner: &mut Runner<T>,
+        mut done: impl FnMut(&Runner<T>) -> bool,
+    ) -> bool {
+        for _ in 0..200 {
+            runner.game_iter(Instant::now() + TICK).await;
+            if done(runner) {
+                return true;
+            }
+            tokio::time::sleep(TICK).await;
+        }
+        false
+    }
+
+    /// Logs in the bots to `server` and runs the game loop until they are in
+    /// the game
+    async fn join(server: &MockServer) -> Runner<v340::Protocol> {
+        let proxies = ProxyPool::new(Vec::new(), ProxyLimits::default()).shared();
+        let stats = LoginStats::shared();
+        let throttle = LoginThrottle::new(ThrottleOptions {
+            initial_delay: Duration::from_millis(10),
+            min_delay: Duration::from_millis(10),
+            ..default()
+        })
+        .shared();
+
+        let (users, bot_receiver) = tokio::sync::mpsc::channel(BOTS.len());
+        for name in BOTS {
+            let bot = BotData {
+                user: offline_user(name.to_string()),
+                proxy: None,
+                mojang: server.mojang(),
+            };
+            users.send(bot).await.unwrap();
+        }
+        drop(users);
+
+        let connections = Connection::stream(
+            server.address.clone(),
+            bot_receiver,
+            proxies.clone(),
+            stats.clone(),
+            throttle.clone(),
+        );
+
+        let options = RunnerOptions {
+            throttle,
+            ws_port: 0,
+            proxies,
+            reconnect: ReconnectPolicy::default(),
+            stats,
+            revalidator: None,
+            bot_configs: default(),
+            mine_region_radius: 16,
+            capture: None,
+            chunk_memory: None,
+        };
+
+        let mut runner = Runner::<v340::Protocol>::init(connections, options)
+            .await
+            .unwrap();
+
+        // the bots send their location every tick once they are in the game
+        let joined = run_until(&mut runner, |runner| {
+            runner.bots.len() == BOTS.len()
+                && BOTS.iter().all(|name| server.location(name).is_some())
+        })
+        .await;
+        assert!(joined);
+
+        runner
+    }
+
+    #[test]
+    fn test_runner() {
+        let rt = Runtime::new().unwrap();
+        LocalSet::new().block_on(&rt, async move {
+            let server = MockServer::start(WorldBlocks::flat(), MockOptions::default())
+                .await
+                .unwrap();
+
+            let mut runner = join(&server).await;
+
+            let goal = BlockLocation::new(5, 1, 5);
+            let command = CommandData::GoTo(GoTo { location: goal });
+            runner.process_command(command).unwrap();
+
+            let moved = run_until(&mut runner, |_| {
+                BOTS.iter().all(|name| {
+                    server
+                        .location(name)
+                        .map_or(false, |location| location.dist2(goal.center_bottom()) < 0.5)
+                })
+            })
+            .await;
+            assert!(moved);
+        });
+    }
+
+    #[test]
+    fn test_server_switch() {
+        let rt = Runtime::new().unwrap();
+        LocalSet::new().block_on(&rt, async move {
+            let server = MockServer::start(WorldBlocks::flat(), MockOptions::default())
+                .await
+                .unwrap();
+            let mut runner = join(&server).await;
+
+            let origin = BlockLocation::new(0, 0, 0);
+            let block = |runner: &Runner<v340::Protocol>, name: &str, location| {
+                let bot = runner
+                    .bots
+                    .iter()
+                    .find(|bot| bot.state.info.username == name)
+                    .unwrap();
+                let block = runner.global_state.blocks[bot.state.world()].get_block(location)?;
+                Some(block.as_real())
+            };
+
+            let spawned = run_until(&mut runner, |runner| {
+                BOTS.iter()
+                    .all(|name| block(runner, name, origin) == Some(BlockState::STONE))
+            })
+            .await;
+            assert!(spawned);
+
+            // only Bot1 moves to a server which only has a block far away
+            let mut other = WorldBlocks::default();
+            let far = BlockLocation::new(1000, 0, 1000);
+            other.set_block(far, BlockState::STONE);
+            server.switch("Bot1", &other);
+
+            let switched = run_until(&mut runner, |runner| {
+                block(runner, "Bot1", far) == Some(BlockState::STONE)
+            })
+            .await;
+            assert!(switched);
+
+            assert_eq!(block(&runner, "Bot1", origin), None);
+            assert_eq!(block(&runner, "Bot0", origin), Some(BlockState::STONE));
+            assert_eq!(block(&runner, "Bot0", far), None);
+
+            // nobody is left on the server Bot1 moves away from
+            let backend = |runner: &Runner<v340::Protocol>, name: &str| {
+                let bot = runner
+                    .bots
+                    .iter()
+                    .find(|bot| bot.state.info.username == name);
+                bot.unwrap().state.backend
+            };
+            let left = backend(&runner, "Bot1");
+            server.switch("Bot1", &other);
+
+            let switched = run_until(&mut runner, |runner| backend(runner, "Bot1") != left).await;
+            assert!(switched);
+            assert!(runner
+                .global_state
+                .blocks
+                .iter()
+                .all(|(world, _)| world.backend != left));
+            assert_eq!(block(&runner, "Bot0", origin), Some(BlockState::STONE));
+        });
+    }
+}
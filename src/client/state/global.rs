@@ -13,26 +13,35 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-    client::{
-        pathfind::context::PathConfig,
-        state::global::{mine_alloc::MineAlloc, world_players::WorldPlayers},
-    },
-    storage::{blocks::WorldBlocks, entities::WorldEntities},
+    client::state::global::{mine_alloc::MineAlloc, world_players::WorldPlayers},
+    storage::{blocks::WorldBlocks, dimensions::Worlds, entities::WorldEntities},
 };
-use interfaces::types::block_data::BlockData;
+use interfaces::types::{block_data::BlockData, entity_types::EntityTypes};
+use std::collections::{HashMap, HashSet};
 
 pub mod mine_alloc;
 pub mod world_players;
 
 #[derive(Default)]
 pub struct GlobalState {
-    pub blocks: WorldBlocks,
+    /// index with the world of the bot
+    pub blocks: Worlds<WorldBlocks>,
     pub mine: MineAlloc,
     pub block_data: BlockData,
-    pub entities: WorldEntities,
+    pub entities: Worlds<WorldEntities>,
+    pub entity_types: EntityTypes,
     pub players: WorldPlayers,
+
+    /// the UUIDs of the bots which are logged in
+    pub swarm: HashSet<u128>,
     pub ticks: usize,
-    pub travel_config: PathConfig,
+
+    /// the last backend given out. Each bot a proxy moves gets a new one
+    pub last_backend: u32,
+
+    /// the bots on each backend. The worlds of a backend are dropped once the
+    /// last bot leaves it
+    pub backend_bots: HashMap<u32, HashSet<u32>>,
 }
 
 impl GlobalState {
@@ -40,6 +49,48 @@ impl GlobalState {
         GlobalState::default()
     }
 
+    /// Forgets the chunks and entities `bot_id` has loaded in every world,
+    /// i.e., when it disconnects. Other bots take over the entities they
+    /// also see
+    pub fn remove_bot(&mut self, bot_id: u32) {
+        for (_, blocks) in self.blocks.iter_mut() {
+            blocks.unload_bot(bot_id);
+        }
+        for (_, entities) in self.entities.iter_mut() {
+            entities.remove_bot(bot_id);
+        }
+    }
+
+    /// Moves bot `bot_id` from `backend` to a backend no bot has used so far
+    pub fn switch_backend(&mut self, bot_id: u32, backend: u32) -> u32 {
+        self.last_backend += 1;
+        let new = self.last_backend;
+        self.enter_backend(bot_id, new);
+        self.leave_backend(bot_id, backend);
+        new
+    }
+
+    pub fn enter_backend(&mut self, bot_id: u32, backend: u32) {
+        self.backend_bots.entry(backend).or_default().insert(bot_id);
+    }
+
+    /// Bot `bot_id` left `backend`. Its worlds are dropped if no bot is on it
+    pub fn leave_backend(&mut self, bot_id: u32, backend: u32) {
+        let empty = match self.backend_bots.get_mut(&backend) {
+            Some(bots) => {
+                bots.remove(&bot_id);
+                bots.is_empty()
+            }
+            None => true,
+        };
+
+        if empty {
+            self.backend_bots.remove(&backend);
+            self.blocks.remove(backend);
+            self.entities.remove(backend);
+        }
+    }
+
     /// # Goal
     /// we want to assign regions to explore for each bot
     /// we want to explore in rings
@@ -120,3 +171,38 @@ impl GlobalState {
         // let mut r = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use interfaces::types::Dimension;
+
+    use crate::{
+        client::state::global::GlobalState,
+        storage::{dimensions::World, entities::EntityKind},
+        types::Location,
+    };
+
+    #[test]
+    fn test_remove_bot() {
+        let mut global = GlobalState::init();
+        let world = World {
+            backend: 0,
+            dimension: Dimension::Overworld,
+        };
+
+        let entities = &mut global.entities[world];
+        entities.put_entity(1, 0, Location::default(), EntityKind::Normal);
+        entities.put_entity(1, 1, Location::default(), EntityKind::Normal);
+        entities.put_entity(2, 0, Location::default(), EntityKind::Normal);
+
+        global.remove_bot(0);
+
+        // bot 1 still sees entity 1 and applies its updates now
+        let entities = &mut global.entities[world];
+        let moved = Location::new(10.0, 0.0, 0.0);
+        entities.update_entity(1, 1, moved.into());
+        assert_eq!(entities.by_id(1).unwrap().owner, Some(1));
+        assert_eq!(entities.by_id(1).unwrap().location, moved);
+        assert!(entities.by_id(2).is_none());
+    }
+}
@@ -23,9 +23,18 @@ use rayon::prelude::ParallelSliceMut;
 struct MineRegion(BlockLocation2D);
 
 /// Allocates mine regions to bots
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct MineAlloc {
     regions: VecDeque<MineRegion>,
+
+    /// the radius of a region. A region is `2r + 1` blocks wide
+    region_r: i32,
+}
+
+impl Default for MineAlloc {
+    fn default() -> Self {
+        Self::new(Self::REGION_R)
+    }
 }
 
 pub enum MinePreference {
@@ -36,8 +45,23 @@ pub enum MinePreference {
 pub type Locations = impl Iterator<Item = BlockLocation>;
 
 impl MineAlloc {
+    /// the default region radius
     pub const REGION_R: i32 = 3;
-    pub const REGION_WIDTH: i32 = Self::REGION_R * 2 + 1;
+
+    pub fn new(region_r: i32) -> Self {
+        Self {
+            regions: VecDeque::new(),
+            region_r,
+        }
+    }
+
+    pub fn region_r(&self) -> i32 {
+        self.region_r
+    }
+
+    pub fn region_width(&self) -> i32 {
+        self.region_r * 2 + 1
+    }
 
     pub fn cancel(&mut self) {
         self.regions.clear();
@@ -45,7 +69,8 @@ impl MineAlloc {
 
     pub fn obtain_region(&mut self) -> Option<BlockLocation2D> {
         let BlockLocation2D { x, z } = self.regions.pop_front()?.0;
-        let centered = BlockLocation2D::new(x + Self::REGION_WIDTH / 2, z + Self::REGION_WIDTH / 2);
+        let half = self.region_width() / 2;
+        let centered = BlockLocation2D::new(x + half, z + half);
         Some(centered)
     }
 
@@ -56,13 +81,13 @@ impl MineAlloc {
             .map(move |((y, z), x)| BlockLocation::new(center.x + x, y as i16, center.z + z))
     }
 
-    pub fn locations(center: BlockLocation2D) -> Locations {
-        Self::locations_rad(center, Self::REGION_R)
+    pub fn locations(&self, center: BlockLocation2D) -> Locations {
+        Self::locations_rad(center, self.region_r)
     }
 
     // locations plus 1 block extra
-    pub fn locations_extra(center: BlockLocation2D) -> Locations {
-        Self::locations_rad(center, Self::REGION_R + 1)
+    pub fn locations_extra(&self, center: BlockLocation2D) -> Locations {
+        Self::locations_rad(center, self.region_r + 1)
     }
 
     pub fn mine(
@@ -80,9 +105,10 @@ impl MineAlloc {
         }
 
         let mut vec = Vec::new();
+        let width = self.region_width() as usize;
 
-        for x in (from.x..=to.x).step_by(Self::REGION_WIDTH as usize) {
-            for z in (from.z..=to.z).step_by(Self::REGION_WIDTH as usize) {
+        for x in (from.x..=to.x).step_by(width) {
+            for z in (from.z..=to.z).step_by(width) {
                 let loc = BlockLocation2D::new(x, z);
                 vec.push(MineRegion(loc));
             }
@@ -12,37 +12,160 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+#[derive(Serialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Gamemode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl Gamemode {
+    pub fn from_id(id: i32) -> Option<Gamemode> {
+        use Gamemode::*;
+        let gamemode = match id {
+            0 => Survival,
+            1 => Creative,
+            2 => Adventure,
+            3 => Spectator,
+            _ => return None,
+        };
+        Some(gamemode)
+    }
+}
+
+/// A profile property. `textures` holds the skin and cape
+#[derive(Serialize, Clone, Debug)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
 pub struct Player {
     pub name: String,
     pub uuid: u128,
+
+    /// None if the server sent an id we do not know
+    pub gamemode: Option<Gamemode>,
+
+    /// the latency the server measured in milliseconds
+    pub ping: i32,
+
+    /// the name in the tab list if it is not `name`
+    pub display_name: Option<String>,
+    pub properties: Vec<Property>,
+}
+
+/// An entry of a Player List Item packet
+pub enum PlayerUpdate {
+    Add(Player),
+    Gamemode(Option<Gamemode>),
+    Ping(i32),
+    DisplayName(Option<String>),
+    Remove,
 }
 
+/// The tab list. Every bot receives it, so the same updates arrive once per
+/// bot
 #[derive(Default)]
 pub struct WorldPlayers {
-    players: Vec<Player>,
+    /// map of UUID => Player
+    players: HashMap<u128, Player>,
 }
 
 impl WorldPlayers {
     pub fn add(&mut self, player: Player) {
-        self.players.push(player);
+        self.players.insert(player.uuid, player);
     }
 
-    pub fn by_name(&mut self, name: &str) -> Option<&Player> {
-        self.players.iter().find(|player| player.name == name)
+    pub fn by_name(&self, name: &str) -> Option<&Player> {
+        self.players.values().find(|player| player.name == name)
     }
 
-    pub fn by_uuid(&mut self, uuid: u128) -> Option<&Player> {
-        self.players.iter().find(|player| player.uuid == uuid)
+    pub fn by_uuid(&self, uuid: u128) -> Option<&Player> {
+        self.players.get(&uuid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Player> + '_ {
+        self.players.values()
     }
 
     /// return true if successfully removed
     pub fn remove(&mut self, uuid: u128) -> bool {
-        let mut action = || {
-            let idx = self.players.iter().position(|player| player.uuid == uuid)?;
-            self.players.swap_remove(idx);
-            Some(())
+        self.players.remove(&uuid).is_some()
+    }
+
+    /// Updates of players we do not know are ignored
+    pub fn update(&mut self, uuid: u128, update: PlayerUpdate) {
+        if let PlayerUpdate::Add(player) = update {
+            self.add(player);
+            return;
+        }
+
+        if let PlayerUpdate::Remove = update {
+            self.remove(uuid);
+            return;
+        }
+
+        let player = match self.players.get_mut(&uuid) {
+            Some(player) => player,
+            None => return,
+        };
+
+        match update {
+            PlayerUpdate::Gamemode(gamemode) => player.gamemode = gamemode,
+            PlayerUpdate::Ping(ping) => player.ping = ping,
+            PlayerUpdate::DisplayName(name) => player.display_name = name,
+            PlayerUpdate::Add(_) | PlayerUpdate::Remove => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::world_players::{
+        Gamemode, Player, PlayerUpdate, WorldPlayers,
+    };
+
+    #[test]
+    fn test_update() {
+        let player = || Player {
+            name: "hello".to_string(),
+            uuid: 1,
+            gamemode: Gamemode::from_id(0),
+            ping: 0,
+            display_name: None,
+            properties: Vec::new(),
         };
 
-        action().is_some()
+        let mut players = WorldPlayers::default();
+
+        // every bot is sent the player
+        players.update(1, PlayerUpdate::Add(player()));
+        players.update(1, PlayerUpdate::Add(player()));
+        assert_eq!(players.iter().count(), 1);
+
+        players.update(1, PlayerUpdate::Gamemode(Gamemode::from_id(1)));
+        players.update(1, PlayerUpdate::Ping(120));
+        players.update(
+            1,
+            PlayerUpdate::DisplayName(Some("[admin] hello".to_string())),
+        );
+        players.update(2, PlayerUpdate::Ping(50));
+
+        let player = players.by_name("hello").unwrap();
+        assert_eq!(player.gamemode, Some(Gamemode::Creative));
+        assert_eq!(player.ping, 120);
+        assert_eq!(player.display_name.as_deref(), Some("[admin] hello"));
+        assert!(players.by_uuid(2).is_none());
+
+        players.update(1, PlayerUpdate::Remove);
+        assert!(players.by_uuid(1).is_none());
     }
 }
@@ -13,8 +13,10 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
+    bootstrap::config::BotConfig,
     client::{physics::Physics, state::local::inventory::PlayerInventory},
     protocol::{ClientInfo, Face},
+    storage::dimensions::World,
     types::Dimension,
 };
 use interfaces::types::BlockLocation;
@@ -34,10 +36,19 @@ pub struct LocalState {
     pub bot_id: u32,
     pub physics: Physics,
     pub disconnected: bool,
+
+    /// why the bot disconnected. Decides whether it reconnects
+    pub disconnect_reason: Option<String>,
     pub inventory: PlayerInventory,
     pub info: ClientInfo,
     pub alive: bool,
     pub dimension: Dimension,
+
+    /// the server behind a proxy the bot is on. See [`World`]
+    pub backend: u32,
+
+    /// values from the config file which can differ between bots
+    pub config: BotConfig,
 }
 
 impl LocalState {
@@ -49,10 +60,20 @@ impl LocalState {
                 uuid: Default::default(),
                 entity_id: 0,
             },
+            BotConfig::default(),
         )
     }
 
-    pub fn new(bot_id: u32, info: ClientInfo) -> LocalState {
+    /// the storage in [`crate::client::state::global::GlobalState`] the bot
+    /// uses
+    pub fn world(&self) -> World {
+        World {
+            backend: self.backend,
+            dimension: self.dimension,
+        }
+    }
+
+    pub fn new(bot_id: u32, info: ClientInfo, config: BotConfig) -> LocalState {
         LocalState {
             ticks: 0,
             health: 0.0,
@@ -61,10 +82,13 @@ impl LocalState {
             bot_id,
             physics: Physics::default(),
             disconnected: false,
+            disconnect_reason: None,
             inventory: PlayerInventory::default(),
             alive: true,
             dimension: Dimension::Overworld,
+            backend: 0,
             info,
+            config,
         }
     }
 }
@@ -46,14 +46,14 @@ impl TaskStream for AttackEntity {
         let current_location = local.physics.location();
 
         // we cannot do anything if we do not know the location so we end the task
-        let entity_location = global.entities.by_id(self.id)?.location;
+        let entity_location = global.entities[local.world()].by_id(self.id)?.location;
 
         let dist2 = entity_location.dist2(current_location);
 
-        const THRESHOLD_DIST: f64 = 3.0;
-        const THRESHOLD_DIST_SMALLER: f64 = THRESHOLD_DIST - 0.5;
+        let threshold_dist = local.config.attack_distance;
+        let threshold_dist_smaller = threshold_dist - 0.5;
 
-        if dist2 < THRESHOLD_DIST * THRESHOLD_DIST {
+        if dist2 < threshold_dist * threshold_dist {
             // we can hit the entity
             let hit = HitEntityTask::new(self.id);
             let mut compound = CompoundTask::default();
@@ -66,7 +66,7 @@ impl TaskStream for AttackEntity {
             let travel = TravelProblem::navigate_near_block(
                 current_location.into(),
                 BlockLocation2D::from(BlockLocation::from(entity_location)),
-                THRESHOLD_DIST_SMALLER * THRESHOLD_DIST_SMALLER,
+                threshold_dist_smaller * threshold_dist_smaller,
                 false,
             );
             let task = NavigateProblem::from(travel);
@@ -0,0 +1,92 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use interfaces::types::{BlockKind, BlockLocation};
+
+use crate::{
+    client::{
+        state::{global::GlobalState, local::LocalState},
+        tasks::{
+            compound::CompoundTask, delay::DelayTask, navigate::BlockTravelTask,
+            stream::TaskStream, Task,
+        },
+    },
+    protocol::InterfaceOut,
+};
+
+/// Items cannot be picked up right after they are dropped
+const PICKUP_DELAY_TICKS: u32 = 10;
+
+/// Walks to the closest dropped item until there are none left in `radius`
+pub struct CollectItems {
+    /// only collect items of this kind
+    kind: Option<BlockKind>,
+    radius: f64,
+
+    /// items we already went to. They are not tried again so items we cannot
+    /// reach or pick up do not keep the task going forever
+    tried: HashSet<u32>,
+}
+
+impl CollectItems {
+    pub fn new(kind: Option<BlockKind>, radius: f64) -> Self {
+        Self {
+            kind,
+            radius,
+            tried: HashSet::new(),
+        }
+    }
+}
+
+impl TaskStream for CollectItems {
+    fn poll(
+        &mut self,
+        _out: &mut impl InterfaceOut,
+        local: &mut LocalState,
+        global: &mut GlobalState,
+    ) -> Option<Task> {
+        let current_location = local.physics.location();
+
+        let tried = &self.tried;
+        let kind = self.kind;
+        let (id, item) = global.entities[local.world()].nearest(
+            current_location,
+            self.radius,
+            |id, entity| {
+                let matches = match (kind, &entity.item) {
+                    (None, _) => true,
+                    (Some(kind), Some(stack)) => stack.kind == kind,
+
+                    // we do not know what the item is yet
+                    (Some(_), None) => false,
+                };
+                entity.kind.is_item() && matches && !tried.contains(&id)
+            },
+        )?;
+
+        self.tried.insert(id);
+
+        let goal = BlockLocation::from(item.location);
+        let mut compound = CompoundTask::default();
+
+        if BlockLocation::from(current_location) != goal {
+            compound.add(BlockTravelTask::new(goal, local));
+        }
+        compound.add(DelayTask(PICKUP_DELAY_TICKS));
+
+        Some(compound.into())
+    }
+}
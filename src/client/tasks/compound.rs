@@ -12,12 +12,13 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::VecDeque, time::Instant};
+use std::collections::VecDeque;
 
 use crate::{
     client::{
         state::{global::GlobalState, local::LocalState},
         tasks::{Task, TaskTrait},
+        timing::Budget,
     },
     protocol::InterfaceOut,
 };
@@ -59,10 +60,10 @@ impl TaskTrait for CompoundTask {
         true
     }
 
-    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+    fn expensive(&mut self, budget: Budget, local: &mut LocalState, global: &GlobalState) {
         match self.tasks.front_mut() {
             None => {}
-            Some(res) => res.expensive(end_at, local, global),
+            Some(res) => res.expensive(budget, local, global),
         };
     }
 }
@@ -12,12 +12,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::time::Instant;
-
 use crate::{
     client::{
         state::{global::GlobalState, local::LocalState},
         tasks::{Task, TaskTrait},
+        timing::Budget,
     },
     protocol::InterfaceOut,
 };
@@ -62,8 +61,8 @@ impl<T: Lazy> TaskTrait for LazyTask<T> {
         task.tick(out, local, global)
     }
 
-    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+    fn expensive(&mut self, budget: Budget, local: &mut LocalState, global: &GlobalState) {
         let task = self.get(local, global);
-        task.expensive(end_at, local, global);
+        task.expensive(budget, local, global);
     }
 }
@@ -12,12 +12,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::time::Instant;
-
 use crate::{
     client::{
         state::{global::GlobalState, local::LocalState},
         tasks::{stream::TaskStream, Task, TaskTrait},
+        timing::Budget,
     },
     protocol::InterfaceOut,
 };
@@ -71,11 +70,11 @@ impl<T: TaskStream> TaskTrait for LazyStream<T> {
         true
     }
 
-    fn expensive(&mut self, end_by: Instant, local: &mut LocalState, global: &GlobalState) {
+    fn expensive(&mut self, budget: Budget, local: &mut LocalState, global: &GlobalState) {
         let current = match self.current.as_mut() {
             None => return,
             Some(inner) => inner,
         };
-        current.expensive(end_by, local, global);
+        current.expensive(budget, local, global);
     }
 }
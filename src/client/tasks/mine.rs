@@ -35,7 +35,9 @@ impl MineTask {
         local: &mut LocalState,
         global: &GlobalState,
     ) -> MineTask {
-        let kind = global.blocks.get_block_kind(location).unwrap();
+        let kind = global.blocks[local.world()]
+            .get_block_kind(location)
+            .unwrap();
 
         let tool = local.inventory.switch_tool(kind, &global.block_data, out);
 
@@ -75,7 +77,7 @@ impl TaskTrait for MineTask {
         out.swing_arm();
         if self.ticks == 0 {
             out.mine(self.location, Mine::Finished, self.face);
-            global.blocks.set_block(self.location, BlockState::AIR);
+            global.blocks[local.world()].set_block(self.location, BlockState::AIR);
             true
         } else {
             self.ticks -= 1;
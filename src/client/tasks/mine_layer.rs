@@ -38,8 +38,7 @@ impl TaskStream for MineLayer {
 
         let origin_loc = BlockLocation::from(local.physics.location()).below();
 
-        let block_to_mine = global
-            .blocks
+        let block_to_mine = global.blocks[local.world()]
             .y_slice(origin_loc, RADIUS, |state| {
                 state.kind().mineable(&global.block_data)
             })?
@@ -11,8 +11,6 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use std::time::Instant;
-
 use bridge::*;
 use center::CenterTask;
 use compound::*;
@@ -35,10 +33,12 @@ use crate::{
         state::{global::GlobalState, local::LocalState},
         tasks::{
             attack_entity::AttackEntity,
+            collect_items::CollectItems,
             lazy_stream::LazyStream,
             navigate::{BlockTravelTask, ChunkTravelTask},
             safe_mine_coord::SafeMineRegion,
         },
+        timing::Budget,
     },
     protocol::InterfaceOut,
 };
@@ -46,6 +46,7 @@ use crate::{
 pub mod attack_entity;
 pub mod bridge;
 pub mod center;
+pub mod collect_items;
 pub mod compound;
 pub mod delay;
 pub mod eat;
@@ -76,23 +77,25 @@ pub trait TaskTrait {
 
     /// Do an expensive part of the task. This is done in a multi-threaded
     /// environment. An example of This has a default implementation of
-    /// nothing. However, tasks like pathfinding use this. The task MUST end
-    /// by the given {end_by} duration else the game loop is held up. This is
-    /// called every game loop cycle so if the task hasn't finished it by
-    /// {end_by} it should instead until this function is called again.
-    fn expensive(&mut self, _end_by: Instant, _local: &mut LocalState, _global: &GlobalState) {}
+    /// nothing. However, tasks like pathfinding use this. The task MUST stop
+    /// when the {budget} is exhausted else the game loop is held up. This is
+    /// called every game loop cycle so if the task hasn't finished within
+    /// {budget} it should instead until this function is called again.
+    fn expensive(&mut self, _budget: Budget, _local: &mut LocalState, _global: &GlobalState) {}
 }
 
 pub type GoMineTopTask = LazyTask<GoMineTop>;
 pub type MineRegionTask = LazyStream<MineRegion>;
 pub type SafeMineRegionTask = LazyTask<SafeMineRegion>;
 pub type AttackEntityTask = LazyStream<AttackEntity>;
+pub type CollectItemsTask = LazyStream<CollectItems>;
 
 #[allow(clippy::enum_variant_names)]
 #[enum_dispatch(TaskTrait)]
 pub enum Task {
     CompoundTask,
     AttackEntityTask,
+    CollectItemsTask,
     HitEntityTask,
     EatTask,
     MineRegionTask,
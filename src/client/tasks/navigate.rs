@@ -13,7 +13,6 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use interfaces::types::{BlockLocation, ChunkLocation};
-use std::time::Instant;
 
 use crate::{
     client::{
@@ -31,7 +30,7 @@ use crate::{
         },
         state::{global::GlobalState, local::LocalState},
         tasks::TaskTrait,
-        timing::Increment,
+        timing::{Budget, Increment},
     },
     protocol::InterfaceOut,
 };
@@ -108,12 +107,12 @@ impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> TaskTrait for Navig
         }
     }
 
-    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+    fn expensive(&mut self, budget: Budget, local: &mut LocalState, global: &GlobalState) {
         if !self.calculate {
             return;
         }
 
-        let res = self.problem.iterate_until(end_at, local, global);
+        let res = self.problem.iterate_until(budget, local, global);
         match res {
             Increment::Finished(res) => {
                 self.calculate = false;
@@ -52,7 +52,7 @@ impl TaskTrait for PillarTask {
             let below_loc = BlockLocation::from(local.physics.location()).below();
 
             // return true if block below us is solid
-            if global.blocks.get_block_simple(below_loc) == Some(SimpleType::Solid) {
+            if global.blocks[local.world()].get_block_simple(below_loc) == Some(SimpleType::Solid) {
                 return true;
             }
         }
@@ -68,19 +68,19 @@ impl TaskTrait for PillarTask {
         let two_below = below_block.below();
 
         let below_valid = matches!(
-            global.blocks.get_block_simple(below_block),
+            global.blocks[local.world()].get_block_simple(below_block),
             Some(SimpleType::Water) | Some(SimpleType::WalkThrough)
         );
 
         let two_below_valid = matches!(
-            global.blocks.get_block_simple(two_below),
+            global.blocks[local.world()].get_block_simple(two_below),
             Some(SimpleType::Solid)
         );
 
         if below_valid && two_below_valid {
             let below = BlockLocation::from(local.physics.location()).below();
             let against = below.below();
-            if global.blocks.get_block_simple(against) == Some(SimpleType::Solid) {
+            if global.blocks[local.world()].get_block_simple(against) == Some(SimpleType::Solid) {
                 local.physics.place_hand(against);
             }
         }
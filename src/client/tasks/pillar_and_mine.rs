@@ -56,7 +56,7 @@ impl TaskStream for PillarOrMine {
         let mut set = HashSet::new();
         local
             .physics
-            .in_cross_section(above1, &global.blocks, &mut set);
+            .in_cross_section(above1, &global.blocks[local.world()], &mut set);
 
         macro_rules! mine_task {
             ($position:expr) => {{
@@ -72,7 +72,7 @@ impl TaskStream for PillarOrMine {
             let above2 = local.physics.location() + Displacement::new(0., 3.5, 0.);
             local
                 .physics
-                .in_cross_section(above2, &global.blocks, &mut set);
+                .in_cross_section(above2, &global.blocks[local.world()], &mut set);
             if let Some(&position) = set.iter().next() {
                 mine_task!(position)
             } else {
@@ -13,10 +13,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::client::{
-    state::{
-        global::{mine_alloc::MineAlloc, GlobalState},
-        local::LocalState,
-    },
+    state::{global::GlobalState, local::LocalState},
     tasks::{
         center::CenterTask,
         compound::CompoundTask,
@@ -39,20 +36,23 @@ impl Lazy for SafeMineRegion {
 
         // if we should skip this region. For example, if there is water or lava we will
         // want to avoid it
-        let avoid = MineAlloc::locations_extra(center).any(|loc| {
+        let avoid = global.mine.locations_extra(center).any(|loc| {
             // there is often lava under bedrock that we don't really care about
             if loc.y < MineColumn::MIN_MINE_LOC {
                 return false;
             }
 
-            match global.blocks.get_block_exact(loc).map(|x| x.kind().id()) {
+            match global.blocks[local.world()]
+                .get_block_exact(loc)
+                .map(|x| x.kind().id())
+            {
                 // water or lava
                 Some(8..=11) => {
                     println!(
                         "skipping region {}, {} because of {:?} at {}",
                         center.x,
                         center.z,
-                        global.blocks.get_block_exact(loc),
+                        global.blocks[local.world()].get_block_exact(loc),
                         loc
                     );
                     true
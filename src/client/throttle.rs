@@ -0,0 +1,208 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Adapts the delay between logins to the server. Every successful login
+//! lowers the delay a little and every throttle kick doubles it (AIMD).
+
+use std::{
+    cell::RefCell,
+    default::default,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+/// Disconnect reasons (lowercase) which mean we are logging in too fast
+const THROTTLED: &[&str] = &[
+    "logged in too fast",
+    "logging in too fast",
+    "connection throttled",
+    "please wait before reconnecting",
+    "too many connections",
+];
+
+/// How much a successful login lowers the delay
+const STEP: Duration = Duration::from_millis(50);
+
+pub type Throttle = Rc<RefCell<LoginThrottle>>;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ThrottleOptions {
+    /// the delay between logins we start with
+    pub initial_delay: Duration,
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+
+    /// logins which can be in progress at once. 0 is unlimited
+    pub max_in_flight: usize,
+}
+
+impl Default for ThrottleOptions {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            min_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(30),
+            max_in_flight: 8,
+        }
+    }
+}
+
+/// Schedules logins
+pub struct LoginThrottle {
+    options: ThrottleOptions,
+    delay: Duration,
+    in_flight: usize,
+    slot_freed: Rc<Notify>,
+
+    /// when the next login may start
+    next_start: Instant,
+}
+
+impl LoginThrottle {
+    pub fn new(options: ThrottleOptions) -> LoginThrottle {
+        let delay = options
+            .initial_delay
+            .max(options.min_delay)
+            .min(options.max_delay);
+
+        LoginThrottle {
+            options,
+            delay,
+            in_flight: 0,
+            slot_freed: default(),
+            next_start: Instant::now(),
+        }
+    }
+
+    pub fn shared(self) -> Throttle {
+        Rc::new(RefCell::new(self))
+    }
+
+    /// Whether the server disconnected us for logging in too fast
+    pub fn is_throttle(reason: &str) -> bool {
+        let reason = reason.to_lowercase();
+        THROTTLED.iter().any(|throttled| reason.contains(throttled))
+    }
+
+    /// How long to wait before starting the next login
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    fn full(&self) -> bool {
+        self.options.max_in_flight != 0 && self.in_flight >= self.options.max_in_flight
+    }
+
+    /// Reserves the next start time, keeping [`LoginThrottle::delay`] between
+    /// the starts of logins
+    fn next_start(&mut self) -> Instant {
+        let start = self.next_start.max(Instant::now());
+        self.next_start = start + self.delay;
+        start
+    }
+
+    /// Waits for a free login slot and takes it, then waits for the turn of
+    /// this login. [`LoginThrottle::finish`] frees the slot
+    pub async fn acquire(throttle: &Throttle) {
+        loop {
+            let slot_freed = {
+                let throttle = throttle.borrow();
+                if !throttle.full() {
+                    break;
+                }
+                throttle.slot_freed.clone()
+            };
+            slot_freed.notified().await;
+        }
+
+        let start = {
+            let mut throttle = throttle.borrow_mut();
+            throttle.in_flight += 1;
+            throttle.next_start()
+        };
+
+        // the delay adapts to how fast the server lets us log in
+        tokio::time::sleep_until(tokio::time::Instant::from_std(start)).await;
+    }
+
+    /// A login finished (successfully or not)
+    pub fn finish(&mut self) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.slot_freed.notify_one();
+    }
+
+    pub fn success(&mut self) {
+        self.delay = self.delay.saturating_sub(STEP).max(self.options.min_delay);
+    }
+
+    /// A bot was disconnected. Backs off if it was for logging in too fast
+    pub fn disconnected(&mut self, reason: &str) {
+        if !Self::is_throttle(reason) {
+            return;
+        }
+
+        self.delay = (self.delay * 2).min(self.options.max_delay);
+        println!(
+            "logins are throttled, waiting {}ms between logins",
+            self.delay.as_millis()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::client::throttle::{LoginThrottle, ThrottleOptions};
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = LoginThrottle::new(ThrottleOptions {
+            initial_delay: Duration::from_millis(200),
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            max_in_flight: 2,
+        });
+
+        throttle.success();
+        assert_eq!(throttle.delay(), Duration::from_millis(150));
+        throttle.success();
+        throttle.success();
+        assert_eq!(throttle.delay(), Duration::from_millis(100));
+
+        throttle.disconnected("Server closed");
+        assert_eq!(throttle.delay(), Duration::from_millis(100));
+
+        let kick = r#"{"text":"Connection throttled! Please wait before reconnecting."}"#;
+        throttle.disconnected(kick);
+        assert_eq!(throttle.delay(), Duration::from_millis(200));
+
+        for _ in 0..5 {
+            throttle.disconnected("You logged in too fast!");
+        }
+        assert_eq!(throttle.delay(), Duration::from_millis(1000));
+
+        throttle.in_flight = 2;
+        assert!(throttle.full());
+        throttle.finish();
+        assert!(!throttle.full());
+
+        // logins are spaced by the delay
+        let first = throttle.next_start();
+        let second = throttle.next_start();
+        assert_eq!(second - first, Duration::from_millis(1000));
+    }
+}
@@ -12,6 +12,40 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::time::{Duration, Instant};
+
+/// How long one game loop iteration is
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// How much work the expensive part of a task can do in one tick
+#[derive(Copy, Clone, Debug)]
+pub enum Budget {
+    /// until this time. The runner uses this to keep up with the game loop
+    Until(Instant),
+
+    /// this many iterations, counted as a full tick of work. Replays use this
+    /// so the same work is done on every run
+    Iterations(u32),
+}
+
+impl Budget {
+    /// Whether the work is done after `iterations` iterations
+    pub fn exhausted(&self, iterations: u32) -> bool {
+        match *self {
+            Budget::Until(end) => Instant::now() >= end,
+            Budget::Iterations(max) => iterations >= max,
+        }
+    }
+
+    /// The time the work which started at `start` counts as
+    pub fn spent(&self, start: Instant) -> Duration {
+        match self {
+            Budget::Until(_) => start.elapsed(),
+            Budget::Iterations(_) => TICK,
+        }
+    }
+}
+
 pub enum Increment<T> {
     Finished(T),
     InProgress,
@@ -28,6 +28,7 @@ pub enum Error {
     Csv(csv::Error),
     Socks5(tokio_socks::Error),
     Serde(serde_json::Error),
+    Toml(toml::de::Error),
     Reqwest(reqwest::Error),
     Resolve(Box<trust_dns_resolver::error::ResolveError>),
     WrongPacket {
@@ -37,6 +38,34 @@ pub enum Error {
     },
     Simple(String),
     Mojang(MojangErr),
+    Proxy(ProxyErr),
+
+    /// the server kicked us while logging in
+    Disconnected(String),
+
+    /// a packet could not be decoded, i.e., it was shorter than its fields
+    Malformed(String),
+}
+
+impl Error {
+    /// A short description of what failed. Used to group failed logins
+    pub fn category(&self) -> String {
+        match self {
+            Error::IO(io) => format!("io {:?}", io.kind()),
+            Error::Csv(_) => "csv".to_string(),
+            Error::Socks5(socks) => format!("proxy {}", socks),
+            Error::Proxy(proxy) => proxy.to_string(),
+            Error::Serde(_) => "invalid json".to_string(),
+            Error::Toml(_) => "invalid toml".to_string(),
+            Error::Reqwest(_) => "http".to_string(),
+            Error::Resolve(_) => "dns".to_string(),
+            Error::WrongPacket { state, .. } => format!("wrong packet in state {}", state),
+            Error::Simple(str) => str.clone(),
+            Error::Mojang(_) => "authentication".to_string(),
+            Error::Disconnected(reason) => format!("kicked {}", reason),
+            Error::Malformed(_) => "malformed packet".to_string(),
+        }
+    }
 }
 
 impl From<serde_json::Error> for Error {
@@ -51,6 +80,12 @@ pub enum MojangErr {
         error_code: StatusCode,
         info: Option<String>,
     },
+    Microsoft {
+        step: &'static str,
+        error_code: StatusCode,
+        info: Option<String>,
+    },
+    DeviceCodeExpired,
 }
 
 impl Display for MojangErr {
@@ -61,6 +96,38 @@ impl Display for MojangErr {
                 error_code,
                 info.clone().unwrap_or_default()
             )),
+            MojangErr::Microsoft {
+                step,
+                error_code,
+                info,
+            } => f.write_fmt(format_args!(
+                "microsoft auth failed at {} #{} info {}",
+                step,
+                error_code,
+                info.clone().unwrap_or_default()
+            )),
+            MojangErr::DeviceCodeExpired => {
+                f.write_str("the device code expired before it was entered")
+            }
+        }
+    }
+}
+
+/// A proxy which is not SOCKS5 failed to open a tunnel
+#[derive(Debug)]
+pub enum ProxyErr {
+    /// the proxy rejected our credentials
+    Auth(String),
+
+    /// the proxy would not (or could not) connect to the server
+    Refused(String),
+}
+
+impl Display for ProxyErr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyErr::Auth(info) => f.write_fmt(format_args!("proxy auth failed {}", info)),
+            ProxyErr::Refused(info) => f.write_fmt(format_args!("proxy refused {}", info)),
         }
     }
 }
@@ -74,6 +141,11 @@ impl Display for Error {
             Error::Reqwest(inner) => std::fmt::Display::fmt(inner, f),
             Error::Socks5(socks) => std::fmt::Display::fmt(socks, f),
             Error::Mojang(inner) => std::fmt::Display::fmt(inner, f),
+            Error::Proxy(inner) => std::fmt::Display::fmt(inner, f),
+            Error::Disconnected(reason) => {
+                f.write_fmt(format_args!("disconnected during login: {}", reason))
+            }
+            Error::Malformed(reason) => f.write_fmt(format_args!("malformed packet: {}", reason)),
             Error::WrongPacket {
                 state,
                 actual,
@@ -84,6 +156,7 @@ impl Display for Error {
             )),
             Error::Resolve(r) => std::fmt::Display::fmt(r, f),
             Error::Serde(s) => std::fmt::Display::fmt(s, f),
+            Error::Toml(t) => std::fmt::Display::fmt(t, f),
         }
     }
 }
@@ -92,6 +165,12 @@ pub fn err(str: &str) -> Error {
     Error::Simple(str.to_string())
 }
 
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Toml(err)
+    }
+}
+
 impl From<csv::Error> for Error {
     fn from(err: csv::Error) -> Self {
         Self::Csv(err)
@@ -110,6 +189,18 @@ impl From<MojangErr> for Error {
     }
 }
 
+impl From<ProxyErr> for Error {
+    fn from(err: ProxyErr) -> Self {
+        Self::Proxy(err)
+    }
+}
+
+impl From<nbt::Error> for Error {
+    fn from(err: nbt::Error) -> Self {
+        Self::Malformed(format!("nbt .. {}", err))
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Self::IO(err)
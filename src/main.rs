@@ -42,12 +42,34 @@ extern crate test;
 #[macro_use]
 extern crate thiserror;
 
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use tokio::{runtime::Runtime, task};
 
 use crate::{
-    bootstrap::{dns::normalize_address, opts::Opts, storage::BotData, Connection},
-    client::runner::{Runner, RunnerOptions},
-    error::{HasContext, ResContext},
+    bootstrap::{
+        cache_cmd,
+        config::{AuthConfig, Config},
+        dns::normalize_address,
+        login_stats::LoginStats,
+        mojang::AuthMethod,
+        opts::{Command, Opts},
+        proxy_pool::{ProxyLimits, ProxyPool},
+        storage::{load_proxies, BotData},
+        Connection,
+    },
+    client::{
+        reconnect::ReconnectPolicy,
+        replay,
+        runner::{Runner, RunnerOptions},
+        throttle::{LoginThrottle, ThrottleOptions},
+    },
+    error::{err, HasContext, ResContext},
+    protocol::{status::ping, transform::Flattening},
+    storage::blocks::MemoryCap,
 };
 
 mod bootstrap;
@@ -65,8 +87,8 @@ fn main() {
     let local = task::LocalSet::new();
     local.block_on(&rt, async move {
         match run().await {
-            // this should never happen as this should be an infinite loop
-            Ok(_) => println!("Program exited without errors somehow"),
+            // only subcommands and --load finish. The swarm is an infinite loop
+            Ok(_) => {}
 
             // print the error in non-debug fashion
             Err(err) => println!("{}", err),
@@ -75,21 +97,78 @@ fn main() {
 }
 
 async fn run() -> ResContext {
-    let Opts {
+    let mut opts = Opts::get();
+    let command = opts.command.take();
+    let load = opts.load;
+
+    // flags override the config file
+    let mut config = Config::load(opts.config.as_deref())?;
+    let bot_configs = config.bot_configs();
+    config.apply(opts);
+
+    if let Some(command) = command {
+        return run_command(command, &config).await;
+    }
+
+    let Config {
+        server,
+        auth,
+        proxies,
+        runner,
+        ..
+    } = config;
+
+    let host = server
+        .host
+        .ok_or_else(|| err("pass a host or set host in [server]"))
+        .context_str("no server to join")?;
+
+    let servers = auth.servers();
+    let endpoints = auth.microsoft_endpoints();
+    let policy = auth.token_policy();
+
+    let AuthConfig {
         users_file,
-        proxies_file,
-        host,
-        count,
-        version,
-        port,
-        delay_ms,
-        load,
-        ws_port,
-        proxy,
-    } = Opts::get();
+        offline,
+        offline_prefix,
+        microsoft,
+        encrypt_cache,
+        ..
+    } = auth;
+
+    let auth = if microsoft {
+        let endpoints = endpoints
+            .ok_or_else(|| err("--ms-client-id (or ms_client_id in [auth]) is required for Microsoft authentication"))
+            .context_str("could not set up Microsoft authentication")?;
+
+        AuthMethod::Microsoft(endpoints)
+    } else {
+        AuthMethod::Mojang
+    };
+
+    let limits = ProxyLimits {
+        max_bots: proxies.max_bots,
+        max_failures: proxies.max_failures,
+        quarantine: Duration::from_secs(proxies.quarantine_secs),
+    };
+    let proxies = ProxyPool::new(load_proxies(proxies.enabled, &proxies.file)?, limits).shared();
 
     // A list of users we will login
-    let mut bot_receiver = BotData::load(proxy, &users_file, &proxies_file, count)?;
+    let (mut bot_receiver, revalidator) = if offline {
+        let bots = BotData::offline(&offline_prefix, runner.count, proxies.clone());
+        (bots, None)
+    } else {
+        let (bots, revalidator) = BotData::load(
+            &users_file,
+            runner.count,
+            auth,
+            servers,
+            proxies.clone(),
+            policy,
+            encrypt_cache,
+        )?;
+        (bots, Some(revalidator))
+    };
 
     if load {
         while bot_receiver.recv().await.is_some() {
@@ -99,19 +178,99 @@ async fn run() -> ResContext {
     }
 
     // looks up DNS records, etc
-    let server_address = normalize_address(&host, port).await;
+    let server_address = normalize_address(&host, server.port).await;
+
+    let version = match server.version {
+        Some(version) => version,
+        None => {
+            let status = ping(&server_address)
+                .await
+                .context_str("could not detect the server version (pass --version)")?;
+            println!(
+                "detected version {} (protocol {})",
+                status.version, status.protocol
+            );
+            status.protocol
+        }
+    };
+
+    // the results of connecting and logging in every bot
+    let stats = LoginStats::shared();
+
+    let throttle = LoginThrottle::new(ThrottleOptions {
+        initial_delay: Duration::from_millis(runner.delay_ms),
+        min_delay: Duration::from_millis(runner.login_min_delay_ms),
+        max_delay: Duration::from_millis(runner.login_max_delay_ms),
+        max_in_flight: runner.max_logins_in_flight,
+    })
+    .shared();
 
     // taking the users and generating connections to the Minecraft server
-    let connections = Connection::stream(server_address, bot_receiver);
+    let connections = Connection::stream(
+        server_address,
+        bot_receiver,
+        proxies.clone(),
+        stats.clone(),
+        throttle.clone(),
+    );
 
-    let run_options = RunnerOptions { delay_ms, ws_port };
+    let run_options = RunnerOptions {
+        throttle,
+        ws_port: runner.ws_port,
+        proxies,
+        stats,
+        reconnect: ReconnectPolicy {
+            max_retries: runner.reconnect_retries,
+            base_delay: Duration::from_millis(runner.reconnect_delay_ms),
+            max_delay: Duration::from_millis(runner.reconnect_max_delay_ms),
+            ..ReconnectPolicy::default()
+        },
+        revalidator,
+        bot_configs,
+        mine_region_radius: runner.mine_region_radius,
+        capture: runner.capture.map(PathBuf::from),
+        chunk_memory: runner.chunk_memory_mb.map(|mb| MemoryCap {
+            bytes: mb << 20,
+            downgrade: runner.chunk_downgrade,
+        }),
+    };
 
     match version {
         340 => Runner::<protocol::v340::Protocol>::run(connections, run_options)
             .await
             .context_str("Error starting up 1.12")?, // 1.12
+        754 => {
+            // read the block and item ids once instead of failing every login
+            Flattening::get().context_str("1.16 needs blocks_754.json and items_754.json")?;
+            Runner::<protocol::v754::Protocol>::run(connections, run_options)
+                .await
+                .context_str("Error starting up 1.16")? // 1.16.5
+        }
         _ => {
-            panic!("version {} does not exist", version)
+            let msg = format!(
+                "protocol {} is not supported (supported: 340, 754)",
+                version
+            );
+            return Err(err(&msg)).context_str("could not start");
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a subcommand instead of the swarm
+async fn run_command(command: Command, config: &Config) -> ResContext {
+    match command {
+        Command::Ping { host, port } => {
+            let address = normalize_address(&host, port).await;
+            let status = ping(&address)
+                .await
+                .context(|| format!("could not ping {}", String::from(&address)))?;
+            println!("{}", status);
+        }
+        Command::Cache { file, action } => cache_cmd::run(&file, action, &config.auth).await?,
+        Command::Replay { file } => {
+            replay::run(Path::new(&file)).context(|| format!("could not replay {}", file))?
         }
     }
 
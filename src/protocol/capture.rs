@@ -0,0 +1,380 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Captures of the clientbound packets of a bot and the commands it was
+//! given. A capture can be replayed offline with a [`ReplayQueue`] to
+//! reproduce what the bot did
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::Path,
+    sync::mpsc::Sender,
+};
+
+use swarm_bot_packets::{
+    read::ByteReader,
+    types::{VarInt, UUID},
+    write::ByteWriter,
+};
+
+use crate::{
+    client::{commands::BotCommand, processor::InterfaceIn},
+    error::{err, Res},
+    protocol::{io::decode, ClientInfo, EventQueue, Minecraft},
+    types::PacketData,
+};
+
+const MAGIC: &[u8] = b"SWARMCAP1";
+
+/// The packet id of a record holding a [`BotCommand`]
+const COMMAND_ID: i32 = -1;
+
+/// Writes the packets a bot receives and the commands it is given with the
+/// tick they were processed in
+pub struct Capture {
+    file: BufWriter<File>,
+    tick: u64,
+}
+
+impl Capture {
+    pub fn create(path: &Path, protocol: u32, info: &ClientInfo) -> Res<Capture> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let mut header = ByteWriter::new();
+        header
+            .write(MAGIC)
+            .write(VarInt(protocol as i32))
+            .write(info.username.clone())
+            .write(info.uuid)
+            .write(VarInt(info.entity_id as i32));
+        file.write_all(&header.freeze())?;
+
+        Ok(Capture { file, tick: 0 })
+    }
+
+    /// Records a packet before it is processed
+    pub fn record(&mut self, data: &PacketData) -> Res {
+        let mut record = ByteWriter::new();
+        record
+            .write(self.tick)
+            .write(VarInt(data.id as i32))
+            .write(data.reader.remaining().to_vec());
+        self.file.write_all(&record.freeze())?;
+        Ok(())
+    }
+
+    /// Records a command. Commands are given before the packets of a tick
+    /// are processed
+    pub fn record_command(&mut self, command: &BotCommand) -> Res {
+        let mut record = ByteWriter::new();
+        record
+            .write(self.tick)
+            .write(VarInt(COMMAND_ID))
+            .write(serde_json::to_vec(command)?);
+        self.file.write_all(&record.freeze())?;
+        Ok(())
+    }
+
+    /// Called at the end of each flush of the queue
+    pub fn end_tick(&mut self) -> Res {
+        self.tick += 1;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Records a packet if `capture` is set. Stops capturing if the file cannot be
+/// written
+pub fn record(capture: &mut Option<Capture>, data: &PacketData) {
+    if let Some(inner) = capture.as_mut() {
+        if let Err(e) = inner.record(data) {
+            println!("stopped capturing: {}", e);
+            *capture = None;
+        }
+    }
+}
+
+/// Records a command if `capture` is set. Stops capturing if the file cannot
+/// be written
+pub fn record_command(capture: &mut Option<Capture>, command: &BotCommand) {
+    if let Some(inner) = capture.as_mut() {
+        if let Err(e) = inner.record_command(command) {
+            println!("stopped capturing: {}", e);
+            *capture = None;
+        }
+    }
+}
+
+/// Ends the tick of `capture` if it is set
+pub fn end_tick(capture: &mut Option<Capture>) {
+    if let Some(inner) = capture.as_mut() {
+        if let Err(e) = inner.end_tick() {
+            println!("stopped capturing: {}", e);
+            *capture = None;
+        }
+    }
+}
+
+pub enum Event {
+    Packet(PacketData),
+    Command(BotCommand),
+}
+
+pub struct Record {
+    pub tick: u64,
+    pub event: Event,
+}
+
+impl Record {
+    fn read(reader: &mut ByteReader) -> Res<Record> {
+        let (tick, id, data) = decode(|| {
+            let tick = reader.read();
+            let VarInt(id) = reader.read();
+            let data: Vec<u8> = reader.read();
+            (tick, id, data)
+        })?;
+
+        let event = match id {
+            COMMAND_ID => Event::Command(serde_json::from_slice(&data)?),
+            id => Event::Packet(PacketData {
+                id: id as u32,
+                reader: ByteReader::new(data),
+            }),
+        };
+
+        Ok(Record { tick, event })
+    }
+}
+
+/// A capture read back
+pub struct Replay {
+    pub protocol: u32,
+    pub info: ClientInfo,
+    pub records: Vec<Record>,
+}
+
+impl Replay {
+    pub fn read(path: &Path) -> Res<Replay> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        if !bytes.starts_with(MAGIC) {
+            return Err(err("not a capture"));
+        }
+
+        let mut reader = ByteReader::new(bytes[MAGIC.len()..].to_vec());
+
+        let (protocol, username, uuid, entity_id) = decode(|| {
+            let VarInt(protocol) = reader.read();
+            let username: String = reader.read();
+            let uuid: UUID = reader.read();
+            let VarInt(entity_id) = reader.read();
+            (protocol, username, uuid, entity_id)
+        })?;
+
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            match Record::read(&mut reader) {
+                Ok(record) => records.push(record),
+
+                // the bot was most likely stopped while writing
+                Err(e) => {
+                    println!("the capture ends in a broken record: {}", e);
+                    break;
+                }
+            }
+        }
+
+        Ok(Replay {
+            protocol: protocol as u32,
+            info: ClientInfo {
+                username,
+                uuid,
+                entity_id: entity_id as u32,
+            },
+            records,
+        })
+    }
+}
+
+/// Feeds a capture through the queue of protocol `T`. Every flush is one tick
+/// of the capture. The commands of a tick are taken with
+/// [`ReplayQueue::commands`] before it is flushed
+pub struct ReplayQueue<T: Minecraft> {
+    records: VecDeque<Record>,
+    tick: u64,
+    tx: Sender<Res<PacketData>>,
+    queue: T::Queue,
+}
+
+impl<T: Minecraft> ReplayQueue<T> {
+    /// The queue and an interface which drops everything the bot sends
+    pub fn new(protocol: u32, records: Vec<Record>) -> Res<(ReplayQueue<T>, T::Interface)> {
+        if protocol != T::PROTOCOL {
+            let msg = format!("the capture is protocol {} not {}", protocol, T::PROTOCOL);
+            return Err(err(&msg));
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (queue, out) = T::offline(rx)?;
+
+        let queue = ReplayQueue {
+            records: records.into(),
+            tick: 0,
+            tx,
+            queue,
+        };
+
+        Ok((queue, out))
+    }
+
+    /// Whether every packet has been replayed
+    pub fn finished(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The commands given to the bot in the current tick
+    pub fn commands(&mut self) -> Vec<BotCommand> {
+        let mut commands = Vec::new();
+        while let Some(record) = self.records.front() {
+            if record.tick > self.tick || !matches!(record.event, Event::Command(_)) {
+                break;
+            }
+
+            if let Some(Record {
+                event: Event::Command(command),
+                ..
+            }) = self.records.pop_front()
+            {
+                commands.push(command);
+            }
+        }
+        commands
+    }
+}
+
+impl<T: Minecraft> EventQueue for ReplayQueue<T> {
+    fn flush(&mut self, processor: &mut impl InterfaceIn) {
+        while let Some(record) = self.records.front() {
+            // commands come before the packets of their tick
+            if record.tick > self.tick || matches!(record.event, Event::Command(_)) {
+                break;
+            }
+
+            if let Some(Record {
+                event: Event::Packet(data),
+                ..
+            }) = self.records.pop_front()
+            {
+                // the queue is owned by us so it cannot have hung up
+                let _ = self.tx.send(Ok(data));
+            }
+        }
+
+        self.queue.flush(processor);
+        self.tick += 1;
+    }
+
+    fn capture(&mut self, capture: Capture) {
+        self.queue.capture(capture);
+    }
+
+    fn record_command(&mut self, command: &BotCommand) {
+        self.queue.record_command(command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use interfaces::types::BlockLocation;
+    use swarm_bot_packets::{read::ByteReader, types::UUID};
+
+    use crate::{
+        client::commands::BotCommand,
+        protocol::{
+            capture::{Capture, Event, Replay},
+            ClientInfo,
+        },
+        types::PacketData,
+    };
+
+    #[test]
+    fn test_capture() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-capture-{}", std::process::id()));
+        let info = ClientInfo {
+            username: "Bot0".to_string(),
+            uuid: UUID(123),
+            entity_id: 7,
+        };
+
+        let mut capture = Capture::create(&path, 340, &info).unwrap();
+        capture.end_tick().unwrap();
+
+        let mut data = PacketData {
+            id: 0x1f,
+            reader: ByteReader::new(vec![0x1f, 1, 2, 3]),
+        };
+
+        // the id is already read when the packet is recorded
+        let _: u8 = data.read();
+        capture
+            .record_command(&BotCommand::GoTo(BlockLocation::new(1, 2, 3)))
+            .unwrap();
+        capture.record(&data).unwrap();
+        capture.end_tick().unwrap();
+        drop(capture);
+
+        // a record cut off in the middle
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[2, 0]).unwrap();
+        drop(file);
+
+        let Replay {
+            protocol,
+            info,
+            records,
+        } = Replay::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(protocol, 340);
+        assert_eq!(info.username, "Bot0");
+        assert_eq!(info.uuid.0, 123);
+        assert_eq!(info.entity_id, 7);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].tick, 1);
+        match &records[0].event {
+            Event::Command(BotCommand::GoTo(location)) => {
+                assert_eq!(*location, BlockLocation::new(1, 2, 3))
+            }
+            _ => panic!("expected the goto command"),
+        }
+
+        assert_eq!(records[1].tick, 1);
+        match &records[1].event {
+            Event::Packet(data) => {
+                assert_eq!(data.id, 0x1f);
+                assert_eq!(data.reader.remaining(), &[1, 2, 3]);
+            }
+            _ => panic!("expected a packet"),
+        }
+    }
+}
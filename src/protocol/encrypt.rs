@@ -15,27 +15,33 @@
 use rand::{random, rngs::OsRng};
 use rsa::{BigUint, PaddingScheme, PublicKey, RsaPublicKey};
 
+use crate::error::{Error::Simple, Res};
+
 pub struct Rsa {
     key: RsaPublicKey,
 }
 
 impl Rsa {
-    pub fn from_der(der: &[u8]) -> Rsa {
+    pub fn from_der(der: &[u8]) -> Res<Rsa> {
         // https://wiki.vg/Protocol_Encryption
-        let (n, e) = rsa_der::public_key_from_der(der).unwrap();
+        let (n, e) = rsa_der::public_key_from_der(der)
+            .map_err(|e| Simple(format!("invalid server public key: {:?}", e)))?;
 
         // might be wrong endian
         let (n, e) = (BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e));
 
-        Rsa {
-            key: RsaPublicKey::new(n, e).unwrap(),
-        }
+        let key = RsaPublicKey::new(n, e)
+            .map_err(|e| Simple(format!("invalid server public key: {}", e)))?;
+
+        Ok(Rsa { key })
     }
 
-    pub fn encrypt(&self, elem: &[u8]) -> rsa::errors::Result<Vec<u8>> {
+    pub fn encrypt(&self, elem: &[u8]) -> Res<Vec<u8>> {
         let mut rng = OsRng;
         let padding = PaddingScheme::new_pkcs1v15_encrypt();
-        self.key.encrypt(&mut rng, padding, elem)
+        self.key
+            .encrypt(&mut rng, padding, elem)
+            .map_err(|e| Simple(format!("could not encrypt: {}", e)))
     }
 }
 
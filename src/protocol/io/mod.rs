@@ -12,7 +12,10 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::io::{Read, Write};
+use std::{
+    io::{Read, Write},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
 
 use aes::{
     cipher::{AsyncStreamCipher, NewCipher},
@@ -21,9 +24,25 @@ use aes::{
 use cfb8::Cfb8;
 use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 
+use crate::error::{Error, Res};
+
 pub mod reader;
 pub mod writer;
 
+/// Runs `decode`, which reads a packet. The readers panic on a short or
+/// malformed packet, so the panic is turned into an error for this bot instead
+/// of taking down every bot.
+pub fn decode<T>(decode: impl FnOnce() -> T) -> Res<T> {
+    catch_unwind(AssertUnwindSafe(decode)).map_err(|payload| {
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|reason| reason.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Error::Malformed(reason)
+    })
+}
+
 type AesCfb8 = Cfb8<Aes128>;
 
 /// https://github.com/RustCrypto/block-ciphers/issues/28
@@ -61,10 +80,10 @@ impl ZLib {
     fn new(threshold: u32) -> ZLib {
         ZLib { threshold }
     }
-    pub fn decompress(&self, input: &[u8]) -> Vec<u8> {
+    pub fn decompress(&self, input: &[u8]) -> tokio::io::Result<Vec<u8>> {
         let mut buf = Vec::with_capacity((input.len() as f64 * EXPANSION) as usize);
-        ZlibDecoder::new(input).read_to_end(&mut buf).unwrap();
-        buf
+        ZlibDecoder::new(input).read_to_end(&mut buf)?;
+        Ok(buf)
     }
 
     pub fn compress(&self, input: &[u8]) -> tokio::io::Result<Vec<u8>> {
@@ -73,3 +92,53 @@ impl ZLib {
         e.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+
+    use swarm_bot_packets::{
+        read::ByteReader,
+        types::{VarInt, VarLong},
+    };
+    use tokio::runtime::Runtime;
+
+    use crate::{error::Error, protocol::io::decode};
+
+    #[test]
+    fn test_decode() {
+        // an i32 needs 4 bytes
+        let mut reader = ByteReader::new(vec![0x01]);
+        let res = decode(|| {
+            let _: i32 = reader.read();
+        });
+        assert!(matches!(res, Err(Error::Malformed(_))));
+
+        let mut reader = ByteReader::new(vec![0, 0, 0, 7]);
+        let value: i32 = decode(|| reader.read()).unwrap();
+        assert_eq!(value, 7);
+    }
+
+    #[test]
+    fn test_varint_too_long() {
+        let bytes = vec![0x80; 11];
+
+        let mut reader = ByteReader::new(bytes[..6].to_vec());
+        let res = decode(|| {
+            let _: VarInt = reader.read();
+        });
+        assert!(matches!(res, Err(Error::Malformed(_))));
+
+        let mut reader = ByteReader::new(bytes.clone());
+        let res = decode(|| {
+            let _: VarLong = reader.read();
+        });
+        assert!(matches!(res, Err(Error::Malformed(_))));
+
+        let mut slice = &bytes[..6];
+        let res = Runtime::new()
+            .unwrap()
+            .block_on(VarInt::read_async(Pin::new(&mut slice)));
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
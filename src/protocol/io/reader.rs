@@ -28,11 +28,18 @@ use swarm_bot_packets::{
 };
 
 use crate::{
-    error::{Error::WrongPacket, Res},
-    protocol::io::{Aes, ZLib},
+    error::{
+        err,
+        Error::{Simple, WrongPacket},
+        Res,
+    },
+    protocol::io::{decode, Aes, ZLib},
     types::PacketData,
 };
 
+/// The largest packet the protocol allows (a 3 byte VarInt)
+const MAX_PACKET_LEN: i32 = (1 << 21) - 1;
+
 pub struct PacketReader {
     reader: EncryptedReader,
     compression: Option<ZLib>,
@@ -92,8 +99,11 @@ impl PacketReader {
 
         // ignore 0-sized packets
         loop {
-            let len = VarInt::read_async(Pin::new(&mut self.reader)).await;
+            let len = VarInt::read_async(Pin::new(&mut self.reader)).await?;
             let len = len.0;
+            if !(0..=MAX_PACKET_LEN).contains(&len) {
+                return Err(Simple(format!("invalid packet length {}", len)));
+            }
             if len != 0 {
                 pkt_len = len as usize;
                 break;
@@ -102,22 +112,25 @@ impl PacketReader {
 
         // the raw bytes with length determined by pkt_len
         let mut data = vec![0_u8; pkt_len];
-        self.reader.read_exact(&mut data).await.unwrap();
+        self.reader.read_exact(&mut data).await?;
 
-        let mut reader = ByteReader::new(data);
+        let compression = self.compression;
+        decode(move || {
+            let mut reader = ByteReader::new(data);
 
-        let data = match self.compression.as_ref() {
-            None => packet_reader(&mut reader, pkt_len),
-            Some(zlib) => packet_reader_compressed(&mut reader, zlib, pkt_len),
-        };
+            let data = match compression.as_ref() {
+                None => packet_reader(&mut reader, pkt_len),
+                Some(zlib) => packet_reader_compressed(&mut reader, zlib, pkt_len)?,
+            };
 
-        let mut reader = ByteReader::new(data);
-        let VarInt(id) = reader.read();
+            let mut reader = ByteReader::new(data);
+            let VarInt(id) = reader.read();
 
-        Ok(PacketData {
-            id: id as u32,
-            reader,
-        })
+            Ok(PacketData {
+                id: id as u32,
+                reader,
+            })
+        })?
     }
 
     pub async fn read_exact_packet<T>(&mut self) -> Res<T>
@@ -140,23 +153,24 @@ impl PacketReader {
                 actual: id,
             })
         } else {
-            let packet = T::read_from_bytes(&mut reader);
-            Ok(packet)
+            decode(|| T::read_from_bytes(&mut reader))
         }
     }
 }
 
-fn packet_reader_compressed(reader: &mut ByteReader, zlib: &ZLib, len: usize) -> Vec<u8> {
+fn packet_reader_compressed(reader: &mut ByteReader, zlib: &ZLib, len: usize) -> Res<Vec<u8>> {
     let data: LenRead<VarInt> = reader.read_with_len();
 
-    let len_left = len - data.len;
+    let len_left = len
+        .checked_sub(data.len)
+        .ok_or_else(|| err("compressed packet is shorter than its header"))?;
 
     let RawVec(inner) = reader.read_like(&len_left);
 
     if data.value.0 == 0 {
-        inner
+        Ok(inner)
     } else {
-        zlib.decompress(&inner)
+        Ok(zlib.decompress(&inner)?)
     }
 }
 
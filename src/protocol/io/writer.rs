@@ -78,7 +78,17 @@ impl PacketWriteChannel {
     pub fn write<T: Packet + ByteWritable>(&mut self, packet: T) {
         let data = data(packet, &self.compression);
 
-        self.tx.send(data).unwrap();
+        // the socket closed. The reader notices this too
+        let _ = self.tx.send(data);
+    }
+
+    /// A channel which drops everything written to it
+    pub fn discard() -> PacketWriteChannel {
+        let (tx, _) = tokio::sync::mpsc::unbounded_channel();
+        PacketWriteChannel {
+            tx,
+            compression: None,
+        }
     }
 }
 
@@ -103,7 +113,10 @@ impl PacketWriter {
 
         tokio::task::spawn_local(async move {
             while let Some(mut elem) = rx.recv().await {
-                writer.write_all(&mut elem).await.unwrap();
+                // the socket closed. The reader notices this too
+                if writer.write_all(&mut elem).await.is_err() {
+                    return;
+                }
             }
         });
 
@@ -160,10 +173,16 @@ impl ByteWritableLike for CompletePacket {
                         .write(data);
                 } else {
                     let data: RawVec = zlib.compress(&data.inner()).unwrap().into();
-                    let compressed_len: VarInt = (data.len() as i32 + uncompressed_len).into();
+
+                    // the packet length counts the bytes of the data length, not its value
+                    let mut data_len = ByteWriter::new();
+                    data_len.write(VarInt(uncompressed_len));
+                    let data_len = data_len.freeze();
+
+                    let packet_len = VarInt((data_len.len() + data.len()) as i32);
                     writer
-                        .write(compressed_len)
-                        .write(VarInt(uncompressed_len))
+                        .write(packet_len)
+                        .write(data_len.as_slice())
                         .write(data);
                 }
             }
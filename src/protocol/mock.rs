@@ -0,0 +1,868 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A local 1.12.2 server for integration tests. It logs bots in, sends them
+//! the chunks of a [`WorldBlocks`] and applies the blocks they dig and place
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use interfaces::types::{BlockLocation, BlockState};
+use rand::rngs::OsRng;
+use rsa::{PaddingScheme, PublicKeyParts, RsaPrivateKey};
+use swarm_bot_packets::types::{Packet, VarInt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::spawn_local,
+};
+
+use crate::{
+    bootstrap::{
+        mojang::{MojangApi, YggdrasilServers},
+        offline::{offline_user, offline_uuid},
+        Address, Connection,
+    },
+    error::{err, Error::Simple, Res},
+    protocol::{
+        encrypt::rand_bits,
+        io::{
+            reader::PacketReader,
+            writer::{PacketWriteChannel, PacketWriter},
+        },
+        v340::clientbound::EncryptionRequest,
+        Face,
+    },
+    schematic::Schematic,
+    storage::blocks::WorldBlocks,
+    types::{Direction, Location, PacketData},
+};
+
+/// The dig status of a finished block
+const DIG_FINISHED: i32 = 2;
+
+pub struct MockOptions {
+    /// the compression threshold. Packets are not compressed if None
+    pub compression: Option<u32>,
+
+    /// encrypt connections. Bots join through a session server which accepts
+    /// everyone (see [`MockServer::mojang`])
+    pub encryption: bool,
+
+    /// where bots spawn
+    pub spawn: Location,
+
+    /// the mock has no inventories so every placed block is this
+    pub place: BlockState,
+}
+
+impl Default for MockOptions {
+    fn default() -> Self {
+        Self {
+            compression: None,
+            encryption: false,
+            spawn: Location::new(0.5, 1.0, 0.5),
+            place: BlockState::STONE,
+        }
+    }
+}
+
+struct World {
+    blocks: WorldBlocks,
+
+    /// the last location each player sent, by username
+    players: HashMap<String, Location>,
+
+    /// every logged in player by username. Block changes are sent to all of
+    /// them
+    clients: HashMap<String, PacketWriteChannel>,
+
+    next_entity_id: u32,
+}
+
+impl World {
+    fn set_block(&mut self, location: BlockLocation, state: BlockState) {
+        self.blocks.set_block(location, state);
+        for client in self.clients.values_mut() {
+            client.write(clientbound::BlockChange {
+                location,
+                block_id: VarInt(state.0 as i32),
+            });
+        }
+    }
+
+    fn process(&mut self, username: &str, mut data: PacketData, options: &MockOptions) {
+        use serverbound::*;
+        match data.id {
+            PlayerPosition::ID => {
+                let PlayerPosition { location, .. } = data.read();
+                self.players.insert(username.to_string(), location);
+            }
+            PlayerPositionAndRotation::ID => {
+                let PlayerPositionAndRotation { location, .. } = data.read();
+                self.players.insert(username.to_string(), location);
+            }
+            PlayerDig::ID => {
+                let PlayerDig {
+                    status, location, ..
+                } = data.read();
+                if status.0 == DIG_FINISHED {
+                    self.set_block(location, BlockState::AIR);
+                }
+            }
+            PlaceBlock::ID => {
+                let PlaceBlock { location, face, .. } = data.read();
+
+                // -1 is using an item instead of placing
+                if (0..6).contains(&face.0) {
+                    let against = Face::from(face.0 as u8).change();
+                    self.set_block(location + against, options.place);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Shared {
+    options: MockOptions,
+    key: Option<RsaPrivateKey>,
+    world: RefCell<World>,
+}
+
+pub struct MockServer {
+    pub address: Address,
+
+    /// the session server url if connections are encrypted
+    session: Option<String>,
+    shared: Rc<Shared>,
+}
+
+impl MockServer {
+    /// Starts listening on a free localhost port. Must be called in a
+    /// [`tokio::task::LocalSet`]
+    pub async fn start(blocks: WorldBlocks, options: MockOptions) -> Res<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        let (key, session) = if options.encryption {
+            let key = RsaPrivateKey::new(&mut OsRng, 1024)
+                .map_err(|e| Simple(format!("could not generate key: {}", e)))?;
+            (Some(key), Some(session_server().await?))
+        } else {
+            (None, None)
+        };
+
+        let shared = Rc::new(Shared {
+            options,
+            key,
+            world: RefCell::new(World {
+                blocks,
+                players: HashMap::new(),
+                clients: HashMap::new(),
+                next_entity_id: 1,
+            }),
+        });
+
+        {
+            let shared = shared.clone();
+            spawn_local(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let shared = shared.clone();
+                    spawn_local(async move {
+                        if let Err(e) = serve(stream, &shared).await {
+                            println!("mock server: {}", e);
+                        }
+                    });
+                }
+            });
+        }
+
+        Ok(MockServer {
+            address: Address {
+                host: "127.0.0.1".to_string(),
+                port,
+            },
+            session,
+            shared,
+        })
+    }
+
+    /// A server with the blocks of a schematic
+    pub async fn schematic(schematic: &Schematic, options: MockOptions) -> Res<MockServer> {
+        let mut blocks = WorldBlocks::default();
+        blocks.paste(schematic);
+        MockServer::start(blocks, options).await
+    }
+
+    /// A client which joins through the session server of the mock
+    pub fn mojang(&self) -> MojangApi {
+        match &self.session {
+            None => MojangApi::default(),
+            Some(session) => MojangApi::default().servers(YggdrasilServers {
+                auth: session.clone(),
+                session: session.clone(),
+            }),
+        }
+    }
+
+    /// Connects an offline user to the mock
+    pub async fn connect(&self, username: &str) -> Res<Connection> {
+        let stream = TcpStream::connect((self.address.host.as_str(), self.address.port)).await?;
+        let (read, write) = stream.into_split();
+
+        Ok(Connection {
+            user: offline_user(username.to_string()),
+            address: self.address.clone(),
+            mojang: self.mojang(),
+            proxy: None,
+            read,
+            write,
+        })
+    }
+
+    pub fn block(&self, location: BlockLocation) -> Option<BlockState> {
+        let world = self.shared.world.borrow();
+        let block = world.blocks.get_block(location)?;
+        Some(block.as_real())
+    }
+
+    /// Moves a player to another server with the blocks `blocks` like a
+    /// BungeeCord proxy does: a respawn in another dimension, a respawn back
+    /// and the new chunks
+    pub fn switch(&self, username: &str, blocks: &WorldBlocks) {
+        use clientbound::*;
+
+        let mut world = self.shared.world.borrow_mut();
+        let client = world.clients.get_mut(username).unwrap();
+
+        for dimension in [-1, 0] {
+            client.write(Respawn {
+                dimension,
+                difficulty: 0,
+                game_mode: 0,
+                level_type: "flat".to_string(),
+            });
+        }
+        for (&location, data) in blocks.real_chunks() {
+            client.write(Chunk::new(location, data));
+        }
+    }
+
+    /// The last location the player sent
+    pub fn location(&self, username: &str) -> Option<Location> {
+        let world = self.shared.world.borrow();
+        world.players.get(username).copied()
+    }
+}
+
+/// Logs in a player and applies its packets until it disconnects
+async fn serve(stream: TcpStream, shared: &Shared) -> Res {
+    use clientbound::*;
+    use serverbound::*;
+
+    let (read, write) = stream.into_split();
+    let mut reader = PacketReader::from(read);
+    let mut writer = PacketWriter::from(write);
+
+    let Handshake {
+        protocol_version,
+        next_state,
+        ..
+    } = reader.read_exact_packet().await?;
+
+    // only logins are supported, not status pings
+    if next_state.0 != 2 {
+        return Ok(());
+    }
+
+    let LoginStart { username } = reader.read_exact_packet().await?;
+
+    if protocol_version.0 != 340 {
+        let reason = format!(r#"{{"text":"protocol {} is not 340"}}"#, protocol_version.0);
+        writer.write(Disconnect { reason }).await?;
+        return Ok(());
+    }
+
+    if let Some(key) = &shared.key {
+        // rsa_der reads these as signed. The leading zero keeps the modulus positive
+        let n = [&[0], key.n().to_bytes_be().as_slice()].concat();
+        let public_key_der = rsa_der::public_key_to_der(&n, &key.e().to_bytes_be());
+        let verify_token = rand_bits().to_vec();
+
+        writer
+            .write(EncryptionRequest {
+                server_id: String::new(),
+                public_key_der,
+                verify_token: verify_token.clone(),
+            })
+            .await?;
+
+        let EncryptionResponse {
+            shared_secret,
+            verify_token: encrypted_verify,
+        } = reader.read_exact_packet().await?;
+
+        let decrypt = |data: &[u8]| {
+            key.decrypt(PaddingScheme::new_pkcs1v15_encrypt(), data)
+                .map_err(|e| Simple(format!("could not decrypt: {}", e)))
+        };
+
+        if decrypt(&encrypted_verify)? != verify_token {
+            return Err(err("wrong verify token"));
+        }
+
+        let shared_secret = decrypt(&shared_secret)?;
+        writer.encryption(&shared_secret);
+        reader.encryption(&shared_secret);
+    }
+
+    if let Some(threshold) = shared.options.compression {
+        writer
+            .write(SetCompression {
+                threshold: VarInt(threshold as i32),
+            })
+            .await?;
+        writer.compression(threshold);
+        reader.compression(threshold);
+    }
+
+    writer
+        .write(LoginSuccess {
+            uuid: hyphenated(&offline_uuid(&username).to_string()),
+            username: username.clone(),
+        })
+        .await?;
+
+    let (entity_id, chunks) = {
+        let mut world = shared.world.borrow_mut();
+        let entity_id = world.next_entity_id;
+        world.next_entity_id += 1;
+
+        let chunks: Vec<_> = world
+            .blocks
+            .real_chunks()
+            .map(|(&location, data)| Chunk::new(location, data))
+            .collect();
+
+        (entity_id, chunks)
+    };
+
+    writer
+        .write(JoinGame {
+            entity_id,
+            game_mode: 0,
+            dimension: 0,
+            difficulty: 0,
+            max_players: 20,
+            level_type: "flat".to_string(),
+            reduced_debug_info: false,
+        })
+        .await?;
+
+    for chunk in chunks {
+        writer.write(chunk).await?;
+    }
+
+    writer
+        .write(UpdateHealth {
+            health: 20.0,
+            food: VarInt(20),
+            food_saturation: 5.0,
+        })
+        .await?;
+
+    let spawn = shared.options.spawn;
+    writer
+        .write(PlayerPositionAndLook {
+            location: spawn,
+            direction: Direction::default(),
+            flags: 0,
+            teleport_id: VarInt(0),
+        })
+        .await?;
+
+    {
+        let mut world = shared.world.borrow_mut();
+        world.players.insert(username.clone(), spawn);
+        world
+            .clients
+            .insert(username.clone(), writer.into_channel());
+    }
+
+    loop {
+        let data = reader.read().await?;
+        let mut world = shared.world.borrow_mut();
+        world.process(&username, data, &shared.options);
+    }
+}
+
+/// `0123...` as `01234567-89ab-cdef-...`. 1.12 sends UUIDs this way
+fn hyphenated(uuid: &str) -> String {
+    format!(
+        "{}-{}-{}-{}-{}",
+        &uuid[..8],
+        &uuid[8..12],
+        &uuid[12..16],
+        &uuid[16..20],
+        &uuid[20..]
+    )
+}
+
+/// A session server which accepts every join
+async fn session_server() -> Res<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+
+    spawn_local(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            spawn_local(async move {
+                if let Err(e) = no_content(stream).await {
+                    println!("mock session server: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(url)
+}
+
+/// Reads an HTTP request and answers 204 No Content
+async fn no_content(mut stream: TcpStream) -> Res {
+    let mut request = Vec::new();
+    let mut buf = [0_u8; 1024];
+
+    loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+
+        let text = String::from_utf8_lossy(&request);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let body_len = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+
+            if request.len() >= end + 4 + body_len {
+                break;
+            }
+        }
+    }
+
+    stream
+        .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+        .await?;
+    Ok(())
+}
+
+mod serverbound {
+    use interfaces::types::BlockLocation;
+    use swarm_bot_packets::{types::VarInt, *};
+
+    use crate::types::{Direction, Location};
+
+    #[derive(Packet, Readable)]
+    #[packet(0x00, Handshake)]
+    pub struct Handshake {
+        pub protocol_version: VarInt,
+        pub host: String,
+        pub port: u16,
+        pub next_state: VarInt,
+    }
+
+    #[derive(Packet, Readable)]
+    #[packet(0x00, Login)]
+    pub struct LoginStart {
+        pub username: String,
+    }
+
+    #[derive(Packet, Readable)]
+    #[packet(0x01, Login)]
+    pub struct EncryptionResponse {
+        pub shared_secret: Vec<u8>,
+        pub verify_token: Vec<u8>,
+    }
+
+    #[derive(Packet, Readable)]
+    #[packet(0x0d, Play)]
+    pub struct PlayerPosition {
+        pub location: Location,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Readable)]
+    #[packet(0x0e, Play)]
+    pub struct PlayerPositionAndRotation {
+        pub location: Location,
+        pub direction: Direction,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Readable)]
+    #[packet(0x14, Play)]
+    pub struct PlayerDig {
+        pub status: VarInt,
+        pub location: BlockLocation,
+        pub face: u8,
+    }
+
+    #[derive(Packet, Readable)]
+    #[packet(0x1f, Play)]
+    pub struct PlaceBlock {
+        pub location: BlockLocation,
+        pub face: VarInt,
+        pub hand: VarInt,
+        pub cursor: (f32, f32, f32),
+    }
+}
+
+mod clientbound {
+    use interfaces::types::{BlockLocation, ChunkLocation};
+    use swarm_bot_packets::{
+        types::VarInt,
+        write::{ByteWritable, ByteWriter},
+        *,
+    };
+
+    use crate::{
+        storage::chunk::{ChunkData, HighMemoryChunkSection, Palette},
+        types::{Direction, Location},
+    };
+
+    #[derive(Packet, Writable)]
+    #[packet(0x00, Login)]
+    pub struct Disconnect {
+        pub reason: String,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x02, Login)]
+    pub struct LoginSuccess {
+        pub uuid: String,
+        pub username: String,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x03, Login)]
+    pub struct SetCompression {
+        pub threshold: VarInt,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x23, Play)]
+    pub struct JoinGame {
+        pub entity_id: u32,
+        pub game_mode: u8,
+        pub dimension: i32,
+        pub difficulty: u8,
+        pub max_players: u8,
+        pub level_type: String,
+        pub reduced_debug_info: bool,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x35, Play)]
+    pub struct Respawn {
+        pub dimension: i32,
+        pub difficulty: u8,
+        pub game_mode: u8,
+        pub level_type: String,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x2f, Play)]
+    pub struct PlayerPositionAndLook {
+        pub location: Location,
+        pub direction: Direction,
+
+        /// which values are relative. 0 is all absolute
+        pub flags: u8,
+        pub teleport_id: VarInt,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x41, Play)]
+    pub struct UpdateHealth {
+        pub health: f32,
+        pub food: VarInt,
+        pub food_saturation: f32,
+    }
+
+    #[derive(Packet, Writable)]
+    #[packet(0x0b, Play)]
+    pub struct BlockChange {
+        pub location: BlockLocation,
+        pub block_id: VarInt,
+    }
+
+    /// A full chunk column with every non-empty section
+    #[derive(Packet)]
+    #[packet(0x20, Play)]
+    pub struct Chunk {
+        location: ChunkLocation,
+        primary_bitmask: i32,
+
+        /// the sections and biomes
+        data: Vec<u8>,
+    }
+
+    impl Chunk {
+        pub fn new(location: ChunkLocation, column: &ChunkData<HighMemoryChunkSection>) -> Chunk {
+            let mut primary_bitmask = 0;
+            let mut data = ByteWriter::new();
+
+            for (idx, section) in column.sections.iter().enumerate() {
+                let section = match section {
+                    None => continue,
+                    Some(section) => section,
+                };
+
+                primary_bitmask |= 1 << idx;
+
+                // our palettes can use fewer than the 4 bits per block the protocol needs
+                let palette = Palette::from_states(&section.palette.all_states());
+
+                data.write(palette.bits_per_block());
+                match palette.id_to_state() {
+                    Some(states) => {
+                        data.write(VarInt(states.len() as i32));
+                        for state in states {
+                            data.write(VarInt(state.0 as i32));
+                        }
+                    }
+                    None => {
+                        data.write(VarInt(0));
+                    }
+                }
+
+                data.write(VarInt(palette.storage().len() as i32));
+                for &long in palette.storage() {
+                    data.write(long);
+                }
+
+                // block light and sky light at full brightness
+                data.write(&[0xff_u8; 2048][..]).write(&[0xff_u8; 2048][..]);
+            }
+
+            // plains
+            data.write(&[1_u8; 256][..]);
+
+            Chunk {
+                location,
+                primary_bitmask,
+                data: data.freeze(),
+            }
+        }
+    }
+
+    impl ByteWritable for Chunk {
+        fn write_to_bytes(self, writer: &mut ByteWriter) {
+            let ChunkLocation(x, z) = self.location;
+            writer
+                .write(x)
+                .write(z)
+                .write(true)
+                .write(VarInt(self.primary_bitmask))
+                .write(self.data)
+                .write(VarInt(0)); // block entities
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, future::Future, time::Duration};
+
+    use interfaces::types::{BlockLocation, BlockState};
+    use tokio::{runtime::Runtime, task::LocalSet};
+
+    use crate::{
+        bootstrap::config::BotConfig,
+        client::{
+            bot::ActionState,
+            processor::SimpleInterfaceIn,
+            state::{global::GlobalState, local::LocalState},
+        },
+        protocol::{
+            mock::{MockOptions, MockServer},
+            v340::{self, EventQueue340, Interface340},
+            EventQueue, Face, InterfaceOut, Login, Mine, Minecraft,
+        },
+        schematic::Schematic,
+        storage::blocks::WorldBlocks,
+        types::Location,
+    };
+
+    /// A bot logged in to the mock with the state the runner would give it
+    struct TestBot {
+        login: Login<EventQueue340, Interface340>,
+        local: LocalState,
+        global: GlobalState,
+    }
+
+    impl TestBot {
+        async fn join(server: &MockServer) -> TestBot {
+            let connection = server.connect("Bot0").await.unwrap();
+            let login = v340::Protocol::login(connection).await.unwrap();
+            let local = LocalState::new(0, login.info.clone(), BotConfig::default());
+
+            TestBot {
+                login,
+                local,
+                global: GlobalState::init(),
+            }
+        }
+
+        /// Flushes the queue until `done` or a few seconds pass
+        async fn flush_until(&mut self, mut done: impl FnMut(&TestBot) -> bool) -> bool {
+            let mut actions = ActionState::default();
+            for _ in 0..100 {
+                let mut processor = SimpleInterfaceIn::new(
+                    &mut self.local,
+                    &mut actions,
+                    &mut self.global,
+                    &mut self.login.out,
+                );
+                self.login.queue.flush(&mut processor);
+                if done(self) {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            false
+        }
+
+        fn block(&self, location: BlockLocation) -> Option<BlockState> {
+            let block = self.global.blocks[self.local.world()].get_block(location)?;
+            Some(block.as_real())
+        }
+    }
+
+    fn block_on(future: impl Future<Output = ()>) {
+        let rt = Runtime::new().unwrap();
+        LocalSet::new().block_on(&rt, future);
+    }
+
+    /// Logs in, receives the world and changes blocks on the server
+    fn play(options: MockOptions) {
+        block_on(async move {
+            let server = MockServer::start(WorldBlocks::flat(), options)
+                .await
+                .unwrap();
+            let mut bot = TestBot::join(&server).await;
+            assert_eq!(bot.login.info.username, "Bot0");
+
+            let origin = BlockLocation::new(0, 0, 0);
+            let spawned = bot
+                .flush_until(|bot| {
+                    // the bot is teleported slightly above the spawn
+                    let spawn = Location::new(0.5, 1.0, 0.5);
+                    bot.local.physics.location().dist2(spawn) < 0.01
+                        && bot.block(origin) == Some(BlockState::STONE)
+                })
+                .await;
+            assert!(spawned);
+
+            let out = &mut bot.login.out;
+            out.mine(origin, Mine::Finished, Face::PosY);
+            out.place_block(BlockLocation::new(5, 0, 5), Face::PosY);
+            out.teleport(Location::new(3.5, 1.0, 3.5));
+
+            let changed = bot
+                .flush_until(|bot| bot.block(origin) == Some(BlockState::AIR))
+                .await;
+            assert!(changed);
+
+            assert_eq!(server.block(origin), Some(BlockState::AIR));
+            assert_eq!(
+                server.block(BlockLocation::new(5, 1, 5)),
+                Some(BlockState::STONE)
+            );
+            assert_eq!(server.location("Bot0"), Some(Location::new(3.5, 1.0, 3.5)));
+        });
+    }
+
+    #[test]
+    fn test_server_switch() {
+        block_on(async move {
+            let server = MockServer::start(WorldBlocks::flat(), MockOptions::default())
+                .await
+                .unwrap();
+            let mut bot = TestBot::join(&server).await;
+
+            let origin = BlockLocation::new(0, 0, 0);
+            let spawned = bot
+                .flush_until(|bot| bot.block(origin) == Some(BlockState::STONE))
+                .await;
+            assert!(spawned);
+
+            // the other server only has a block far away
+            let mut other = WorldBlocks::default();
+            let far = BlockLocation::new(1000, 0, 1000);
+            other.set_block(far, BlockState::STONE);
+            server.switch("Bot0", &other);
+
+            let switched = bot
+                .flush_until(|bot| bot.block(far) == Some(BlockState::STONE))
+                .await;
+            assert!(switched);
+            assert_eq!(bot.block(origin), None);
+        });
+    }
+
+    #[test]
+    fn test_mock_server() {
+        play(MockOptions::default());
+    }
+
+    #[test]
+    fn test_mock_server_encrypted() {
+        play(MockOptions {
+            compression: Some(256),
+            encryption: true,
+            ..MockOptions::default()
+        });
+    }
+
+    #[test]
+    fn test_mock_server_schematic() {
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .open("test-data/parkour.schematic")
+            .unwrap();
+        let schematic = Schematic::load(&mut reader);
+
+        block_on(async move {
+            let server = MockServer::schematic(&schematic, MockOptions::default())
+                .await
+                .unwrap();
+            let mut bot = TestBot::join(&server).await;
+
+            let received = bot
+                .flush_until(|bot| {
+                    schematic
+                        .blocks()
+                        .all(|(location, state)| bot.block(location) == Some(state))
+                })
+                .await;
+            assert!(received);
+        });
+    }
+}
@@ -18,18 +18,24 @@ use swarm_bot_packets::types::UUID;
 use crate::{
     bootstrap::Connection,
     client::{
-        pathfind::moves::CardinalDirection, processor::InterfaceIn,
+        commands::BotCommand, pathfind::moves::CardinalDirection, processor::InterfaceIn,
         state::local::inventory::ItemStack,
     },
     error::Res,
-    types::{Direction, Location},
+    protocol::capture::Capture,
+    types::{Direction, Location, PacketData},
 };
 
+pub mod capture;
+pub mod status;
 pub mod v340;
+pub mod v754;
 
 mod encrypt;
 mod io;
-mod transform;
+#[cfg(test)]
+pub mod mock;
+pub mod transform;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[allow(dead_code)]
@@ -146,14 +152,30 @@ pub trait InterfaceOut {
 pub trait Minecraft: Sized {
     type Queue: EventQueue;
     type Interface: InterfaceOut;
+
+    /// the protocol version this implements
+    const PROTOCOL: u32;
+
     async fn login(conn: Connection) -> Res<Login<Self::Queue, Self::Interface>>;
+
+    /// a queue fed by `rx` instead of a socket. Everything sent to the
+    /// interface is dropped
+    fn offline(
+        rx: std::sync::mpsc::Receiver<Res<PacketData>>,
+    ) -> Res<(Self::Queue, Self::Interface)>;
 }
 
 pub trait EventQueue {
     fn flush(&mut self, processor: &mut impl InterfaceIn);
+
+    /// record every packet received from now on
+    fn capture(&mut self, capture: Capture);
+
+    /// record a command given to the bot if it is captured
+    fn record_command(&mut self, command: &BotCommand);
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ClientInfo {
     pub username: String,
     pub uuid: UUID,
@@ -0,0 +1,213 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The server list ping (the Status state). This is the same for every
+//! protocol version.
+
+use std::{
+    fmt::{Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+use swarm_bot_packets::{
+    types::VarInt,
+    write::{ByteWritable, ByteWriter},
+    *,
+};
+
+use crate::{
+    bootstrap::{tunnel, Address},
+    error::{err, Res},
+    protocol::{
+        io::{reader::PacketReader, writer::PacketWriter},
+        v340::serverbound::{Handshake, HandshakeNextState},
+    },
+};
+
+/// How long we wait for a server to answer a ping
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Packet)]
+#[packet(0x00, Status)]
+struct Request;
+
+impl ByteWritable for Request {
+    fn write_to_bytes(self, _writer: &mut ByteWriter) {}
+}
+
+#[derive(Packet, Writable)]
+#[packet(0x01, Status)]
+struct Ping {
+    payload: u64,
+}
+
+#[derive(Packet, Readable)]
+#[packet(0x00, Status)]
+struct Response {
+    json: String,
+}
+
+#[derive(Packet, Readable)]
+#[packet(0x01, Status)]
+struct Pong {
+    payload: u64,
+}
+
+#[derive(Deserialize)]
+struct RawVersion {
+    name: String,
+    protocol: u32,
+}
+
+#[derive(Deserialize)]
+struct RawPlayers {
+    max: u32,
+    online: u32,
+}
+
+#[derive(Deserialize)]
+struct RawStatus {
+    version: RawVersion,
+    players: Option<RawPlayers>,
+    description: Option<Value>,
+}
+
+/// What a server reports in the server list
+#[derive(Debug)]
+pub struct ServerStatus {
+    pub motd: String,
+    pub version: String,
+    pub protocol: u32,
+    pub online: u32,
+    pub max: u32,
+    pub latency: Duration,
+}
+
+impl Display for ServerStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}\nversion {} (protocol {})\nplayers {}/{}\nlatency {}ms",
+            self.motd,
+            self.version,
+            self.protocol,
+            self.online,
+            self.max,
+            self.latency.as_millis()
+        ))
+    }
+}
+
+/// The plain text of a chat component (or a plain string)
+fn chat_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Object(component) => {
+            let mut text = component
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+
+            if let Some(Value::Array(extra)) = component.get("extra") {
+                for part in extra {
+                    text.push_str(&chat_text(part));
+                }
+            }
+            text
+        }
+        Value::Array(parts) => parts.iter().map(chat_text).collect(),
+        _ => String::new(),
+    }
+}
+
+fn parse_status(json: &str, latency: Duration) -> Res<ServerStatus> {
+    let RawStatus {
+        version,
+        players,
+        description,
+    } = serde_json::from_str(json)?;
+
+    let (online, max) = players.map_or((0, 0), |players| (players.online, players.max));
+
+    Ok(ServerStatus {
+        motd: description.as_ref().map(chat_text).unwrap_or_default(),
+        version: version.name,
+        protocol: version.protocol,
+        online,
+        max,
+        latency,
+    })
+}
+
+async fn ping_inner(address: &Address) -> Res<ServerStatus> {
+    let (read, write) = tunnel::connect(address, None).await?.into_split();
+
+    let mut reader = PacketReader::from(read);
+    let mut writer = PacketWriter::from(write);
+
+    writer
+        .write(Handshake {
+            // -1 as we do not know which version to use yet
+            protocol_version: VarInt(-1),
+            host: address.host.clone(),
+            port: address.port,
+            next_state: HandshakeNextState::Status,
+        })
+        .await?;
+
+    writer.write(Request).await?;
+    let Response { json } = reader.read_exact_packet().await?;
+
+    let start = Instant::now();
+    writer.write(Ping { payload: 0 }).await?;
+    let Pong { .. } = reader.read_exact_packet().await?;
+    let latency = start.elapsed();
+
+    parse_status(&json, latency)
+}
+
+/// Pings the server like the server list does
+pub async fn ping(address: &Address) -> Res<ServerStatus> {
+    tokio::time::timeout(PING_TIMEOUT, ping_inner(address))
+        .await
+        .map_err(|_| err("the server did not answer the ping in time"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::protocol::status::parse_status;
+
+    #[test]
+    fn test_parse_status() {
+        let json = r#"{
+            "version": {"name": "1.12.2", "protocol": 340},
+            "players": {"max": 20, "online": 3, "sample": []},
+            "description": {"text": "A ", "extra": [{"text": "Minecraft"}, " Server"]}
+        }"#;
+
+        let status = parse_status(json, Duration::from_millis(12)).unwrap();
+        assert_eq!(status.motd, "A Minecraft Server");
+        assert_eq!(status.protocol, 340);
+        assert_eq!((status.online, status.max), (3, 20));
+
+        let plain = r#"{"version": {"name": "1.16.5", "protocol": 754}, "description": "hi"}"#;
+        let status = parse_status(plain, Duration::default()).unwrap();
+        assert_eq!(status.motd, "hi");
+        assert_eq!(status.protocol, 754);
+    }
+}
@@ -11,3 +11,608 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Translates the block states and item ids of each protocol into the
+//! internal block model ([`BlockData`]) so nothing past the protocol cares
+//! which version the server is
+
+use std::{collections::HashMap, fs::File, lazy::SyncOnceCell};
+
+use interfaces::types::{block_data::BlockData, BlockKind, BlockState};
+use serde::Deserialize;
+
+use crate::error::Res;
+
+/// minecraft-data `blocks.json` and `items.json` of 1.16.5
+pub const BLOCKS_PATH: &str = "blocks_754.json";
+pub const ITEMS_PATH: &str = "items_754.json";
+
+/// wool, glass etc come in these colors. The index is the 1.12 metadata
+const COLORS: [&str; 16] = [
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+/// colored 1.16 blocks (without the color) and the 1.12 block they are a
+/// variant of
+const COLORED: &[(&str, &str)] = &[
+    ("wool", "wool"),
+    ("carpet", "carpet"),
+    ("stained_glass", "stained_glass"),
+    ("stained_glass_pane", "stained_glass_pane"),
+    ("terracotta", "stained_hardened_clay"),
+    ("concrete", "concrete"),
+    ("concrete_powder", "concrete_powder"),
+];
+
+/// 1.16 names which are not the 1.12 name
+const RENAMED: &[(&str, &str, u16)] = &[
+    ("cave_air", "air", 0),
+    ("void_air", "air", 0),
+    ("granite", "stone", 1),
+    ("polished_granite", "stone", 2),
+    ("diorite", "stone", 3),
+    ("polished_diorite", "stone", 4),
+    ("andesite", "stone", 5),
+    ("polished_andesite", "stone", 6),
+    ("grass_block", "grass", 0),
+    ("coarse_dirt", "dirt", 1),
+    ("podzol", "dirt", 2),
+    ("oak_planks", "planks", 0),
+    ("spruce_planks", "planks", 1),
+    ("birch_planks", "planks", 2),
+    ("jungle_planks", "planks", 3),
+    ("acacia_planks", "planks", 4),
+    ("dark_oak_planks", "planks", 5),
+    ("red_sand", "sand", 1),
+    ("oak_log", "log", 0),
+    ("spruce_log", "log", 1),
+    ("birch_log", "log", 2),
+    ("jungle_log", "log", 3),
+    ("acacia_log", "log2", 0),
+    ("dark_oak_log", "log2", 1),
+    ("oak_leaves", "leaves", 0),
+    ("spruce_leaves", "leaves", 1),
+    ("birch_leaves", "leaves", 2),
+    ("jungle_leaves", "leaves", 3),
+    ("acacia_leaves", "leaves2", 0),
+    ("dark_oak_leaves", "leaves2", 1),
+    ("cobweb", "web", 0),
+    ("grass", "tallgrass", 1),
+    ("fern", "tallgrass", 2),
+    ("dead_bush", "deadbush", 0),
+    ("dandelion", "yellow_flower", 0),
+    ("poppy", "red_flower", 0),
+    ("sugar_cane", "reeds", 0),
+    ("lily_pad", "waterlily", 0),
+    ("snow", "snow_layer", 0),
+    ("snow_block", "snow", 0),
+    ("terracotta", "hardened_clay", 0),
+    ("spawner", "mob_spawner", 0),
+    ("stone_bricks", "stonebrick", 0),
+    ("nether_quartz_ore", "quartz_ore", 0),
+    ("magma_block", "magma", 0),
+    ("slime_block", "slime", 0),
+    ("oak_door", "wooden_door", 0),
+    ("oak_trapdoor", "trapdoor", 0),
+    ("oak_fence", "fence", 0),
+    ("oak_fence_gate", "fence_gate", 0),
+    ("cobblestone_stairs", "stone_stairs", 0),
+];
+
+/// 1.16 slabs and the 1.12 slab, double slab and metadata they are
+const SLABS: &[(&str, &str, &str, u16)] = &[
+    ("oak_slab", "wooden_slab", "double_wooden_slab", 0),
+    ("spruce_slab", "wooden_slab", "double_wooden_slab", 1),
+    ("birch_slab", "wooden_slab", "double_wooden_slab", 2),
+    ("jungle_slab", "wooden_slab", "double_wooden_slab", 3),
+    ("acacia_slab", "wooden_slab", "double_wooden_slab", 4),
+    ("dark_oak_slab", "wooden_slab", "double_wooden_slab", 5),
+    ("smooth_stone_slab", "stone_slab", "double_stone_slab", 0),
+    ("sandstone_slab", "stone_slab", "double_stone_slab", 1),
+    ("petrified_oak_slab", "stone_slab", "double_stone_slab", 2),
+    ("cobblestone_slab", "stone_slab", "double_stone_slab", 3),
+    ("brick_slab", "stone_slab", "double_stone_slab", 4),
+    ("stone_brick_slab", "stone_slab", "double_stone_slab", 5),
+    ("nether_brick_slab", "stone_slab", "double_stone_slab", 6),
+    ("quartz_slab", "stone_slab", "double_stone_slab", 7),
+    ("red_sandstone_slab", "stone_slab2", "double_stone_slab2", 0),
+    ("purpur_slab", "purpur_slab", "purpur_double_slab", 0),
+];
+
+/// Blocks which are not full blocks. 1.12 keeps their properties in the
+/// metadata. 1.16 blocks of a shape which did not exist in 1.12 become the
+/// [`Shape::fallback`]
+#[derive(Copy, Clone, Debug)]
+enum Shape {
+    Door,
+    Trapdoor,
+    Fence,
+    FenceGate,
+    Slab,
+    Stairs,
+}
+
+impl Shape {
+    fn of(name: &str) -> Option<Shape> {
+        // trapdoor before door and fence_gate before fence
+        const SUFFIXES: [(&str, Shape); 6] = [
+            ("_trapdoor", Shape::Trapdoor),
+            ("_door", Shape::Door),
+            ("_fence_gate", Shape::FenceGate),
+            ("_fence", Shape::Fence),
+            ("_slab", Shape::Slab),
+            ("_stairs", Shape::Stairs),
+        ];
+
+        SUFFIXES
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|&(_, shape)| shape)
+    }
+
+    fn fallback(self) -> &'static str {
+        match self {
+            Shape::Door => "wooden_door",
+            Shape::Trapdoor => "trapdoor",
+            Shape::Fence => "fence",
+            Shape::FenceGate => "fence_gate",
+            Shape::Slab => "stone_slab",
+            Shape::Stairs => "stone_stairs",
+        }
+    }
+
+    /// The 1.12 metadata of the 1.16 properties. Slabs are in [`SLABS`]
+    fn meta(self, properties: &HashMap<&str, &str>) -> u16 {
+        let property = |name| properties.get(name).copied().unwrap_or_default();
+        let flag = |name, bit| if property(name) == "true" { bit } else { 0 };
+
+        // the order of the facings differs between blocks
+        let facing = |order: [&str; 4]| {
+            let facing = property("facing");
+            order.iter().position(|&f| f == facing).unwrap_or_default() as u16
+        };
+
+        match self {
+            Shape::Door if property("half") == "upper" => {
+                let hinge = if property("hinge") == "right" { 1 } else { 0 };
+                8 | hinge | flag("powered", 2)
+            }
+            Shape::Door => facing(["east", "south", "west", "north"]) | flag("open", 4),
+            Shape::Trapdoor => {
+                let top = if property("half") == "top" { 8 } else { 0 };
+                facing(["north", "south", "west", "east"]) | flag("open", 4) | top
+            }
+            Shape::FenceGate => {
+                facing(["south", "west", "north", "east"]) | flag("open", 4) | flag("powered", 8)
+            }
+            Shape::Stairs => {
+                let top = if property("half") == "top" { 4 } else { 0 };
+                facing(["east", "west", "south", "north"]) | top
+            }
+            // fences connect to the blocks next to them
+            Shape::Fence | Shape::Slab => 0,
+        }
+    }
+}
+
+/// Translates between the ids of a protocol and the internal ones
+pub trait Translation {
+    fn state(&self, id: u32) -> BlockState;
+
+    /// the internal kind and damage of an item id
+    fn item(&self, id: u32) -> (BlockKind, u16);
+
+    /// the item id of an internal item
+    fn item_id(&self, kind: BlockKind, damage: u16) -> u32;
+}
+
+/// 1.12. The internal ids are the 1.12 ids. Damage is sent separately
+pub struct Legacy;
+
+impl Translation for Legacy {
+    #[inline]
+    fn state(&self, id: u32) -> BlockState {
+        BlockState(id)
+    }
+
+    #[inline]
+    fn item(&self, id: u32) -> (BlockKind, u16) {
+        (BlockKind(id), 0)
+    }
+
+    #[inline]
+    fn item_id(&self, kind: BlockKind, _damage: u16) -> u32 {
+        kind.id()
+    }
+}
+
+/// 1.12 ids of items (not blocks) the bot uses
+const ITEMS: &[(&str, u32)] = &[
+    ("iron_shovel", 256),
+    ("iron_pickaxe", 257),
+    ("iron_axe", 258),
+    ("iron_sword", 267),
+    ("wooden_sword", 268),
+    ("wooden_shovel", 269),
+    ("wooden_pickaxe", 270),
+    ("wooden_axe", 271),
+    ("stone_sword", 272),
+    ("stone_shovel", 273),
+    ("stone_pickaxe", 274),
+    ("stone_axe", 275),
+    ("diamond_sword", 276),
+    ("diamond_shovel", 277),
+    ("diamond_pickaxe", 278),
+    ("diamond_axe", 279),
+    ("golden_sword", 283),
+    ("golden_shovel", 284),
+    ("golden_pickaxe", 285),
+    ("golden_axe", 286),
+    ("wooden_hoe", 290),
+    ("stone_hoe", 291),
+    ("iron_hoe", 292),
+    ("diamond_hoe", 293),
+    ("golden_hoe", 294),
+    ("bucket", 325),
+    ("water_bucket", 326),
+    ("lava_bucket", 327),
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FlatBlock {
+    name: String,
+    min_state_id: u32,
+    max_state_id: u32,
+    bounding_box: String,
+
+    /// the state ids count through the values of the last property first
+    #[serde(default)]
+    states: Vec<FlatProperty>,
+}
+
+#[derive(Deserialize)]
+struct FlatProperty {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    num_values: u32,
+
+    /// bools have none
+    #[serde(default)]
+    values: Vec<String>,
+}
+
+impl FlatBlock {
+    /// The value of each property of state `state_id`
+    fn properties(&self, state_id: u32) -> HashMap<&str, &str> {
+        let mut offset = state_id - self.min_state_id;
+        let mut properties = HashMap::new();
+        for property in self.states.iter().rev().filter(|p| p.num_values > 0) {
+            let idx = (offset % property.num_values) as usize;
+            offset /= property.num_values;
+
+            let value = match property.kind.as_str() {
+                "bool" => ["true", "false"][idx % 2],
+                _ => property.values.get(idx).map_or("", String::as_str),
+            };
+            properties.insert(property.name.as_str(), value);
+        }
+        properties
+    }
+}
+
+/// minecraft-data `items.json`
+#[derive(Deserialize)]
+struct FlatItem {
+    id: u32,
+    name: String,
+}
+
+fn read<T: for<'de> Deserialize<'de>>(path: &str) -> Res<T> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// 1.13+ ids (the "flattening" of ids and metadata) of 1.16.5
+pub struct Flattening {
+    /// the 1.12 state of every 1.16 state id
+    states: Vec<BlockState>,
+
+    /// the 1.12 id and damage of every 1.16 item id
+    items: Vec<(BlockKind, u16)>,
+
+    item_ids: HashMap<(BlockKind, u16), u32>,
+}
+
+impl Flattening {
+    /// The translation of 1.16.5. It is read from the working directory the
+    /// first time
+    pub fn get() -> Res<&'static Flattening> {
+        static FLATTENING: SyncOnceCell<Flattening> = SyncOnceCell::new();
+        FLATTENING.get_or_try_init(|| {
+            Ok(Flattening::new(
+                read(BLOCKS_PATH)?,
+                read(ITEMS_PATH)?,
+                &BlockData::read()?,
+            ))
+        })
+    }
+
+    fn new(flat_blocks: Vec<FlatBlock>, flat_items: Vec<FlatItem>, data: &BlockData) -> Flattening {
+        let lookup = |name: &str| -> Option<(u32, u16)> {
+            if let Some(&(_, old_name, meta)) = RENAMED.iter().find(|(new, ..)| *new == name) {
+                return data.by_name(old_name).map(|id| (id, meta));
+            }
+
+            for (meta, color) in COLORS.iter().enumerate() {
+                let kind = match name.strip_prefix(color).and_then(|s| s.strip_prefix('_')) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                if let Some(&(_, old_name)) = COLORED.iter().find(|(new, _)| *new == kind) {
+                    return data.by_name(old_name).map(|id| (id, meta as u16));
+                }
+            }
+
+            if let Some(&(_, id)) = ITEMS.iter().find(|(item, _)| *item == name) {
+                return Some((id, 0));
+            }
+
+            data.by_name(name).map(|id| (id, 0))
+        };
+
+        let state_count = flat_blocks
+            .iter()
+            .map(|block| block.max_state_id + 1)
+            .max()
+            .unwrap_or_default();
+
+        let mut states = vec![BlockState::AIR; state_count as usize];
+        for block in &flat_blocks {
+            if let Some(shape) = Shape::of(&block.name) {
+                if let Some(shaped) = Self::shaped(block, shape, data, lookup(&block.name)) {
+                    for state_id in block.min_state_id..=block.max_state_id {
+                        states[state_id as usize] = shaped(&block.properties(state_id));
+                    }
+                    continue;
+                }
+            }
+
+            let (id, meta) = lookup(&block.name).unwrap_or_else(|| {
+                // we only need to know whether we can walk through it
+                match block.bounding_box.as_str() {
+                    "empty" => (0, 0),
+                    _ => (1, 0),
+                }
+            });
+
+            for state_id in block.min_state_id..=block.max_state_id {
+                // the only property of water and lava is the level. 0 is a source block
+                let level = state_id - block.min_state_id;
+                let state = match block.name.as_str() {
+                    "water" | "lava" if level != 0 => BlockState::from(id - 1, level as u16),
+                    _ => BlockState::from(id, meta),
+                };
+                states[state_id as usize] = state;
+            }
+        }
+
+        let item_count = flat_items
+            .iter()
+            .map(|item| item.id + 1)
+            .max()
+            .unwrap_or_default();
+
+        // items we do not know are air
+        let mut items = vec![(BlockKind(0), 0); item_count as usize];
+        let mut item_ids = HashMap::new();
+        for item in &flat_items {
+            if let Some((id, damage)) = lookup(&item.name) {
+                let kind = BlockKind(id);
+                items[item.id as usize] = (kind, damage);
+                item_ids.entry((kind, damage)).or_insert(item.id);
+            }
+        }
+
+        Flattening {
+            states,
+            items,
+            item_ids,
+        }
+    }
+
+    /// The 1.12 state of each state of `block`, which has the shape `shape`.
+    /// `known` is the 1.12 block of the same name
+    fn shaped(
+        block: &FlatBlock,
+        shape: Shape,
+        data: &BlockData,
+        known: Option<(u32, u16)>,
+    ) -> Option<impl Fn(&HashMap<&str, &str>) -> BlockState> {
+        let (id, double, meta) = match shape {
+            Shape::Slab => {
+                let &(_, single, double, meta) = SLABS
+                    .iter()
+                    .find(|(new, ..)| *new == block.name)
+                    .unwrap_or(&("", "stone_slab", "double_stone_slab", 0));
+                (data.by_name(single)?, data.by_name(double)?, meta)
+            }
+            _ => {
+                let id = match known {
+                    Some((id, _)) => id,
+                    None => data.by_name(shape.fallback())?,
+                };
+                (id, id, 0)
+            }
+        };
+
+        Some(move |properties: &HashMap<&str, &str>| match shape {
+            Shape::Slab => match properties.get("type").copied() {
+                Some("double") => BlockState::from(double, meta),
+                Some("top") => BlockState::from(id, meta | 8),
+                _ => BlockState::from(id, meta),
+            },
+            _ => BlockState::from(id, shape.meta(properties)),
+        })
+    }
+}
+
+impl Translation for Flattening {
+    fn state(&self, id: u32) -> BlockState {
+        self.states.get(id as usize).copied().unwrap_or_default()
+    }
+
+    fn item(&self, id: u32) -> (BlockKind, u16) {
+        self.items.get(id as usize).copied().unwrap_or_default()
+    }
+
+    fn item_id(&self, kind: BlockKind, damage: u16) -> u32 {
+        self.item_ids
+            .get(&(kind, damage))
+            .or_else(|| self.item_ids.get(&(kind, 0)))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use interfaces::types::{block_data::BlockData, BlockKind, BlockState};
+
+    use crate::protocol::transform::{Flattening, Translation};
+
+    #[test]
+    fn test_flattening() {
+        let flat_blocks = serde_json::from_str(
+            r#"[
+                {"name": "air", "minStateId": 0, "maxStateId": 0, "boundingBox": "empty"},
+                {"name": "granite", "minStateId": 1, "maxStateId": 1, "boundingBox": "block"},
+                {"name": "water", "minStateId": 2, "maxStateId": 17, "boundingBox": "empty"},
+                {"name": "orange_wool", "minStateId": 18, "maxStateId": 18, "boundingBox": "block"},
+                {"name": "crimson_nylium", "minStateId": 19, "maxStateId": 19, "boundingBox": "block"}
+            ]"#,
+        )
+        .unwrap();
+
+        let flat_items = serde_json::from_str(
+            r#"[
+                {"id": 0, "name": "air"},
+                {"id": 1, "name": "granite"},
+                {"id": 2, "name": "water_bucket"},
+                {"id": 3, "name": "netherite_ingot"}
+            ]"#,
+        )
+        .unwrap();
+
+        let blocks = serde_json::from_str(
+            r#"[
+                {"id": 0, "name": "air", "hardness": 0},
+                {"id": 1, "name": "stone", "hardness": 1.5},
+                {"id": 8, "name": "flowing_water", "hardness": 100},
+                {"id": 9, "name": "water", "hardness": 100},
+                {"id": 35, "name": "wool", "hardness": 0.8}
+            ]"#,
+        )
+        .unwrap();
+
+        let data = BlockData::new(blocks, Vec::new());
+        let flattening = Flattening::new(flat_blocks, flat_items, &data);
+
+        assert_eq!(flattening.state(0), BlockState::AIR);
+        assert_eq!(flattening.state(1), BlockState::from(1, 1));
+        assert_eq!(flattening.state(2), BlockState::from(9, 0));
+        assert_eq!(flattening.state(5), BlockState::from(8, 3));
+        assert_eq!(flattening.state(18), BlockState::from(35, 1));
+
+        // unknown solid blocks are stone
+        assert_eq!(flattening.state(19), BlockState::from(1, 0));
+
+        assert_eq!(flattening.item(1), (BlockKind(1), 1));
+        assert_eq!(flattening.item(2), (BlockKind(326), 0));
+        assert_eq!(flattening.item(3), (BlockKind(0), 0));
+
+        assert_eq!(flattening.item_id(BlockKind(326), 0), 2);
+        assert_eq!(flattening.item_id(BlockKind(1), 1), 1);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let flat_blocks = serde_json::from_str(
+            r#"[
+                {"name": "oak_door", "minStateId": 0, "maxStateId": 63, "boundingBox": "block", "states": [
+                    {"name": "facing", "type": "enum", "num_values": 4, "values": ["north", "south", "west", "east"]},
+                    {"name": "half", "type": "enum", "num_values": 2, "values": ["upper", "lower"]},
+                    {"name": "hinge", "type": "enum", "num_values": 2, "values": ["left", "right"]},
+                    {"name": "open", "type": "bool", "num_values": 2},
+                    {"name": "powered", "type": "bool", "num_values": 2}
+                ]},
+                {"name": "oak_fence", "minStateId": 64, "maxStateId": 95, "boundingBox": "block", "states": [
+                    {"name": "east", "type": "bool", "num_values": 2},
+                    {"name": "north", "type": "bool", "num_values": 2},
+                    {"name": "south", "type": "bool", "num_values": 2},
+                    {"name": "waterlogged", "type": "bool", "num_values": 2},
+                    {"name": "west", "type": "bool", "num_values": 2}
+                ]},
+                {"name": "oak_slab", "minStateId": 96, "maxStateId": 101, "boundingBox": "block", "states": [
+                    {"name": "type", "type": "enum", "num_values": 3, "values": ["top", "bottom", "double"]},
+                    {"name": "waterlogged", "type": "bool", "num_values": 2}
+                ]},
+                {"name": "blackstone_stairs", "minStateId": 102, "maxStateId": 181, "boundingBox": "block", "states": [
+                    {"name": "facing", "type": "enum", "num_values": 4, "values": ["north", "south", "west", "east"]},
+                    {"name": "half", "type": "enum", "num_values": 2, "values": ["top", "bottom"]},
+                    {"name": "shape", "type": "enum", "num_values": 5, "values": ["straight", "inner_left", "inner_right", "outer_left", "outer_right"]},
+                    {"name": "waterlogged", "type": "bool", "num_values": 2}
+                ]}
+            ]"#,
+        )
+        .unwrap();
+
+        let blocks = serde_json::from_str(
+            r#"[
+                {"id": 0, "name": "air", "hardness": 0},
+                {"id": 1, "name": "stone", "hardness": 1.5},
+                {"id": 64, "name": "wooden_door", "hardness": 3},
+                {"id": 67, "name": "stone_stairs", "hardness": 2},
+                {"id": 85, "name": "fence", "hardness": 2},
+                {"id": 125, "name": "double_wooden_slab", "hardness": 2},
+                {"id": 126, "name": "wooden_slab", "hardness": 2}
+            ]"#,
+        )
+        .unwrap();
+
+        let data = BlockData::new(blocks, Vec::new());
+        let flattening = Flattening::new(flat_blocks, Vec::new(), &data);
+
+        // the lower half facing north and open, the upper half with the hinge on the
+        // right
+        assert_eq!(flattening.state(9), BlockState::from(64, 7));
+        assert_eq!(flattening.state(7), BlockState::from(64, 9));
+
+        assert_eq!(flattening.state(64), BlockState::from(85, 0));
+        assert_eq!(flattening.state(95), BlockState::from(85, 0));
+
+        // a bottom, top and double slab
+        assert_eq!(flattening.state(99), BlockState::from(126, 0));
+        assert_eq!(flattening.state(96), BlockState::from(126, 8));
+        assert_eq!(flattening.state(100), BlockState::from(125, 0));
+
+        // stairs which did not exist in 1.12 facing east and upside down
+        assert_eq!(flattening.state(102 + 60), BlockState::from(67, 4));
+    }
+}
@@ -14,7 +14,7 @@
 
 use std::cmp::max;
 
-use interfaces::types::{BlockLocation, BlockState};
+use interfaces::types::BlockLocation;
 use itertools::Itertools;
 
 use swarm_bot_packets::{
@@ -24,6 +24,8 @@ use swarm_bot_packets::{
 };
 
 use crate::{
+    client::state::global::world_players::{self, Gamemode, PlayerUpdate, Property},
+    protocol::transform::{Legacy, Translation},
     storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette},
     types::{
         Chat, Dimension, Direction, DirectionOrigin, Location, LocationFloat, LocationOrigin,
@@ -122,6 +124,16 @@ pub struct PlayerProperty {
     pub signature: Option<String>,
 }
 
+impl From<PlayerProperty> for Property {
+    fn from(property: PlayerProperty) -> Self {
+        Self {
+            name: property.name,
+            value: property.value,
+            signature: property.signature,
+        }
+    }
+}
+
 impl ByteReadable for PlayerProperty {
     fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
         let name = byte_reader.read();
@@ -186,6 +198,29 @@ impl ByteReadableLike for PlayerListType {
         }
     }
 }
+impl PlayerListType {
+    pub fn into_update(self, uuid: u128) -> PlayerUpdate {
+        match self {
+            PlayerListType::AddPlayer(add) => PlayerUpdate::Add(world_players::Player {
+                name: add.name,
+                uuid,
+                gamemode: Gamemode::from_id(add.gamemode.into()),
+                ping: add.ping.into(),
+                display_name: add.display_name.map(|name| name.plain()),
+                properties: add.properties.into_iter().map(Property::from).collect(),
+            }),
+            PlayerListType::UpdateGamemode(id) => {
+                PlayerUpdate::Gamemode(Gamemode::from_id(id.into()))
+            }
+            PlayerListType::UpdateLatency(ping) => PlayerUpdate::Ping(ping.into()),
+            PlayerListType::UpdateDisplayName(name) => {
+                PlayerUpdate::DisplayName(name.map(|name| name.plain()))
+            }
+            PlayerListType::RemovePlayer => PlayerUpdate::Remove,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Player {
     pub uuid: UUID,
@@ -247,11 +282,128 @@ pub struct PlayerPositionAndLookRaw {
 
 pub mod entity {
     use swarm_bot_packets::{
+        read::{ByteReadable, ByteReader},
         types::{Angle, VarInt, UUID},
         *,
     };
 
-    use crate::types::{Location, ShortLoc};
+    use crate::{
+        storage::entities::{EntityFlags, EntityMetadata},
+        types::{Location, ShortLoc, Slot},
+    };
+
+    /// Entity metadata in the 1.12 format. Only the values of
+    /// [`EntityMetadata`] are kept
+    #[derive(Debug)]
+    pub struct MetadataValues(pub EntityMetadata);
+
+    impl ByteReadable for MetadataValues {
+        fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+            let mut metadata = EntityMetadata::default();
+            loop {
+                let index: u8 = byte_reader.read();
+                if index == 0xff {
+                    break;
+                }
+
+                let VarInt(kind) = byte_reader.read();
+                match (index, kind) {
+                    (0, 0) => {
+                        let flags: u8 = byte_reader.read();
+                        metadata.flags = Some(EntityFlags {
+                            burning: flags & 0x01 != 0,
+                            sneaking: flags & 0x02 != 0,
+                        });
+                    }
+
+                    // the stack of dropped items
+                    (6, 5) => {
+                        let slot: Slot = byte_reader.read();
+                        metadata.item = slot.into();
+                    }
+
+                    // the health of living entities
+                    (7, 2) => metadata.health = Some(byte_reader.read()),
+                    _ => skip_value(byte_reader, kind),
+                }
+            }
+            MetadataValues(metadata)
+        }
+    }
+
+    fn skip_value(byte_reader: &mut ByteReader, kind: i32) {
+        match kind {
+            0 => {
+                let _: u8 = byte_reader.read();
+            }
+            1 | 10 | 12 => {
+                let _: VarInt = byte_reader.read();
+            }
+            2 => {
+                let _: f32 = byte_reader.read();
+            }
+            3 | 4 => {
+                let _: String = byte_reader.read();
+            }
+            5 => {
+                let _: Slot = byte_reader.read();
+            }
+            6 => {
+                let _: bool = byte_reader.read();
+            }
+            7 => {
+                let _: (f32, f32, f32) = byte_reader.read();
+            }
+            8 => {
+                let _: u64 = byte_reader.read();
+            }
+            9 => {
+                if byte_reader.read() {
+                    let _: u64 = byte_reader.read();
+                }
+            }
+            11 => {
+                if byte_reader.read() {
+                    let _: UUID = byte_reader.read();
+                }
+            }
+            13 => {
+                let _ = nbt::Blob::from_reader(byte_reader);
+            }
+            kind => panic!("metadata type {} is not valid", kind),
+        }
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x00, Play)]
+    pub struct ObjectSpawn {
+        pub entity_id: VarInt,
+        pub object_uuid: UUID,
+        pub type_id: u8,
+        pub location: Location,
+        pub pitch: Angle,
+        pub yaw: Angle,
+        pub data: i32,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x01, Play)]
+    pub struct ExperienceOrbSpawn {
+        pub entity_id: VarInt,
+        pub location: Location,
+        pub count: i16,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x4b, Play)]
+    pub struct CollectItem {
+        pub collected_id: VarInt,
+        pub collector_id: VarInt,
+        pub count: VarInt,
+    }
 
     #[derive(Packet, Debug, Readable)]
     #[packet(0x03, Play)]
@@ -266,6 +418,7 @@ pub mod entity {
         pub velocity_x: i16,
         pub velocity_y: i16,
         pub velocity_z: i16,
+        pub metadata: MetadataValues,
     }
 
     #[derive(Packet, Debug, Readable)]
@@ -276,11 +429,49 @@ pub mod entity {
         pub location: Location,
         pub yaw: Angle,
         pub pitch: Angle,
-        // TODO metadata
-        // pub head_pitch: Angle,
-        // pub velocity_x: i16,
-        // pub velocity_y: i16,
-        // pub velocity_z: i16,
+        pub metadata: MetadataValues,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x3c, Play)]
+    pub struct Metadata {
+        pub entity_id: VarInt,
+        pub metadata: MetadataValues,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x3f, Play)]
+    pub struct Equipment {
+        pub entity_id: VarInt,
+        pub slot: VarInt,
+        pub item: Slot,
+    }
+
+    /// In 1/8000 of a block per tick
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x3e, Play)]
+    pub struct Velocity {
+        pub entity_id: VarInt,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x4f, Play)]
+    pub struct Effect {
+        pub entity_id: VarInt,
+        pub effect_id: u8,
+        pub amplifier: u8,
+        pub duration: VarInt,
+        pub flags: u8,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x33, Play)]
+    pub struct RemoveEffect {
+        pub entity_id: VarInt,
+        pub effect_id: u8,
     }
 
     #[derive(Packet, Debug, Readable)]
@@ -419,6 +610,13 @@ pub struct PlayDisconnect {
 // #[derive(Packet)]
 // #[packet(0x20, Play)]
 
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1d, Play)]
+pub struct UnloadChunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+}
+
 pub const CHUNK_PKT_ID: u32 = 0x20;
 
 pub struct ChunkColumnPacket {
@@ -521,12 +719,14 @@ impl ByteReadableLike for ChunkSection {
             let block_state_ids: Vec<VarInt> = byte_reader.read();
             let block_state_ids = block_state_ids
                 .into_iter()
-                .map(|id| BlockState(id.0 as u32))
+                .map(|id| Legacy.state(id.0 as u32))
                 .collect_vec();
             let storage: Vec<u64> = byte_reader.read();
             Palette::indirect(bits_per_block, block_state_ids, storage)
         } else {
             let VarInt(_place_holder) = byte_reader.read();
+
+            // the global palette. Legacy states are the internal states
             let storage: Vec<u64> = byte_reader.read();
             Palette::direct(storage)
         };
@@ -22,14 +22,21 @@ use swarm_bot_packets::{
 
 use crate::{
     bootstrap::{mojang::calc_hash, storage::ValidUser, Address, Connection},
-    client::processor::InterfaceIn,
-    error::{err, Error::WrongPacket, Res},
+    client::{commands::BotCommand, processor::InterfaceIn},
+    error::{
+        err,
+        Error::{Disconnected, WrongPacket},
+        Res,
+    },
     protocol::{
+        capture::{self, Capture},
         encrypt::{rand_bits, Rsa},
         io::{
+            decode,
             reader::PacketReader,
             writer::{PacketWriteChannel, PacketWriter},
         },
+        transform::{Legacy, Translation},
         v340::{
             clientbound::{JoinGame, LoginSuccess},
             serverbound::{
@@ -38,15 +45,23 @@ use crate::{
         },
         ClientInfo, EventQueue, Face, InterfaceOut, InvAction, Login, Mine, Minecraft,
     },
-    storage::entities::EntityKind,
-    types::{Dimension, Direction, Location, PacketData, Slot},
+    storage::entities::{Effect, EntityKind, EquipmentSlot},
+    types::{Dimension, Direction, Displacement, Location, PacketData, Slot},
 };
 
-mod clientbound;
-mod serverbound;
+/// Velocities are sent in 1/8000 of a block per tick
+fn velocity(x: i16, y: i16, z: i16) -> Displacement {
+    const UNITS: f64 = 8000.0;
+    Displacement::new(x as f64 / UNITS, y as f64 / UNITS, z as f64 / UNITS)
+}
+
+pub(crate) mod clientbound;
+pub(crate) mod serverbound;
 
 pub struct EventQueue340 {
-    rx: std::sync::mpsc::Receiver<PacketData>,
+    /// packets from the reader task. It sends an error and stops if the
+    /// connection breaks
+    rx: std::sync::mpsc::Receiver<Res<PacketData>>,
     out: Interface340,
     location: Location,
     dimension: Dimension,
@@ -54,14 +69,49 @@ pub struct EventQueue340 {
     /// we need to store state because sometimes death packets occur twice and
     /// we only want to send one event
     alive: bool,
+
+    /// whether we received join game. Another one means a proxy moved us to
+    /// another server
+    joined: bool,
+
+    /// whether no chunk came since the last respawn. BungeeCord moves us to
+    /// another server with two respawns in a row
+    respawned: bool,
+
+    capture: Option<Capture>,
 }
 
 impl EventQueue for EventQueue340 {
     fn flush(&mut self, processor: &mut impl InterfaceIn) {
+        self.receive(processor);
+        capture::end_tick(&mut self.capture);
+    }
+
+    fn capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    fn record_command(&mut self, command: &BotCommand) {
+        capture::record_command(&mut self.capture, command);
+    }
+}
+
+impl EventQueue340 {
+    fn receive(&mut self, processor: &mut impl InterfaceIn) {
         loop {
             match self.rx.try_recv() {
-                Ok(data) => {
-                    self.process_packet(data, processor);
+                Ok(Ok(data)) => {
+                    capture::record(&mut self.capture, &data);
+
+                    let id = data.id;
+                    if let Err(e) = decode(|| self.process_packet(data, processor)) {
+                        processor.on_disconnect(&format!("packet 0x{:02x} .. {}", id, e));
+                        return;
+                    }
+                }
+                Ok(Err(e)) => {
+                    processor.on_disconnect(&format!("connection error: {}", e));
+                    return;
                 }
                 Err(err) => {
                     match err {
@@ -75,16 +125,21 @@ impl EventQueue for EventQueue340 {
             }
         }
     }
-}
 
-impl EventQueue340 {
     fn process_packet(&mut self, mut data: PacketData, processor: &mut impl InterfaceIn) {
         use clientbound::*;
         match data.id {
             JoinGame::ID => {
                 let JoinGame { dimension, .. } = data.read();
+                if self.joined {
+                    processor.on_world_reset();
+                    processor.on_server_switch();
+                }
+                self.joined = true;
+                self.respawned = false;
                 processor.on_join();
                 processor.on_dimension_change(dimension);
+                self.dimension = dimension;
             }
 
             window::Set::ID => {
@@ -117,7 +172,7 @@ impl EventQueue340 {
 
             BlockChange::ID => {
                 let BlockChange { block_id, location } = data.read();
-                processor.on_block_change(location, BlockState(block_id.0 as u32));
+                processor.on_block_change(location, Legacy.state(block_id.0 as u32));
             }
             Explosion::ID => {
                 let Explosion { records, .. } = data.read();
@@ -154,28 +209,120 @@ impl EventQueue340 {
                 } = data.read();
                 processor.on_entity_move(entity_id.into(), location.into());
             }
+            entity::Metadata::ID => {
+                let entity::Metadata {
+                    entity_id,
+                    metadata,
+                } = data.read();
+                processor.on_entity_metadata(entity_id.into(), metadata.0);
+            }
+            entity::Equipment::ID => {
+                let entity::Equipment {
+                    entity_id,
+                    slot,
+                    item,
+                } = data.read();
+                if let Some(slot) = EquipmentSlot::from_id(slot.0) {
+                    processor.on_entity_equipment(entity_id.into(), slot, item.into());
+                }
+            }
+            entity::Velocity::ID => {
+                let entity::Velocity {
+                    entity_id,
+                    velocity_x,
+                    velocity_y,
+                    velocity_z,
+                } = data.read();
+                let velocity = velocity(velocity_x, velocity_y, velocity_z);
+                processor.on_entity_velocity(entity_id.into(), velocity);
+            }
+            entity::Effect::ID => {
+                let entity::Effect {
+                    entity_id,
+                    effect_id,
+                    amplifier,
+                    duration,
+                    ..
+                } = data.read();
+                let effect = Effect {
+                    id: effect_id,
+                    amplifier,
+                    duration: duration.0.max(0) as u32,
+                };
+                processor.on_entity_effect(entity_id.into(), effect);
+            }
+            entity::RemoveEffect::ID => {
+                let entity::RemoveEffect {
+                    entity_id,
+                    effect_id,
+                } = data.read();
+                processor.on_entity_effect_removed(entity_id.into(), effect_id);
+            }
+            entity::ObjectSpawn::ID => {
+                let entity::ObjectSpawn {
+                    entity_id,
+                    type_id,
+                    location,
+                    velocity_x,
+                    velocity_y,
+                    velocity_z,
+                    ..
+                } = data.read();
+                let id = entity_id.into();
+                processor.on_entity_spawn(id, location, EntityKind::Object { type_id });
+                processor.on_entity_velocity(id, velocity(velocity_x, velocity_y, velocity_z));
+            }
+            entity::ExperienceOrbSpawn::ID => {
+                let entity::ExperienceOrbSpawn {
+                    entity_id,
+                    location,
+                    ..
+                } = data.read();
+                processor.on_entity_spawn(entity_id.into(), location, EntityKind::ExperienceOrb);
+            }
+            entity::CollectItem::ID => {
+                let entity::CollectItem { collected_id, .. } = data.read();
+                processor.on_entity_collected(collected_id.into());
+            }
             entity::LivingSpawn::ID => {
                 let entity::LivingSpawn {
                     entity_id,
+                    type_id,
                     location,
+                    velocity_x,
+                    velocity_y,
+                    velocity_z,
+                    metadata,
                     ..
                 } = data.read();
-                processor.on_entity_spawn(entity_id.into(), location, EntityKind::Normal);
+                let id = entity_id.into();
+                processor.on_entity_spawn(
+                    id,
+                    location,
+                    EntityKind::Mob {
+                        type_id: type_id.into(),
+                    },
+                );
+                processor.on_entity_velocity(id, velocity(velocity_x, velocity_y, velocity_z));
+                processor.on_entity_metadata(id, metadata.0);
             }
             entity::PlayerSpawn::ID => {
                 let entity::PlayerSpawn {
                     entity_id,
                     location,
                     player_uuid,
+                    metadata,
                     ..
                 } = data.read();
+                let id = entity_id.into();
                 processor.on_entity_spawn(
-                    entity_id.into(),
+                    id,
                     location,
                     EntityKind::Player {
                         uuid: player_uuid.0,
                     },
                 );
+                processor.on_entity_metadata(id, metadata.0);
             }
             UpdateHealth::ID => {
                 let UpdateHealth { health, food, .. } = data.read();
@@ -189,6 +336,14 @@ impl EventQueue340 {
             }
             Respawn::ID => {
                 let Respawn { dimension, .. } = data.read();
+
+                // the client drops the world on every respawn. Proxies also
+                // send one when switching servers
+                processor.on_world_reset();
+                if self.respawned {
+                    processor.on_server_switch();
+                }
+                self.respawned = true;
                 processor.on_dimension_change(dimension);
                 self.dimension = dimension;
             }
@@ -202,8 +357,13 @@ impl EventQueue340 {
                     column,
                     new_chunk,
                 } = data.reader.read_like(&overworld);
+                self.respawned = false;
                 processor.on_recv_chunk(ChunkLocation(chunk_x, chunk_z), column, new_chunk);
             }
+            UnloadChunk::ID => {
+                let UnloadChunk { chunk_x, chunk_z } = data.read();
+                processor.on_unload_chunk(ChunkLocation(chunk_x, chunk_z));
+            }
             MultiBlock::ID => {
                 let MultiBlock {
                     chunk_x,
@@ -223,7 +383,7 @@ impl EventQueue340 {
                 {
                     let location =
                         BlockLocation::new(base_x + x as i32, y as i16, base_z + z as i32);
-                    processor.on_block_change(location, BlockState(block_state.0 as u32))
+                    processor.on_block_change(location, Legacy.state(block_state.0 as u32))
                 }
             }
             PlayerPositionAndLook::ID => {
@@ -246,15 +406,7 @@ impl EventQueue340 {
             PlayerListItem::ID => {
                 let PlayerListItem { players } = data.read();
                 for Player { uuid, list_type } in players {
-                    match list_type {
-                        PlayerListType::AddPlayer(add) => {
-                            processor.on_player_join(uuid.0, add.name);
-                        }
-                        PlayerListType::UpdateGamemode(_) => {}
-                        PlayerListType::UpdateLatency(_) => {}
-                        PlayerListType::UpdateDisplayName(_) => {}
-                        PlayerListType::RemovePlayer => processor.on_player_leave(uuid.0),
-                    }
+                    processor.on_player_list(uuid.0, list_type.into_update(uuid.0));
                 }
             }
             // ignore
@@ -415,6 +567,8 @@ impl Minecraft for Protocol {
     type Queue = EventQueue340;
     type Interface = Interface340;
 
+    const PROTOCOL: u32 = 340;
+
     async fn login(conn: Connection) -> Res<Login<EventQueue340, Interface340>> {
         let Connection {
             user,
@@ -422,6 +576,7 @@ impl Minecraft for Protocol {
             mojang,
             read,
             write,
+            ..
         } = conn;
         let ValidUser {
             username,
@@ -453,39 +608,44 @@ impl Minecraft for Protocol {
             })
             .await?;
 
-        let clientbound::EncryptionRequest {
-            public_key_der,
-            verify_token,
-            server_id,
-        } = reader.read_exact_packet().await?;
+        // an online-mode server asks us to enable encryption first. An offline-mode
+        // (online-mode=false) server skips this and goes straight to compression or
+        // login success
+        let mut data = reader.read().await?;
 
-        let rsa = Rsa::from_der(&public_key_der);
+        if data.id == clientbound::EncryptionRequest::ID {
+            let clientbound::EncryptionRequest {
+                public_key_der,
+                verify_token,
+                server_id,
+            } = data.read();
 
-        let shared_secret = rand_bits();
+            let rsa = Rsa::from_der(&public_key_der)?;
 
-        let encrypted_ss = rsa.encrypt(&shared_secret).unwrap();
-        let encrypted_verify = rsa.encrypt(&verify_token).unwrap();
+            let shared_secret = rand_bits();
 
-        // Mojang online mode requests
-        let hash = calc_hash(&server_id, &shared_secret, &public_key_der);
-        mojang.join(uuid, &hash, &access_id).await?;
+            let encrypted_ss = rsa.encrypt(&shared_secret)?;
+            let encrypted_verify = rsa.encrypt(&verify_token)?;
 
-        // id = 1
-        writer
-            .write(serverbound::EncryptionResponse {
-                shared_secret: encrypted_ss,
-                verify_token: encrypted_verify,
-            })
-            .await?;
+            // Mojang online mode requests
+            let hash = calc_hash(&server_id, &shared_secret, &public_key_der);
+            mojang.join(uuid, &hash, &access_id).await?;
 
-        // writer.flush().await;
+            // id = 1
+            writer
+                .write(serverbound::EncryptionResponse {
+                    shared_secret: encrypted_ss,
+                    verify_token: encrypted_verify,
+                })
+                .await?;
 
-        // we now do everything encrypted
-        writer.encryption(&shared_secret);
-        reader.encryption(&shared_secret);
+            // we now do everything encrypted
+            writer.encryption(&shared_secret);
+            reader.encryption(&shared_secret);
 
-        // set compression or login success
-        let mut data = reader.read().await?;
+            // set compression or login success
+            data = reader.read().await?;
+        }
 
         let LoginSuccess { .. } = match data.id {
             clientbound::SetCompression::ID => {
@@ -497,6 +657,10 @@ impl Minecraft for Protocol {
                 reader.read_exact_packet().await?
             }
             clientbound::LoginSuccess::ID => data.reader.read(),
+            clientbound::Disconnect::ID => {
+                let clientbound::Disconnect { reason } = data.read();
+                return Err(Disconnected(reason));
+            }
             actual => {
                 return Err(WrongPacket {
                     state: PacketState::Login,
@@ -512,17 +676,24 @@ impl Minecraft for Protocol {
         tokio::task::spawn_local(async move {
             let mut oneshot = Some(os_tx);
             loop {
-                let packet = reader.read().await.unwrap();
+                let packet = match reader.read().await {
+                    Ok(packet) => packet,
+
+                    // the socket closed or sent garbage. The bot disconnects with this error
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
                 if packet.id == clientbound::JoinGame::ID {
                     if let Some(os_tx) = oneshot.take() {
                         let mut packet = packet.clone();
                         let processed: JoinGame = packet.read();
-                        os_tx
-                            .send((processed.entity_id, processed.dimension))
-                            .unwrap();
+                        // the login could have given up already
+                        let _ = os_tx.send((processed.entity_id, processed.dimension));
                     }
                 }
-                match tx.send(packet) {
+                match tx.send(Ok(packet)) {
                     Ok(..) => {}
                     Err(..) => {
                         // the other end is stopped and should have printed the error
@@ -546,6 +717,9 @@ impl Minecraft for Protocol {
             out: out.clone(),
             location: Default::default(),
             alive: true,
+            joined: false,
+            respawned: false,
+            capture: None,
         };
 
         let login = Login {
@@ -560,4 +734,23 @@ impl Minecraft for Protocol {
 
         Ok(login)
     }
+
+    fn offline(
+        rx: std::sync::mpsc::Receiver<Res<PacketData>>,
+    ) -> Res<(EventQueue340, Interface340)> {
+        let out = Interface340::new(PacketWriteChannel::discard());
+
+        let queue = EventQueue340 {
+            rx,
+            out: out.clone(),
+            location: Default::default(),
+            dimension: Dimension::Overworld,
+            alive: true,
+            joined: false,
+            respawned: false,
+            capture: None,
+        };
+
+        Ok((queue, out))
+    }
 }
@@ -0,0 +1,650 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use interfaces::types::{BlockLocation, BlockState};
+use serde::Deserialize;
+
+use swarm_bot_packets::{
+    read::{ByteReadable, ByteReader},
+    types::{Identifier, RawVec, VarInt, VarLong, VarUInt, UUID},
+    Packet, Readable,
+};
+
+use crate::{
+    error::{Error, Res},
+    protocol::{
+        transform::Translation,
+        v340::clientbound::{self as v340, GameMode, Player},
+        v754::Position,
+    },
+    storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette},
+    types::{Chat, Dimension, DirectionOrigin, Location, LocationOrigin},
+};
+
+#[derive(Packet, Readable)]
+#[packet(0x00, Login)]
+pub struct Disconnect {
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Packet, Readable)]
+#[packet(0x01, Login)]
+pub struct EncryptionRequest {
+    pub server_id: String,
+    pub public_key_der: Vec<u8>,
+    pub verify_token: Vec<u8>,
+}
+
+#[derive(Debug, Packet, Readable)]
+#[packet(0x02, Login)]
+pub struct LoginSuccess {
+    pub uuid: UUID,
+    pub username: String,
+}
+
+#[derive(Packet, Readable)]
+#[packet(0x03, Login)]
+pub struct SetCompression {
+    pub threshold: VarInt,
+}
+
+/// Sent by proxies such as Velocity. We answer that we do not understand it
+#[derive(Debug, Packet, Readable)]
+#[packet(0x04, Login)]
+pub struct LoginPluginRequest {
+    pub message_id: VarInt,
+    pub channel: Identifier,
+    pub data: RawVec,
+}
+
+/// The part of the dimension type we use
+#[derive(Deserialize)]
+struct DimensionType {
+    effects: String,
+}
+
+/// 1.16 sends the dimension type as NBT. Custom worlds look like one of the
+/// vanilla dimensions
+fn read_dimension(byte_reader: &mut ByteReader) -> Res<Dimension> {
+    let dimension: DimensionType = nbt::from_reader(byte_reader)?;
+    let dimension = match dimension.effects.as_str() {
+        "minecraft:the_nether" => Dimension::Nether,
+        "minecraft:the_end" => Dimension::End,
+        _ => Dimension::Overworld,
+    };
+    Ok(dimension)
+}
+
+#[derive(Packet, Debug)]
+#[packet(0x24, Play)]
+pub struct JoinGame {
+    pub entity_id: u32,
+    pub hardcore: bool,
+    pub game_mode: GameMode,
+    pub dimension: Dimension,
+    pub world_name: Identifier,
+}
+
+impl JoinGame {
+    /// Reads the packet. Unlike most packets it contains NBT, which can fail
+    /// to decode
+    pub fn read(byte_reader: &mut ByteReader) -> Res<JoinGame> {
+        let entity_id = byte_reader.read();
+        let hardcore = byte_reader.read();
+        let game_mode = byte_reader.read();
+        let _previous_game_mode: u8 = byte_reader.read();
+        let _world_names: Vec<Identifier> = byte_reader.read();
+
+        // every dimension and biome of the server. We only need the current dimension
+        nbt::Blob::from_reader(byte_reader)?;
+
+        let dimension = read_dimension(byte_reader)?;
+        let world_name = byte_reader.read();
+
+        // hashed seed, max players, view distance and flags follow
+        Ok(JoinGame {
+            entity_id,
+            hardcore,
+            game_mode,
+            dimension,
+            world_name,
+        })
+    }
+}
+
+#[derive(Packet, Debug)]
+#[packet(0x39, Play)]
+pub struct Respawn {
+    pub dimension: Dimension,
+    pub world_name: Identifier,
+}
+
+impl Respawn {
+    pub fn read(byte_reader: &mut ByteReader) -> Res<Respawn> {
+        let dimension = read_dimension(byte_reader)?;
+        let world_name = byte_reader.read();
+        Ok(Respawn {
+            dimension,
+            world_name,
+        })
+    }
+}
+
+/// Same layout as 1.12
+#[derive(Debug, Packet)]
+#[packet(0x32, Play)]
+pub struct PlayerListItem {
+    pub players: Vec<Player>,
+}
+
+impl ByteReadable for PlayerListItem {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::PlayerListItem { players } = byte_reader.read();
+        Self { players }
+    }
+}
+
+pub mod entity {
+    use swarm_bot_packets::{
+        types::{Angle, VarInt, UUID},
+        *,
+    };
+
+    use crate::types::{Location, ShortLoc};
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x02, Play)]
+    pub struct LivingSpawn {
+        pub entity_id: VarInt,
+        pub entity_uuid: UUID,
+        pub type_id: VarInt,
+        pub location: Location,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub head_pitch: Angle,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x04, Play)]
+    pub struct PlayerSpawn {
+        pub entity_id: VarInt,
+        pub player_uuid: UUID,
+        pub location: Location,
+        pub yaw: Angle,
+        pub pitch: Angle,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x27, Play)]
+    pub struct RelativeMove {
+        pub entity_id: VarInt,
+        pub loc: ShortLoc,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x28, Play)]
+    pub struct LookAndRelativeMove {
+        pub entity_id: VarInt,
+        pub loc: ShortLoc,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x36, Play)]
+    pub struct Destroy {
+        pub ids: Vec<VarInt>,
+    }
+
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x56, Play)]
+    pub struct Teleport {
+        pub entity_id: VarInt,
+        pub location: Location,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub on_ground: bool,
+    }
+}
+
+/// Same layout as 1.12
+#[derive(Packet, Debug)]
+#[packet(0x34, Play)]
+pub struct PlayerPositionAndLook {
+    pub location: LocationOrigin,
+    pub rotation: DirectionOrigin,
+    pub teleport_id: VarInt,
+}
+
+impl ByteReadable for PlayerPositionAndLook {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::PlayerPositionAndLook {
+            location,
+            rotation,
+            teleport_id,
+        } = byte_reader.read();
+
+        Self {
+            location,
+            rotation,
+            teleport_id,
+        }
+    }
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x0e, Play)]
+pub struct ChatMessage {
+    pub chat: Chat,
+    pub position: u8,
+    pub sender: UUID,
+}
+
+/// Same layout as 1.12
+#[derive(Packet, Debug)]
+#[packet(0x1b, Play)]
+pub struct Explosion {
+    pub location: Location,
+    pub radius: f32,
+    pub records: Vec<BlockLocation>,
+}
+
+impl ByteReadable for Explosion {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::Explosion {
+            location,
+            radius,
+            records,
+        } = byte_reader.read();
+
+        Self {
+            location,
+            radius,
+            records,
+        }
+    }
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x0b, Play)]
+pub struct BlockChange {
+    pub location: Position,
+    pub block_id: VarInt,
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+    pub block_state: u32,
+}
+
+impl ByteReadable for Record {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let VarLong(record) = byte_reader.read();
+        let record = record as u64;
+        Self {
+            x: ((record >> 8) & 0xF) as u8,
+            y: (record & 0xF) as u8,
+            z: ((record >> 4) & 0xF) as u8,
+            block_state: (record >> 12) as u32,
+        }
+    }
+}
+
+/// Changes within one chunk section
+#[derive(Packet, Debug)]
+#[packet(0x3b, Play)]
+pub struct MultiBlock {
+    pub section_x: i32,
+    pub section_y: i32,
+    pub section_z: i32,
+    pub records: Vec<Record>,
+}
+
+impl ByteReadable for MultiBlock {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let position: u64 = byte_reader.read();
+        let position = position as i64;
+        let _trust_edges: bool = byte_reader.read();
+        let records = byte_reader.read();
+
+        Self {
+            section_x: (position >> 42) as i32,
+            section_y: (position << 44 >> 44) as i32,
+            section_z: (position << 22 >> 42) as i32,
+            records,
+        }
+    }
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x49, Play)]
+pub struct UpdateHealth {
+    pub health: f32,
+
+    // 0-20
+    pub food: VarInt,
+    pub food_saturation: f32,
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1f, Play)]
+pub struct KeepAlive {
+    pub id: u64,
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x19, Play)]
+pub struct PlayDisconnect {
+    pub reason: String,
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1c, Play)]
+pub struct UnloadChunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+}
+
+pub const CHUNK_PKT_ID: u32 = 0x20;
+
+pub struct ChunkColumnPacket {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub new_chunk: bool,
+    pub column: ChunkColumn,
+}
+
+impl ChunkColumnPacket {
+    pub fn read(byte_reader: &mut ByteReader, translation: &impl Translation) -> Res<Self> {
+        let chunk_x = byte_reader.read();
+        let chunk_z = byte_reader.read();
+        let full_chunk: bool = byte_reader.read();
+        let VarUInt(mut primary_bitmask) = byte_reader.read();
+
+        // heightmaps
+        nbt::Blob::from_reader(byte_reader)?;
+
+        if full_chunk {
+            let _biomes: Vec<VarInt> = byte_reader.read();
+        }
+
+        let _size: VarUInt = byte_reader.read();
+
+        const INIT: Option<Box<HighMemoryChunkSection>> = None;
+        let mut sections = [INIT; 16];
+
+        let mut idx = 0;
+        while primary_bitmask != 0 {
+            if primary_bitmask & 0b1 == 1 {
+                let section = ChunkSection::read(byte_reader, translation)?;
+                sections[idx] = Some(box HighMemoryChunkSection::new(section.palette));
+            }
+            primary_bitmask >>= 1;
+            idx += 1;
+        }
+
+        let data = ChunkData { sections };
+
+        let column = ChunkColumn::HighMemory { data };
+
+        Ok(ChunkColumnPacket {
+            chunk_x,
+            chunk_z,
+            new_chunk: full_chunk,
+            column,
+        })
+    }
+}
+
+/// 1.16 slot data. The damage moved into the NBT
+#[derive(Debug)]
+pub struct Slot {
+    pub item: Option<(VarInt, u8, ItemTag)>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ItemTag {
+    #[serde(rename = "Damage")]
+    pub damage: Option<i32>,
+}
+
+impl Slot {
+    pub fn read(byte_reader: &mut ByteReader) -> Res<Slot> {
+        let present: bool = byte_reader.read();
+        if !present {
+            return Ok(Slot { item: None });
+        }
+
+        let id = byte_reader.read();
+        let count = byte_reader.read();
+
+        let first: u8 = byte_reader.read();
+        let tag = if first == 0 {
+            ItemTag::default()
+        } else {
+            byte_reader.back(1);
+            nbt::from_reader(&mut *byte_reader)?
+        };
+
+        Ok(Slot {
+            item: Some((id, count, tag)),
+        })
+    }
+}
+
+pub mod window {
+    use swarm_bot_packets::read::ByteReader;
+
+    use crate::{error::Res, protocol::v754::clientbound::Slot};
+
+    #[derive(Packet, Debug)]
+    #[packet(0x15, Play)]
+    pub struct Set {
+        pub window_id: u8,
+        pub slot: u16,
+        pub data: Slot,
+    }
+
+    impl Set {
+        pub fn read(byte_reader: &mut ByteReader) -> Res<Set> {
+            Ok(Set {
+                window_id: byte_reader.read(),
+                slot: byte_reader.read(),
+                data: Slot::read(byte_reader)?,
+            })
+        }
+    }
+
+    #[derive(Packet)]
+    #[packet(0x13, Play)]
+    pub struct Items {
+        /// 0 if player inventory
+        pub window_id: u8,
+        pub slots: Vec<Slot>,
+    }
+
+    impl Items {
+        pub fn read(byte_reader: &mut ByteReader) -> Res<Items> {
+            let window_id = byte_reader.read();
+
+            // number of elements in the array
+            let length: u16 = byte_reader.read();
+            let slots = (0..length)
+                .map(|_| Slot::read(byte_reader))
+                .collect::<Res<_>>()?;
+
+            Ok(Items { window_id, slots })
+        }
+    }
+}
+
+/// A 1.16 chunk section. There is no light data and values do not span longs
+pub struct ChunkSection {
+    palette: Palette,
+}
+
+impl ChunkSection {
+    fn read(byte_reader: &mut ByteReader, translation: &impl Translation) -> Res<ChunkSection> {
+        let _block_count: i16 = byte_reader.read();
+        let bits_per_block: u8 = byte_reader.read();
+
+        if bits_per_block > 32 {
+            let reason = format!("{} bits per block", bits_per_block);
+            return Err(Error::Malformed(reason));
+        }
+
+        let palette = (bits_per_block <= 8).then(|| {
+            let ids: Vec<VarInt> = byte_reader.read();
+            ids.into_iter()
+                .map(|VarInt(id)| translation.state(id as u32))
+                .collect::<Vec<_>>()
+        });
+
+        let bits_per_block = if palette.is_some() {
+            bits_per_block.max(4)
+        } else {
+            bits_per_block
+        } as usize;
+
+        let storage: Vec<u64> = byte_reader.read();
+
+        let per_long = 64 / bits_per_block;
+        let mask = (1 << bits_per_block) - 1;
+
+        if storage.len() * per_long < 4096 {
+            let reason = format!("{} longs in a chunk section", storage.len());
+            return Err(Error::Malformed(reason));
+        }
+
+        let mut states = [BlockState::AIR; 4096];
+        for (i, state) in states.iter_mut().enumerate() {
+            let long = storage[i / per_long];
+            let value = ((long >> ((i % per_long) * bits_per_block)) & mask) as u32;
+            *state = match &palette {
+                Some(palette) => palette.get(value as usize).copied().ok_or_else(|| {
+                    Error::Malformed(format!("palette index {} out of range", value))
+                })?,
+                None => translation.state(value),
+            };
+        }
+
+        Ok(ChunkSection {
+            palette: Palette::from_states(&states),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use interfaces::types::BlockState;
+    use swarm_bot_packets::{
+        read::ByteReader,
+        types::{VarInt, VarLong},
+        write::ByteWriter,
+    };
+
+    use crate::protocol::{
+        transform::Legacy,
+        v754::clientbound::{ChunkSection, MultiBlock, Record, Respawn},
+    };
+
+    /// A section with a palette of `palette` states and `longs` longs of
+    /// 4-bit indices. Only the first long is not zero
+    fn section_bytes(palette: &[i32], longs: usize, first: u64) -> ByteReader {
+        let mut writer = ByteWriter::new();
+        writer.write(1_i16).write(4_u8);
+
+        writer.write(VarInt(palette.len() as i32));
+        for &id in palette {
+            writer.write(VarInt(id));
+        }
+
+        writer.write(VarInt(longs as i32));
+        for i in 0..longs {
+            writer.write(if i == 0 { first } else { 0 });
+        }
+
+        ByteReader::new(writer.freeze())
+    }
+
+    #[test]
+    fn test_chunk_section() {
+        let stone = BlockState::from(1, 0);
+
+        // the second block uses palette entry 1
+        let mut reader = section_bytes(&[0, stone.0 as i32], 256, 0x10);
+        let section = ChunkSection::read(&mut reader, &Legacy).unwrap();
+        let states = section.palette.all_states();
+        assert_eq!(states[0], BlockState::AIR);
+        assert_eq!(states[1], stone);
+        assert_eq!(states[2], BlockState::AIR);
+
+        // too few longs for 4096 blocks
+        let mut reader = section_bytes(&[0], 10, 0);
+        assert!(ChunkSection::read(&mut reader, &Legacy).is_err());
+
+        // an index past the end of the palette
+        let mut reader = section_bytes(&[0], 256, 0x10);
+        assert!(ChunkSection::read(&mut reader, &Legacy).is_err());
+    }
+
+    #[test]
+    fn test_multi_block() {
+        let (x, y, z) = (1_i64, 3_i64, -2_i64);
+        let position = ((x & 0x3FFFFF) << 42) | ((z & 0x3FFFFF) << 20) | (y & 0xFFFFF);
+
+        // state 35, x 5, z 6, y 7
+        let record = (35 << 12) | (5 << 8) | (6 << 4) | 7;
+
+        let mut writer = ByteWriter::new();
+        writer
+            .write(position as u64)
+            .write(false)
+            .write(VarInt(1))
+            .write(VarLong(record));
+
+        let mut reader = ByteReader::new(writer.freeze());
+        let MultiBlock {
+            section_x,
+            section_y,
+            section_z,
+            records,
+        } = reader.read();
+
+        assert_eq!((section_x, section_y, section_z), (1, 3, -2));
+
+        let Record {
+            x,
+            y,
+            z,
+            block_state,
+        } = &records[0];
+        assert_eq!((*x, *y, *z, *block_state), (5, 7, 6, 35));
+    }
+
+    #[test]
+    fn test_truncated_nbt() {
+        // a compound tag without its name
+        let mut reader = ByteReader::new(vec![0x0a]);
+        assert!(Respawn::read(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,727 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! 1.16.5
+
+use interfaces::types::{BlockLocation, BlockState, ChunkLocation};
+use std::{cell::RefCell, rc::Rc, sync::mpsc::TryRecvError};
+
+use swarm_bot_packets::{
+    read::{ByteReadable, ByteReader},
+    types::{Packet, PacketState, VarInt, UUID},
+    write::{ByteWritable, ByteWriter},
+};
+
+use crate::{
+    bootstrap::{mojang::calc_hash, storage::ValidUser, Address, Connection},
+    client::{commands::BotCommand, processor::InterfaceIn, state::local::inventory::ItemStack},
+    error::{
+        err,
+        Error::{Disconnected, WrongPacket},
+        Res,
+    },
+    protocol::{
+        capture::{self, Capture},
+        encrypt::{rand_bits, Rsa},
+        io::{
+            decode,
+            reader::PacketReader,
+            writer::{PacketWriteChannel, PacketWriter},
+        },
+        transform::{Flattening, Translation},
+        v340::clientbound::Player,
+        v754::{
+            clientbound::JoinGame,
+            serverbound::{
+                ClientStatusAction, DigStatus, Hand, HandshakeNextState, InteractEntityKind,
+            },
+        },
+        ClientInfo, EventQueue, Face, InterfaceOut, InvAction, Login, Mine, Minecraft,
+    },
+    storage::entities::EntityKind,
+    types::{Direction, Location, PacketData},
+};
+
+mod clientbound;
+mod serverbound;
+
+/// A block position. 1.14 swapped where y and z are stored
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Position(pub BlockLocation);
+
+impl ByteReadable for Position {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let val: u64 = byte_reader.read();
+        let val = val as i64;
+
+        let x = (val >> 38) as i32;
+        let y = (val << 52 >> 52) as i16;
+        let z = (val << 26 >> 38) as i32;
+
+        Position(BlockLocation::new(x, y, z))
+    }
+}
+
+impl ByteWritable for Position {
+    fn write_to_bytes(self, writer: &mut ByteWriter) {
+        let BlockLocation { x, y, z } = self.0;
+        let write =
+            ((x as u64 & 0x3FFFFFF) << 38) | ((z as u64 & 0x3FFFFFF) << 12) | (y as u64 & 0xFFF);
+        writer.write(write);
+    }
+}
+
+pub struct EventQueue754 {
+    /// packets from the reader task. It sends an error and stops if the
+    /// connection breaks
+    rx: std::sync::mpsc::Receiver<Res<PacketData>>,
+    out: Interface754,
+    location: Location,
+    flattening: &'static Flattening,
+
+    /// we need to store state because sometimes death packets occur twice and
+    /// we only want to send one event
+    alive: bool,
+
+    /// whether we received join game. Another one means a proxy moved us to
+    /// another server
+    joined: bool,
+
+    /// whether no chunk came since the last respawn. BungeeCord moves us to
+    /// another server with two respawns in a row
+    respawned: bool,
+
+    capture: Option<Capture>,
+}
+
+impl EventQueue for EventQueue754 {
+    fn flush(&mut self, processor: &mut impl InterfaceIn) {
+        self.receive(processor);
+        capture::end_tick(&mut self.capture);
+    }
+
+    fn capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    fn record_command(&mut self, command: &BotCommand) {
+        capture::record_command(&mut self.capture, command);
+    }
+}
+
+impl EventQueue754 {
+    fn receive(&mut self, processor: &mut impl InterfaceIn) {
+        loop {
+            match self.rx.try_recv() {
+                Ok(Ok(data)) => {
+                    capture::record(&mut self.capture, &data);
+
+                    let id = data.id;
+                    let processed = decode(|| self.process_packet(data, processor));
+                    if let Err(e) = processed.and_then(|processed| processed) {
+                        processor.on_disconnect(&format!("packet 0x{:02x} .. {}", id, e));
+                        return;
+                    }
+                }
+                Ok(Err(e)) => {
+                    processor.on_disconnect(&format!("connection error: {}", e));
+                    return;
+                }
+                Err(err) => {
+                    match err {
+                        TryRecvError::Empty => {}
+                        TryRecvError::Disconnected => {
+                            processor.on_socket_close();
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn item_stack(&self, slot: clientbound::Slot) -> Option<ItemStack> {
+        let (VarInt(id), count, tag) = slot.item?;
+        let (kind, meta) = self.flattening.item(id as u32);
+        let damage = tag.damage.map_or(meta, |damage| damage as u16);
+        Some(ItemStack::new(kind, count, damage, None))
+    }
+
+    fn process_packet(&mut self, mut data: PacketData, processor: &mut impl InterfaceIn) -> Res {
+        use clientbound::*;
+        match data.id {
+            JoinGame::ID => {
+                let JoinGame { dimension, .. } = JoinGame::read(&mut data.reader)?;
+                if self.joined {
+                    processor.on_world_reset();
+                    processor.on_server_switch();
+                }
+                self.joined = true;
+                self.respawned = false;
+                processor.on_join();
+                processor.on_dimension_change(dimension);
+            }
+
+            window::Set::ID => {
+                let window::Set {
+                    window_id,
+                    slot: idx,
+                    data,
+                } = window::Set::read(&mut data.reader)?;
+                if window_id == 0 {
+                    match self.item_stack(data) {
+                        None => processor.on_lose_item(idx as usize),
+                        Some(item_stack) => processor.on_pickup_item(idx as usize, item_stack),
+                    }
+                }
+            }
+
+            window::Items::ID => {
+                let window::Items { window_id, slots } = window::Items::read(&mut data.reader)?;
+
+                if window_id == 0 {
+                    // is player inventory
+                    for (idx, slot) in slots.into_iter().enumerate() {
+                        match self.item_stack(slot) {
+                            None => processor.on_lose_item(idx),
+                            Some(item_stack) => processor.on_pickup_item(idx, item_stack),
+                        }
+                    }
+                }
+            }
+
+            BlockChange::ID => {
+                let BlockChange { block_id, location } = data.read();
+                let state = self.flattening.state(block_id.0 as u32);
+                processor.on_block_change(location.0, state);
+            }
+            Explosion::ID => {
+                let Explosion { records, .. } = data.read();
+
+                for record in records {
+                    processor.on_block_change(record, BlockState::AIR);
+                }
+            }
+            KeepAlive::ID => {
+                // auto keep alive
+                let KeepAlive { id } = data.read();
+
+                self.out.write(serverbound::KeepAlive { id });
+            }
+            entity::RelativeMove::ID => {
+                let entity::RelativeMove { entity_id, loc, .. } = data.read();
+                processor.on_entity_move(entity_id.into(), loc.into());
+            }
+            entity::LookAndRelativeMove::ID => {
+                let entity::LookAndRelativeMove { entity_id, loc, .. } = data.read();
+                processor.on_entity_move(entity_id.into(), loc.into());
+            }
+            entity::Destroy::ID => {
+                let entity::Destroy { ids } = data.read();
+                for id in ids {
+                    processor.on_entity_destroy(id.into());
+                }
+            }
+            entity::Teleport::ID => {
+                let entity::Teleport {
+                    entity_id,
+                    location,
+                    ..
+                } = data.read();
+                processor.on_entity_move(entity_id.into(), location.into());
+            }
+            entity::LivingSpawn::ID => {
+                let entity::LivingSpawn {
+                    entity_id,
+                    location,
+                    ..
+                } = data.read();
+                processor.on_entity_spawn(entity_id.into(), location, EntityKind::Normal);
+            }
+            entity::PlayerSpawn::ID => {
+                let entity::PlayerSpawn {
+                    entity_id,
+                    location,
+                    player_uuid,
+                    ..
+                } = data.read();
+                processor.on_entity_spawn(
+                    entity_id.into(),
+                    location,
+                    EntityKind::Player {
+                        uuid: player_uuid.0,
+                    },
+                );
+            }
+            UpdateHealth::ID => {
+                let UpdateHealth { health, food, .. } = data.read();
+                if health > 0.0 {
+                    processor.on_update_health(health, food.0 as u8);
+                    self.alive = true;
+                } else if self.alive {
+                    processor.on_death();
+                    self.alive = false;
+                }
+            }
+            Respawn::ID => {
+                let Respawn { dimension, .. } = Respawn::read(&mut data.reader)?;
+
+                // the client drops the world on every respawn. Proxies also
+                // send one when switching servers
+                processor.on_world_reset();
+                if self.respawned {
+                    processor.on_server_switch();
+                }
+                self.respawned = true;
+                processor.on_dimension_change(dimension);
+            }
+            clientbound::CHUNK_PKT_ID => {
+                let ChunkColumnPacket {
+                    chunk_x,
+                    chunk_z,
+                    column,
+                    new_chunk,
+                } = ChunkColumnPacket::read(&mut data.reader, self.flattening)?;
+                self.respawned = false;
+                processor.on_recv_chunk(ChunkLocation(chunk_x, chunk_z), column, new_chunk);
+            }
+            UnloadChunk::ID => {
+                let UnloadChunk { chunk_x, chunk_z } = data.read();
+                processor.on_unload_chunk(ChunkLocation(chunk_x, chunk_z));
+            }
+            MultiBlock::ID => {
+                let MultiBlock {
+                    section_x,
+                    section_y,
+                    section_z,
+                    records,
+                } = data.read();
+
+                let base_x = section_x << 4;
+                let base_y = section_y << 4;
+                let base_z = section_z << 4;
+
+                for Record {
+                    x,
+                    y,
+                    z,
+                    block_state,
+                } in records
+                {
+                    let location = BlockLocation::new(
+                        base_x + x as i32,
+                        (base_y + y as i32) as i16,
+                        base_z + z as i32,
+                    );
+                    let state = self.flattening.state(block_state);
+                    processor.on_block_change(location, state)
+                }
+            }
+            PlayerPositionAndLook::ID => {
+                let PlayerPositionAndLook {
+                    location,
+                    rotation: _,
+                    teleport_id,
+                } = data.read();
+
+                self.location.apply_change(location);
+                processor.on_move(self.location);
+
+                // "accept" the packet
+                self.out.write(serverbound::TeleportConfirm { teleport_id });
+            }
+            PlayDisconnect::ID => {
+                let PlayDisconnect { reason } = data.read();
+                processor.on_disconnect(&reason);
+            }
+            PlayerListItem::ID => {
+                let PlayerListItem { players } = data.read();
+                for Player { uuid, list_type } in players {
+                    processor.on_player_list(uuid.0, list_type.into_update(uuid.0));
+                }
+            }
+            ChatMessage::ID => {
+                let ChatMessage { chat, .. } = data.read();
+                processor.on_chat(chat);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Interface754 {
+    tx: Rc<RefCell<PacketWriteChannel>>,
+    inv_action_id: u16,
+    flattening: &'static Flattening,
+}
+
+impl Interface754 {
+    fn new(tx: PacketWriteChannel, flattening: &'static Flattening) -> Interface754 {
+        Interface754 {
+            tx: Rc::new(RefCell::new(tx)),
+            inv_action_id: 0,
+            flattening,
+        }
+    }
+
+    fn slot(&self, stack: ItemStack) -> serverbound::Slot {
+        let id = self.flattening.item_id(stack.kind, stack.damage);
+        serverbound::Slot {
+            item: Some((VarInt(id as i32), stack.count)),
+        }
+    }
+
+    fn click(&mut self, slot: u16, button: impl Into<u8>, mode: i32, clicked: serverbound::Slot) {
+        let action_number = self.inv_action_id;
+        let to_send = serverbound::ClickWindow {
+            window_id: 0,
+            slot,
+            button: button.into(),
+            action_number,
+            mode: VarInt(mode),
+            clicked,
+        };
+
+        self.write(to_send);
+
+        self.inv_action_id += 1;
+    }
+
+    #[inline]
+    fn write<T: Packet + ByteWritable>(&self, packet: T) {
+        self.tx.borrow_mut().write(packet)
+    }
+}
+
+impl InterfaceOut for Interface754 {
+    fn place_block(&mut self, against: BlockLocation, face: Face) {
+        let face = VarInt(face as i32);
+
+        self.write(serverbound::PlaceBlock {
+            hand: Hand::Main,
+            location: Position(against),
+            face,
+            cursor: serverbound::BlockCursor {
+                x: 1.0,
+                y: 1.5,
+                z: 0.5,
+            },
+            inside_block: false,
+        });
+    }
+
+    fn attack_entity(&mut self, id: u32) {
+        self.write(serverbound::InteractEntity {
+            id: id.into(),
+            kind: InteractEntityKind::Attack,
+            sneaking: false,
+        })
+    }
+
+    fn send_chat(&mut self, message: &str) {
+        self.write(serverbound::ChatMessage {
+            message: message.to_string(),
+        });
+    }
+
+    fn inventory_action(&mut self, action: InvAction) {
+        match action {
+            InvAction::Q(slot) => self.click(slot, 0, 4, serverbound::Slot::EMPTY),
+            InvAction::CtrlQ(slot) => self.click(slot, 1, 4, serverbound::Slot::EMPTY),
+            InvAction::Click(slot, button, clicked) => {
+                let clicked = self.slot(clicked);
+                self.click(slot, button, 0, clicked)
+            }
+            InvAction::ShiftClick(slot, button, clicked) => {
+                let clicked = self.slot(clicked);
+                self.click(slot, button, 1, clicked)
+            }
+        }
+    }
+
+    fn swing_arm(&mut self) {
+        self.write(serverbound::ArmAnimation { hand: Hand::Main });
+    }
+
+    fn finish_eating(&mut self) {
+        self.write(serverbound::PlayerDig::status(
+            DigStatus::ShootArrowOrFinishEat,
+        ));
+    }
+
+    fn use_item(&mut self) {
+        self.write(serverbound::UseItem { hand: Hand::Main });
+    }
+
+    fn change_slot(&mut self, number: u8) {
+        self.write(serverbound::HeldItemChange {
+            slot: number as u16,
+        })
+    }
+
+    fn mine(&mut self, position: BlockLocation, mine: Mine, face: Face) {
+        let status = match mine {
+            Mine::Start => DigStatus::Started,
+            Mine::Cancel => DigStatus::Cancelled,
+            Mine::Finished => DigStatus::Finished,
+        };
+
+        if status == DigStatus::Started {
+            self.swing_arm();
+        }
+
+        self.write(serverbound::PlayerDig {
+            status,
+            position: Position(position),
+            face: face as u8,
+        });
+    }
+
+    fn respawn(&mut self) {
+        self.write(serverbound::ClientStatus {
+            action: ClientStatusAction::Respawn,
+        });
+    }
+
+    fn teleport(&mut self, location: Location) {
+        self.write(serverbound::PlayerPosition {
+            location,
+            on_ground: true,
+        });
+    }
+
+    fn look(&mut self, direction: Direction) {
+        self.write(serverbound::PlayerLook {
+            direction,
+            on_ground: false,
+        })
+    }
+
+    fn teleport_and_look(&mut self, location: Location, direction: Direction, on_ground: bool) {
+        self.write(serverbound::PlayerPositionAndRotation {
+            location,
+            direction,
+            on_ground,
+        })
+    }
+}
+
+pub struct Protocol;
+
+#[async_trait::async_trait]
+impl Minecraft for Protocol {
+    type Queue = EventQueue754;
+    type Interface = Interface754;
+
+    const PROTOCOL: u32 = 754;
+
+    async fn login(conn: Connection) -> Res<Login<EventQueue754, Interface754>> {
+        let flattening = Flattening::get()?;
+
+        let Connection {
+            user,
+            address,
+            mojang,
+            read,
+            write,
+            ..
+        } = conn;
+        let ValidUser {
+            username,
+            uuid,
+            access_id,
+            ..
+        } = user;
+
+        let Address { host, port } = address;
+        let uuid = UUID::from(&uuid);
+
+        let mut reader = PacketReader::from(read);
+        let mut writer = PacketWriter::from(write);
+
+        // START: handshake
+        writer
+            .write(serverbound::Handshake {
+                protocol_version: VarInt(754),
+                host,
+                port,
+                next_state: HandshakeNextState::Login,
+            })
+            .await?;
+
+        // START: login
+        writer
+            .write(serverbound::LoginStart {
+                username: username.clone(),
+            })
+            .await?;
+
+        // encryption (only online-mode), compression and plugin requests (proxies)
+        // can come in any order before login success
+        loop {
+            let mut data = reader.read().await?;
+            match data.id {
+                clientbound::EncryptionRequest::ID => {
+                    let clientbound::EncryptionRequest {
+                        public_key_der,
+                        verify_token,
+                        server_id,
+                    } = data.read();
+
+                    let rsa = Rsa::from_der(&public_key_der)?;
+
+                    let shared_secret = rand_bits();
+
+                    let encrypted_ss = rsa.encrypt(&shared_secret)?;
+                    let encrypted_verify = rsa.encrypt(&verify_token)?;
+
+                    // Mojang online mode requests
+                    let hash = calc_hash(&server_id, &shared_secret, &public_key_der);
+                    mojang.join(uuid, &hash, &access_id).await?;
+
+                    writer
+                        .write(serverbound::EncryptionResponse {
+                            shared_secret: encrypted_ss,
+                            verify_token: encrypted_verify,
+                        })
+                        .await?;
+
+                    // we now do everything encrypted
+                    writer.encryption(&shared_secret);
+                    reader.encryption(&shared_secret);
+                }
+                clientbound::SetCompression::ID => {
+                    let clientbound::SetCompression { threshold } = data.read();
+
+                    reader.compression(threshold.into());
+                    writer.compression(threshold.into());
+                }
+                clientbound::LoginPluginRequest::ID => {
+                    let clientbound::LoginPluginRequest { message_id, .. } = data.read();
+                    writer
+                        .write(serverbound::LoginPluginResponse {
+                            message_id,
+                            successful: false,
+                        })
+                        .await?;
+                }
+                clientbound::LoginSuccess::ID => break,
+                clientbound::Disconnect::ID => {
+                    let clientbound::Disconnect { reason } = data.read();
+                    return Err(Disconnected(reason));
+                }
+                actual => {
+                    return Err(WrongPacket {
+                        state: PacketState::Login,
+                        expected: clientbound::LoginSuccess::ID,
+                        actual,
+                    });
+                }
+            }
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (os_tx, os_rx) = tokio::sync::oneshot::channel();
+
+        tokio::task::spawn_local(async move {
+            let mut oneshot = Some(os_tx);
+            loop {
+                let packet = match reader.read().await {
+                    Ok(packet) => packet,
+
+                    // the socket closed or sent garbage. The bot disconnects with this error
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+                if packet.id == clientbound::JoinGame::ID {
+                    if let Some(os_tx) = oneshot.take() {
+                        let mut packet = packet.clone();
+                        let processed = match JoinGame::read(&mut packet.reader) {
+                            Ok(processed) => processed,
+                            Err(e) => {
+                                let _ = tx.send(Err(e));
+                                return;
+                            }
+                        };
+                        // the login could have given up already
+                        let _ = os_tx.send(processed.entity_id);
+                    }
+                }
+                match tx.send(Ok(packet)) {
+                    Ok(..) => {}
+                    Err(..) => {
+                        // the other end is stopped and should have printed the error
+                        return;
+                    }
+                }
+            }
+        });
+
+        let tx = writer.into_channel();
+
+        let entity_id = os_rx
+            .await
+            .map_err(|_| err("disconnected before join game packet"))?;
+
+        let out = Interface754::new(tx, flattening);
+
+        let queue = EventQueue754 {
+            rx,
+            out: out.clone(),
+            location: Default::default(),
+            flattening,
+            alive: true,
+            joined: false,
+            respawned: false,
+            capture: None,
+        };
+
+        let login = Login {
+            queue,
+            out,
+            info: ClientInfo {
+                username,
+                uuid,
+                entity_id,
+            },
+        };
+
+        Ok(login)
+    }
+
+    fn offline(
+        rx: std::sync::mpsc::Receiver<Res<PacketData>>,
+    ) -> Res<(EventQueue754, Interface754)> {
+        let flattening = Flattening::get()?;
+        let out = Interface754::new(PacketWriteChannel::discard(), flattening);
+
+        let queue = EventQueue754 {
+            rx,
+            out: out.clone(),
+            location: Default::default(),
+            flattening,
+            alive: true,
+            joined: false,
+            respawned: false,
+            capture: None,
+        };
+
+        Ok((queue, out))
+    }
+}
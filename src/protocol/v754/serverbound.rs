@@ -0,0 +1,178 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use swarm_bot_packets::{
+    types::VarInt,
+    write::{ByteWritable, ByteWriter},
+    *,
+};
+
+pub use crate::protocol::v340::serverbound::{
+    BlockCursor, ClientStatusAction, DigStatus, Hand, Handshake, HandshakeNextState,
+    InteractEntityKind, LoginStart,
+};
+use crate::{
+    protocol::v754::Position,
+    types::{Direction, Location},
+};
+
+#[derive(Packet, Writable)]
+#[packet(0x01, Login)]
+pub struct EncryptionResponse {
+    pub shared_secret: Vec<u8>,
+    pub verify_token: Vec<u8>,
+}
+
+#[derive(Packet, Writable)]
+#[packet(0x02, Login)]
+pub struct LoginPluginResponse {
+    pub message_id: VarInt,
+
+    /// false if we did not understand the request. Data only follows if true
+    pub successful: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x00, Play)]
+pub struct TeleportConfirm {
+    pub teleport_id: VarInt,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x03, Play)]
+pub struct ChatMessage {
+    pub message: String,
+}
+
+/// Respawning and show stats
+#[derive(Writable, Packet)]
+#[packet(0x04, Play)]
+pub struct ClientStatus {
+    pub action: ClientStatusAction,
+}
+
+/// 1.16 slot data. We never send NBT
+pub struct Slot {
+    pub item: Option<(VarInt, u8)>,
+}
+
+impl Slot {
+    pub const EMPTY: Slot = Slot { item: None };
+}
+
+impl ByteWritable for Slot {
+    fn write_to_bytes(self, writer: &mut ByteWriter) {
+        match self.item {
+            None => {
+                writer.write(false);
+            }
+            Some((id, count)) => {
+                writer.write(true).write(id).write(count).write(0_u8);
+            }
+        }
+    }
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x09, Play)]
+pub struct ClickWindow {
+    pub window_id: u8,
+    pub slot: u16,
+    pub button: u8,
+    pub action_number: u16,
+    pub mode: VarInt,
+    pub clicked: Slot,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x0e, Play)]
+pub struct InteractEntity {
+    pub id: VarInt,
+    pub kind: InteractEntityKind,
+    pub sneaking: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x10, Play)]
+pub struct KeepAlive {
+    pub id: u64,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x12, Play)]
+pub struct PlayerPosition {
+    pub location: Location,
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x13, Play)]
+pub struct PlayerPositionAndRotation {
+    pub location: Location,
+    pub direction: Direction,
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x14, Play)]
+pub struct PlayerLook {
+    pub direction: Direction,
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x1b, Play)]
+pub struct PlayerDig {
+    pub status: DigStatus,
+    pub position: Position,
+    pub face: u8,
+}
+
+impl PlayerDig {
+    pub fn status(status: DigStatus) -> PlayerDig {
+        Self {
+            status,
+            position: Position::default(),
+            face: 0,
+        }
+    }
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x25, Play)]
+pub struct HeldItemChange {
+    pub slot: u16,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x2c, Play)]
+pub struct ArmAnimation {
+    pub hand: Hand,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x2e, Play)]
+pub struct PlaceBlock {
+    pub hand: Hand,
+    pub location: Position,
+    pub face: VarInt,
+    pub cursor: BlockCursor,
+    pub inside_block: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x2f, Play)]
+pub struct UseItem {
+    pub hand: Hand,
+}
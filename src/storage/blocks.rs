@@ -12,7 +12,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 
 use float_ord::FloatOrd;
 use interfaces::types::{
@@ -26,9 +26,44 @@ use crate::{
     storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection},
 };
 
+/// A limit on the memory of the columns in [`WorldBlocks`]
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryCap {
+    pub bytes: usize,
+
+    /// Downgrade the least recently loaded columns to low memory columns
+    /// before evicting them
+    pub downgrade: bool,
+}
+
+/// The bots which have a column loaded
+#[derive(Default)]
+struct Loaded {
+    bots: HashSet<u32>,
+
+    /// when the column was last sent to a bot. Key of [`WorldBlocks::lru`]
+    last_loaded: u64,
+}
+
 #[derive(Default)]
 pub struct WorldBlocks {
     storage: HashMap<ChunkLocation, ChunkColumn>,
+
+    /// Columns loaded by a bot. Columns not in here (i.e., set with
+    /// [`WorldBlocks::set_block`]) are never evicted. A column evicted by the
+    /// cap stays in here until no bot has it loaded, so the block changes and
+    /// partial columns sent for it are ignored instead of bringing it back
+    /// incomplete
+    loaded: HashMap<ChunkLocation, Loaded>,
+
+    /// the loaded columns, least recently loaded first
+    lru: BTreeMap<u64, ChunkLocation>,
+    loads: u64,
+
+    /// an estimate of the bytes the columns use
+    memory: usize,
+
+    cap: Option<MemoryCap>,
 }
 
 struct HeapIter<T> {
@@ -164,12 +199,162 @@ impl WorldBlocks {
         }
     }
 
+    pub fn with_cap(cap: Option<MemoryCap>) -> WorldBlocks {
+        WorldBlocks {
+            cap,
+            ..WorldBlocks::default()
+        }
+    }
+
     pub fn add_column(&mut self, location: ChunkLocation, column: ChunkColumn) {
-        self.storage.insert(location, column);
+        self.memory += column.memory();
+        if let Some(old) = self.storage.insert(location, column) {
+            self.memory -= old.memory();
+        }
     }
 
     pub fn modify_column(&mut self, location: ChunkLocation, column: ChunkColumn) {
-        self.storage.get_mut(&location).unwrap().modify(column);
+        match self.storage.get_mut(&location) {
+            Some(current) => {
+                self.memory -= current.memory();
+                current.modify(column);
+                self.memory += current.memory();
+            }
+
+            // a partial column cannot replace one evicted by the cap
+            None if self.loaded.contains_key(&location) => {}
+            None => self.add_column(location, column),
+        }
+    }
+
+    /// A column sent to bot `bot_id`. It is kept until no bot has it loaded
+    pub fn load_column(&mut self, location: ChunkLocation, bot_id: u32, column: ChunkColumn) {
+        self.add_column(location, column);
+        self.touch(location, bot_id);
+        self.enforce_cap();
+    }
+
+    /// A partial column sent to bot `bot_id`
+    pub fn load_modified_column(
+        &mut self,
+        location: ChunkLocation,
+        bot_id: u32,
+        column: ChunkColumn,
+    ) {
+        if self.evicted(location) {
+            if let Some(loaded) = self.loaded.get_mut(&location) {
+                loaded.bots.insert(bot_id);
+            }
+            return;
+        }
+
+        self.modify_column(location, column);
+        self.touch(location, bot_id);
+        self.enforce_cap();
+    }
+
+    /// Bot `bot_id` unloaded the column. It is removed if no other bot has it
+    pub fn unload_column(&mut self, location: ChunkLocation, bot_id: u32) {
+        let loaded = match self.loaded.get_mut(&location) {
+            Some(loaded) => loaded,
+            None => return,
+        };
+
+        loaded.bots.remove(&bot_id);
+        if loaded.bots.is_empty() {
+            self.evict(location);
+        }
+    }
+
+    /// Unloads every column of bot `bot_id`, i.e., when it disconnects
+    pub fn unload_bot(&mut self, bot_id: u32) {
+        let locations: Vec<_> = self
+            .loaded
+            .iter()
+            .filter(|(_, loaded)| loaded.bots.contains(&bot_id))
+            .map(|(location, _)| *location)
+            .collect();
+
+        for location in locations {
+            self.unload_column(location, bot_id);
+        }
+    }
+
+    /// The number of columns stored
+    pub fn column_count(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// An estimate of the bytes the columns use
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    fn touch(&mut self, location: ChunkLocation, bot_id: u32) {
+        let loaded = self.loaded.entry(location).or_default();
+        loaded.bots.insert(bot_id);
+
+        self.lru.remove(&loaded.last_loaded);
+        self.loads += 1;
+        loaded.last_loaded = self.loads;
+        self.lru.insert(self.loads, location);
+    }
+
+    /// Whether the cap evicted the column while a bot still has it loaded
+    fn evicted(&self, location: ChunkLocation) -> bool {
+        self.loaded.contains_key(&location) && !self.storage.contains_key(&location)
+    }
+
+    /// Removes a column no bot has loaded
+    fn evict(&mut self, location: ChunkLocation) {
+        self.drop_column(location);
+        self.loaded.remove(&location);
+    }
+
+    /// Removes the blocks of a column but keeps which bots have it loaded
+    fn drop_column(&mut self, location: ChunkLocation) {
+        if let Some(loaded) = self.loaded.get(&location) {
+            self.lru.remove(&loaded.last_loaded);
+        }
+        if let Some(column) = self.storage.remove(&location) {
+            self.memory -= column.memory();
+        }
+    }
+
+    /// Downgrades and then evicts the least recently loaded columns until the
+    /// columns fit in the cap
+    fn enforce_cap(&mut self) {
+        let cap = match self.cap {
+            Some(cap) => cap,
+            None => return,
+        };
+
+        if cap.downgrade && self.memory > cap.bytes {
+            let locations: Vec<_> = self.lru.values().copied().collect();
+            for location in locations {
+                if self.memory <= cap.bytes {
+                    return;
+                }
+                let saves = match self.storage.get(&location) {
+                    Some(column) => column.downgraded_memory() < column.memory(),
+                    None => false,
+                };
+                if saves {
+                    let column = self.storage.remove(&location).unwrap();
+                    self.memory -= column.memory();
+                    self.add_column(location, column.downgrade());
+                }
+            }
+        }
+
+        while self.memory > cap.bytes {
+            match self.lru.values().next() {
+                Some(&location) => self.drop_column(location),
+
+                // only columns we cannot evict are left
+                None => return,
+            }
+        }
     }
 
     pub fn get_block(&self, location: BlockLocation) -> Option<BlockApprox> {
@@ -239,7 +424,7 @@ impl WorldBlocks {
         iterator.map(|node| node.contents)
     }
 
-    fn real_chunks(
+    pub fn real_chunks(
         &self,
     ) -> impl Iterator<Item = (&ChunkLocation, &ChunkData<HighMemoryChunkSection>)> + '_ {
         self.storage
@@ -299,8 +484,15 @@ impl WorldBlocks {
 
         let loc = ChunkLocation(chunk_x, chunk_z);
 
+        // a single block would bring back the column as air
+        if self.evicted(loc) {
+            return;
+        }
+
         let column = self.storage.entry(loc).or_default();
+        self.memory -= column.memory();
         column.set_block(x, y, z, block);
+        self.memory += column.memory();
     }
 
     pub fn get_block_simple(&self, location: BlockLocation) -> Option<SimpleType> {
@@ -328,10 +520,17 @@ mod tests {
     use test::{black_box, Bencher};
 
     use assert_matches::assert_matches as am;
-    use interfaces::types::{BlockApprox, BlockLocation, BlockState};
+    use interfaces::types::{BlockApprox, BlockLocation, BlockState, ChunkLocation, SimpleType};
+    use itertools::Itertools;
     use rand::Rng;
 
-    use crate::{schematic::Schematic, storage::blocks::WorldBlocks};
+    use crate::{
+        schematic::Schematic,
+        storage::{
+            blocks::{MemoryCap, WorldBlocks},
+            chunk::ChunkColumn,
+        },
+    };
 
     #[test]
     fn test_get_set() {
@@ -389,6 +588,105 @@ mod tests {
         }
     }
 
+    /// A column which has 16 kinds of stone up to y = 15. Downgrading it saves
+    /// memory as it needs 4 bits per block
+    fn stone_column() -> ChunkColumn {
+        let mut column = ChunkColumn::default();
+        for ((x, y), z) in (0..16).cartesian_product(0..16).cartesian_product(0..16) {
+            column.set_block(x, y, z, BlockState::from(1, x as u16));
+        }
+        column
+    }
+
+    #[test]
+    fn test_unload() {
+        let mut world = WorldBlocks::default();
+        let loc = ChunkLocation(0, 0);
+
+        world.load_column(loc, 0, stone_column());
+        world.load_column(loc, 1, stone_column());
+
+        world.unload_column(loc, 0);
+        assert!(world.get_block(BlockLocation::default()).is_some());
+
+        world.unload_column(loc, 1);
+        assert!(world.get_block(BlockLocation::default()).is_none());
+        assert_eq!(world.memory(), 0);
+
+        world.load_column(loc, 0, stone_column());
+        world.unload_bot(0);
+        assert_eq!(world.column_count(), 0);
+    }
+
+    #[test]
+    fn test_memory_cap() {
+        let column_memory = stone_column().memory();
+        let low_memory = stone_column().downgrade().memory();
+
+        let cap = MemoryCap {
+            bytes: column_memory + low_memory,
+            downgrade: true,
+        };
+        let mut world = WorldBlocks::with_cap(Some(cap));
+
+        world.load_column(ChunkLocation(0, 0), 0, stone_column());
+        world.load_column(ChunkLocation(1, 0), 0, stone_column());
+
+        // the least recently loaded column is downgraded
+        let first = world.get_block(BlockLocation::new(0, 0, 0));
+        am!(first, Some(BlockApprox::Estimate(SimpleType::Solid)));
+        let second = world.get_block(BlockLocation::new(16, 0, 0));
+        am!(second, Some(BlockApprox::Realized(BlockState::STONE)));
+        assert!(low_memory < column_memory);
+
+        // columns are evicted if they are not downgraded
+        let cap = MemoryCap {
+            bytes: 2 * column_memory,
+            downgrade: false,
+        };
+        let mut world = WorldBlocks::with_cap(Some(cap));
+
+        for x in 0..3 {
+            world.load_column(ChunkLocation(x, 0), 0, stone_column());
+        }
+        assert!(world.get_block(BlockLocation::new(0, 0, 0)).is_none());
+        assert_eq!(world.column_count(), 2);
+        assert!(world.memory() <= cap.bytes);
+    }
+
+    #[test]
+    fn test_evicted_updates() {
+        let cap = MemoryCap {
+            bytes: stone_column().memory(),
+            downgrade: false,
+        };
+        let mut world = WorldBlocks::with_cap(Some(cap));
+
+        let evicted = ChunkLocation(0, 0);
+        world.load_column(evicted, 0, stone_column());
+        world.load_column(ChunkLocation(1, 0), 0, stone_column());
+        assert_eq!(world.column_count(), 1);
+
+        // block changes and partial columns do not bring the column back
+        world.set_block(BlockLocation::new(0, 20, 0), BlockState::STONE);
+        world.load_modified_column(evicted, 1, stone_column());
+        assert!(world.get_block(BlockLocation::new(0, 0, 0)).is_none());
+        assert_eq!(world.column_count(), 1);
+        assert!(world.memory() <= cap.bytes);
+
+        // a full column does
+        world.load_column(evicted, 0, stone_column());
+        let block = world.get_block(BlockLocation::new(0, 0, 0));
+        am!(block, Some(BlockApprox::Realized(BlockState::STONE)));
+
+        // it is forgotten once every bot unloaded it
+        world.load_column(ChunkLocation(1, 0), 0, stone_column());
+        world.unload_column(evicted, 0);
+        world.unload_column(evicted, 1);
+        world.set_block(BlockLocation::new(0, 20, 0), BlockState::STONE);
+        assert!(world.get_block(BlockLocation::new(0, 20, 0)).is_some());
+    }
+
     #[bench]
     fn bench_get_block(b: &mut Bencher) {
         let mut world = WorldBlocks::default();
@@ -38,13 +38,26 @@ pub struct LowMemoryChunkSection {
 }
 
 impl Default for LowMemoryChunkSection {
+    /// every block is walk through, the same as a missing section
     fn default() -> Self {
         Self {
-            storage: [0; SECTION_BYTES],
+            storage: [!0; SECTION_BYTES],
         }
     }
 }
 
+impl From<&HighMemoryChunkSection> for LowMemoryChunkSection {
+    fn from(section: &HighMemoryChunkSection) -> Self {
+        let mut storage = [0; SECTION_BYTES];
+        for (block_number, state) in section.palette.all_states().iter().enumerate() {
+            let idx = block_number >> 2;
+            let offset = (block_number & 0b11) << 1;
+            storage[idx] |= state.simple_type().id() << offset;
+        }
+        Self { storage }
+    }
+}
+
 pub fn bits_needed(number: usize) -> u8 {
     // 1 bit can encode 2
     let mut start = 2;
@@ -65,7 +78,7 @@ impl LowMemoryChunkSection {
 
         // 2 bits per block
         let idx = block_number >> 2;
-        let offset = block_number - (idx << 2);
+        let offset = (block_number - (idx << 2)) << 1;
 
         let mut res = self.storage[idx];
         res >>= offset;
@@ -80,7 +93,7 @@ impl LowMemoryChunkSection {
 
         // 2 bits per block
         let idx = block_number >> 2;
-        let offset = block_number - (idx << 2);
+        let offset = (block_number - (idx << 2)) << 1;
 
         let mut block = self.storage[idx];
 
@@ -248,6 +261,64 @@ impl Palette {
         }
     }
 
+    /// Packs a section given every state (indexed by `(y * 16 + z) * 16 + x`).
+    /// Used by protocols which do not send the 1.12 layout
+    pub fn from_states(states: &[BlockState; 4096]) -> Palette {
+        let mut id_to_state: Vec<BlockState> = Vec::new();
+        let mut reverse_map = HashMap::new();
+        for &state in states.iter() {
+            reverse_map.entry(state).or_insert_with(|| {
+                id_to_state.push(state);
+                id_to_state.len() - 1
+            });
+        }
+
+        let indirect = id_to_state.len() <= 256;
+        let bits_per_block = if indirect {
+            bits_needed(id_to_state.len()).max(4)
+        } else {
+            13
+        } as usize;
+
+        let mut storage = vec![0_u64; 4096 * bits_per_block / 64];
+        for (block_number, state) in states.iter().enumerate() {
+            let value = if indirect {
+                reverse_map[state] as u64
+            } else {
+                state.0 as u64
+            };
+
+            let start_long = (block_number * bits_per_block) / 64;
+            let start_offset = (block_number * bits_per_block) % 64;
+            let end_long = ((block_number + 1) * bits_per_block - 1) / 64;
+
+            storage[start_long] |= value << start_offset;
+            if start_long != end_long {
+                storage[end_long] |= value >> (64 - start_offset);
+            }
+        }
+
+        Palette {
+            bits_per_block: bits_per_block as u8,
+            id_to_state: indirect.then(|| id_to_state),
+            storage,
+        }
+    }
+
+    pub fn bits_per_block(&self) -> u8 {
+        self.bits_per_block
+    }
+
+    /// The states of the palette ids. None for the direct (global) palette
+    pub fn id_to_state(&self) -> Option<&[BlockState]> {
+        self.id_to_state.as_deref()
+    }
+
+    /// The packed ids in the 1.12 layout
+    pub fn storage(&self) -> &[u64] {
+        &self.storage
+    }
+
     pub fn all_states(&self) -> [BlockState; 4096] {
         let mut res = [BlockState::AIR; 4096];
         (0..4096).for_each(|i| res[i] = self.get_block_by_idx(i));
@@ -422,16 +493,77 @@ impl Default for ChunkColumn {
 
 impl ChunkColumn {
     pub fn modify(&mut self, column: ChunkColumn) {
-        if let (ChunkColumn::HighMemory { data: left }, ChunkColumn::HighMemory { data: right }) =
-            (self, column)
-        {
-            for (idx, new_section) in IntoIterator::into_iter(right.sections).enumerate() {
-                if let Some(section) = new_section {
-                    left.sections[idx] = Some(section);
+        let right = match column {
+            ChunkColumn::HighMemory { data } => data,
+            ChunkColumn::LowMemory { .. } => panic!("cannot modify with low memory chunks"),
+        };
+
+        let sections = IntoIterator::into_iter(right.sections).enumerate();
+        match self {
+            ChunkColumn::HighMemory { data: left } => {
+                for (idx, new_section) in sections {
+                    if let Some(section) = new_section {
+                        left.sections[idx] = Some(section);
+                    }
                 }
             }
-        } else {
-            panic!("cannot modify low memory chunks");
+
+            // the column was downgraded since it was loaded
+            ChunkColumn::LowMemory { data: left } => {
+                for (idx, new_section) in sections {
+                    if let Some(section) = new_section {
+                        left.sections[idx] = Some(box LowMemoryChunkSection::from(&*section));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Only keeps the [`SimpleType`] of each block, which is enough to
+    /// pathfind through the column
+    pub fn downgrade(self) -> ChunkColumn {
+        match self {
+            ChunkColumn::HighMemory { data } => {
+                const INIT: Option<Box<LowMemoryChunkSection>> = None;
+                let mut sections = [INIT; 16];
+                for (idx, section) in data.sections.iter().enumerate() {
+                    if let Some(section) = section {
+                        sections[idx] = Some(box LowMemoryChunkSection::from(&**section));
+                    }
+                }
+                ChunkColumn::LowMemory {
+                    data: ChunkData { sections },
+                }
+            }
+            low => low,
+        }
+    }
+
+    /// The bytes the sections of the column use after
+    /// [`ChunkColumn::downgrade`]. It can be more than now for sections
+    /// with few kinds of blocks
+    pub fn downgraded_memory(&self) -> usize {
+        let sections = match self {
+            ChunkColumn::LowMemory { data } => data.sections.iter().flatten().count(),
+            ChunkColumn::HighMemory { data } => data.sections.iter().flatten().count(),
+        };
+        sections * SECTION_BYTES
+    }
+
+    /// An estimate of the bytes the sections of the column use
+    pub fn memory(&self) -> usize {
+        match self {
+            ChunkColumn::LowMemory { .. } => self.downgraded_memory(),
+            ChunkColumn::HighMemory { data } => data
+                .sections
+                .iter()
+                .flatten()
+                .map(|section| {
+                    let palette = &section.palette;
+                    let ids = palette.id_to_state().map_or(0, |ids| ids.len());
+                    palette.storage().len() * 8 + ids * std::mem::size_of::<BlockState>()
+                })
+                .sum(),
         }
     }
 
@@ -565,4 +697,18 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_palette_from_states() {
+        // 5 and 13 bits per block
+        for kinds in [20, 300] {
+            let mut states = [BlockState::AIR; 4096];
+            for (i, state) in states.iter_mut().enumerate() {
+                *state = BlockState((i % kinds) as u32);
+            }
+
+            let palette = Palette::from_states(&states);
+            assert_eq!(palette.all_states(), states);
+        }
+    }
 }
@@ -0,0 +1,206 @@
+// Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    ops::{Index, IndexMut},
+};
+
+use interfaces::types::Dimension;
+
+/// One `T` for each dimension. Chunks and entity ids of different dimensions
+/// overlap, so everything a bot sees is stored in the dimension it is in
+#[derive(Default)]
+pub struct Dimensions<T> {
+    overworld: T,
+    nether: T,
+    end: T,
+}
+
+impl<T> Dimensions<T> {
+    pub fn new(mut init: impl FnMut() -> T) -> Dimensions<T> {
+        Dimensions {
+            overworld: init(),
+            nether: init(),
+            end: init(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Dimension, &T)> + '_ {
+        IntoIterator::into_iter([
+            (Dimension::Overworld, &self.overworld),
+            (Dimension::Nether, &self.nether),
+            (Dimension::End, &self.end),
+        ])
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Dimension, &mut T)> + '_ {
+        IntoIterator::into_iter([
+            (Dimension::Overworld, &mut self.overworld),
+            (Dimension::Nether, &mut self.nether),
+            (Dimension::End, &mut self.end),
+        ])
+    }
+}
+
+impl<T> Index<Dimension> for Dimensions<T> {
+    type Output = T;
+
+    fn index(&self, dimension: Dimension) -> &T {
+        match dimension {
+            Dimension::Overworld => &self.overworld,
+            Dimension::Nether => &self.nether,
+            Dimension::End => &self.end,
+        }
+    }
+}
+
+impl<T> IndexMut<Dimension> for Dimensions<T> {
+    fn index_mut(&mut self, dimension: Dimension) -> &mut T {
+        match dimension {
+            Dimension::Overworld => &mut self.overworld,
+            Dimension::Nether => &mut self.nether,
+            Dimension::End => &mut self.end,
+        }
+    }
+}
+
+/// A dimension of one of the servers behind a proxy. Proxies move bots between
+/// servers, which have different chunks and reuse entity ids, so bots only
+/// share what they see with the bots on the same server
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct World {
+    /// the server. We cannot tell which server a proxy moved a bot to, so
+    /// every switch gets a new one
+    pub backend: u32,
+    pub dimension: Dimension,
+}
+
+/// One [`Dimensions`] for each backend. Created when a bot first modifies them
+pub struct Worlds<T> {
+    backends: HashMap<u32, Dimensions<T>>,
+    init: Box<dyn Fn() -> T + Send + Sync>,
+
+    /// what backends nobody has modified yet look like
+    empty: T,
+}
+
+impl<T: Default + 'static> Default for Worlds<T> {
+    fn default() -> Self {
+        Self::new(T::default)
+    }
+}
+
+impl<T> Worlds<T> {
+    pub fn new(init: impl Fn() -> T + Send + Sync + 'static) -> Worlds<T> {
+        Worlds {
+            backends: HashMap::new(),
+            empty: init(),
+            init: box init,
+        }
+    }
+
+    /// Drops the worlds of `backend`
+    pub fn remove(&mut self, backend: u32) {
+        self.backends.remove(&backend);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (World, &T)> + '_ {
+        self.backends.iter().flat_map(|(&backend, dimensions)| {
+            dimensions
+                .iter()
+                .map(move |(dimension, value)| (World { backend, dimension }, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (World, &mut T)> + '_ {
+        self.backends.iter_mut().flat_map(|(&backend, dimensions)| {
+            dimensions
+                .iter_mut()
+                .map(move |(dimension, value)| (World { backend, dimension }, value))
+        })
+    }
+}
+
+impl<T> Index<World> for Worlds<T> {
+    type Output = T;
+
+    fn index(&self, world: World) -> &T {
+        match self.backends.get(&world.backend) {
+            Some(dimensions) => &dimensions[world.dimension],
+            None => &self.empty,
+        }
+    }
+}
+
+impl<T> IndexMut<World> for Worlds<T> {
+    fn index_mut(&mut self, world: World) -> &mut T {
+        let init = &self.init;
+        let dimensions = self
+            .backends
+            .entry(world.backend)
+            .or_insert_with(|| Dimensions::new(init));
+        &mut dimensions[world.dimension]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use interfaces::types::{BlockLocation, BlockState, Dimension};
+
+    use crate::storage::{
+        blocks::WorldBlocks,
+        dimensions::{Dimensions, World, Worlds},
+    };
+
+    #[test]
+    fn test_dimensions() {
+        let mut blocks: Dimensions<WorldBlocks> = Dimensions::default();
+        let loc = BlockLocation::new(0, 0, 0);
+
+        blocks[Dimension::Overworld].set_block(loc, BlockState::STONE);
+        blocks[Dimension::Nether].set_block(loc, BlockState::AIR);
+
+        assert_eq!(
+            blocks[Dimension::Overworld].get_block_exact(loc),
+            Some(BlockState::STONE)
+        );
+        assert_eq!(
+            blocks[Dimension::Nether].get_block_exact(loc),
+            Some(BlockState::AIR)
+        );
+        assert_eq!(blocks[Dimension::End].get_block_exact(loc), None);
+    }
+
+    #[test]
+    fn test_worlds() {
+        let mut blocks: Worlds<WorldBlocks> = Worlds::default();
+        let loc = BlockLocation::new(0, 0, 0);
+
+        let first = World {
+            backend: 0,
+            dimension: Dimension::Overworld,
+        };
+        let second = World {
+            backend: 1,
+            ..first
+        };
+
+        blocks[first].set_block(loc, BlockState::STONE);
+
+        assert_eq!(blocks[first].get_block_exact(loc), Some(BlockState::STONE));
+        assert_eq!(blocks[second].get_block_exact(loc), None);
+        assert_eq!(blocks.iter().count(), 3);
+    }
+}
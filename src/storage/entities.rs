@@ -12,36 +12,282 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::collections::{HashMap, HashSet};
+
+use float_ord::FloatOrd;
 use indexmap::map::IndexMap;
+use interfaces::types::{
+    entity_types::{EntityCategory, EntityType, EntityTypes},
+    ChunkLocation,
+};
 
-use crate::types::{Location, LocationOrigin};
+use crate::{
+    client::state::local::inventory::ItemStack,
+    types::{Displacement, Location, LocationOrigin},
+};
 
 pub enum EntityKind {
+    /// a living entity whose type we do not know, i.e., on protocols whose mob
+    /// ids are not the 1.12 ones
     Normal,
-    Player { uuid: u128 },
+
+    /// a living entity with its 1.12 mob id
+    Mob {
+        type_id: u32,
+    },
+    Player {
+        uuid: u128,
+    },
+
+    /// items, arrows, falling blocks, boats, ...
+    Object {
+        type_id: u8,
+    },
+    ExperienceOrb,
+}
+
+impl EntityKind {
+    /// The object type of dropped items
+    pub const ITEM_TYPE: u8 = 2;
+
+    pub fn is_item(&self) -> bool {
+        matches!(self, EntityKind::Object { type_id } if *type_id == Self::ITEM_TYPE)
+    }
+
+    /// The mob or object type. None for players, XP orbs and unknown types
+    pub fn entity_type(&self, types: &'a EntityTypes) -> Option<&'a EntityType> {
+        match *self {
+            EntityKind::Mob { type_id } => types.mob(type_id),
+            EntityKind::Object { type_id } => types.object(u32::from(type_id)),
+            _ => None,
+        }
+    }
+
+    pub fn category(&self, types: &EntityTypes) -> EntityCategory {
+        self.entity_type(types)
+            .map_or(EntityCategory::Other, |kind| kind.category)
+    }
+
+    /// If the entity is a mob or object named `name`, i.e., `zombie`
+    pub fn is(&self, name: &str, types: &EntityTypes) -> bool {
+        self.entity_type(types)
+            .map_or(false, |kind| kind.name == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EquipmentSlot {
+    MainHand,
+    OffHand,
+    Boots,
+    Leggings,
+    Chestplate,
+    Helmet,
+}
+
+impl EquipmentSlot {
+    /// The slot of an Entity Equipment packet
+    pub fn from_id(id: i32) -> Option<EquipmentSlot> {
+        use EquipmentSlot::*;
+        let slot = match id {
+            0 => MainHand,
+            1 => OffHand,
+            2 => Boots,
+            3 => Leggings,
+            4 => Chestplate,
+            5 => Helmet,
+            _ => return None,
+        };
+        Some(slot)
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Equipment {
+    pub main_hand: Option<ItemStack>,
+    pub off_hand: Option<ItemStack>,
+
+    /// boots, leggings, chestplate and helmet
+    pub armor: [Option<ItemStack>; 4],
+}
+
+impl Equipment {
+    pub fn set(&mut self, slot: EquipmentSlot, item: Option<ItemStack>) {
+        let to_set = match slot {
+            EquipmentSlot::MainHand => &mut self.main_hand,
+            EquipmentSlot::OffHand => &mut self.off_hand,
+            EquipmentSlot::Boots => &mut self.armor[0],
+            EquipmentSlot::Leggings => &mut self.armor[1],
+            EquipmentSlot::Chestplate => &mut self.armor[2],
+            EquipmentSlot::Helmet => &mut self.armor[3],
+        };
+        *to_set = item;
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct EntityFlags {
+    pub burning: bool,
+    pub sneaking: bool,
+}
+
+/// A potion effect
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Effect {
+    pub id: u8,
+
+    /// 0 is level I
+    pub amplifier: u8,
+
+    /// in ticks, when the server sent the effect
+    pub duration: u32,
+}
+
+/// The entity metadata we use. Values not in a metadata packet are None
+#[derive(Default, Debug)]
+pub struct EntityMetadata {
+    pub flags: Option<EntityFlags>,
+    pub health: Option<f32>,
+
+    /// the stack of a dropped item
+    pub item: Option<ItemStack>,
 }
 
 pub struct EntityData {
     pub location: Location,
+
+    /// the bot which applies the updates of the entity. One of `seen_by`
     pub owner: Option<u32>,
+
+    /// the bots which have the entity spawned
+    pub seen_by: HashSet<u32>,
     pub kind: EntityKind,
+
+    /// None until the server sends it
+    pub health: Option<f32>,
+    pub equipment: Equipment,
+
+    /// blocks per tick
+    pub velocity: Displacement,
+    pub flags: EntityFlags,
+    pub effects: Vec<Effect>,
+
+    /// The stack of a dropped item. None until the server sends it
+    pub item: Option<ItemStack>,
 }
 
 #[derive(Default)]
 pub struct WorldEntities {
     /// map of Id => EntityData
     entities: IndexMap<u32, EntityData>,
+
+    /// the ids of the entities in each chunk column
+    chunks: HashMap<ChunkLocation, HashSet<u32>>,
+
+    /// map of player UUID => Id
+    players: HashMap<u128, u32>,
 }
 
 impl WorldEntities {
-    pub fn update_entity(&mut self, entity_id: u32, bot_id: u32, location: LocationOrigin) {
-        let entity = match self.entities.get_mut(&entity_id) {
-            None => return, // probably an unimportant entity (i.e., TNT)
+    /// The entity if `bot_id` owns it. Every bot receives the same updates so
+    /// only the owner applies them
+    fn owned_mut(&mut self, entity_id: u32, bot_id: u32) -> Option<&mut EntityData> {
+        // probably an unimportant entity (i.e., TNT) if it is not there
+        let entity = self.entities.get_mut(&entity_id)?;
+        let id = entity.owner.get_or_insert(bot_id);
+        (*id == bot_id).then_some(entity)
+    }
+
+    fn index(&mut self, entity_id: u32, location: Location) {
+        let chunk = ChunkLocation::from(location);
+        self.chunks.entry(chunk).or_default().insert(entity_id);
+    }
+
+    fn unindex(&mut self, entity_id: u32, location: Location) {
+        let chunk = ChunkLocation::from(location);
+        if let Some(ids) = self.chunks.get_mut(&chunk) {
+            ids.remove(&entity_id);
+            if ids.is_empty() {
+                self.chunks.remove(&chunk);
+            }
+        }
+    }
+
+    /// Moves the entity in the index if it changed chunks
+    fn reindex(&mut self, entity_id: u32, from: Location, to: Location) {
+        if ChunkLocation::from(from) != ChunkLocation::from(to) {
+            self.unindex(entity_id, from);
+            self.index(entity_id, to);
+        }
+    }
+
+    fn delete(&mut self, entity_id: u32) {
+        let entity = match self.entities.remove(&entity_id) {
             Some(entity) => entity,
+            None => return,
         };
-        let id = entity.owner.get_or_insert(bot_id);
-        if *id == bot_id {
-            entity.location.apply_change(location);
+
+        self.unindex(entity_id, entity.location);
+        if let EntityKind::Player { uuid } = entity.kind {
+            self.players.remove(&uuid);
+        }
+    }
+
+    pub fn update_entity(&mut self, entity_id: u32, bot_id: u32, location: LocationOrigin) {
+        let (from, to) = match self.owned_mut(entity_id, bot_id) {
+            Some(entity) => {
+                let from = entity.location;
+                entity.location.apply_change(location);
+                (from, entity.location)
+            }
+            None => return,
+        };
+        self.reindex(entity_id, from, to);
+    }
+
+    pub fn update_metadata(&mut self, entity_id: u32, bot_id: u32, metadata: EntityMetadata) {
+        if let Some(entity) = self.owned_mut(entity_id, bot_id) {
+            if let Some(flags) = metadata.flags {
+                entity.flags = flags;
+            }
+            if let Some(health) = metadata.health {
+                entity.health = Some(health);
+            }
+            if let Some(item) = metadata.item {
+                entity.item = Some(item);
+            }
+        }
+    }
+
+    pub fn update_equipment(
+        &mut self,
+        entity_id: u32,
+        bot_id: u32,
+        slot: EquipmentSlot,
+        item: Option<ItemStack>,
+    ) {
+        if let Some(entity) = self.owned_mut(entity_id, bot_id) {
+            entity.equipment.set(slot, item);
+        }
+    }
+
+    pub fn update_velocity(&mut self, entity_id: u32, bot_id: u32, velocity: Displacement) {
+        if let Some(entity) = self.owned_mut(entity_id, bot_id) {
+            entity.velocity = velocity;
+        }
+    }
+
+    /// Adds the effect or replaces the effect with the same id
+    pub fn add_effect(&mut self, entity_id: u32, bot_id: u32, effect: Effect) {
+        if let Some(entity) = self.owned_mut(entity_id, bot_id) {
+            entity.effects.retain(|other| other.id != effect.id);
+            entity.effects.push(effect);
+        }
+    }
+
+    pub fn remove_effect(&mut self, entity_id: u32, bot_id: u32, effect_id: u8) {
+        if let Some(entity) = self.owned_mut(entity_id, bot_id) {
+            entity.effects.retain(|effect| effect.id != effect_id);
         }
     }
 
@@ -54,21 +300,82 @@ impl WorldEntities {
     }
 
     pub fn by_player_uuid(&self, uuid: u128) -> Option<u32> {
-        self.iter().find_map(|(id, data)| match data.kind {
-            EntityKind::Normal => None,
-            EntityKind::Player { uuid: player_uuid } => (player_uuid == uuid).then_some(*id),
-        })
+        self.players.get(&uuid).copied()
+    }
+
+    /// The entities within `radius` of `location`
+    pub fn within(
+        &self,
+        location: Location,
+        radius: f64,
+    ) -> impl Iterator<Item = (u32, &EntityData)> + '_ {
+        let corner = Displacement::new(radius, 0.0, radius);
+        let ChunkLocation(min_x, min_z) = ChunkLocation::from(location - corner);
+        let ChunkLocation(max_x, max_z) = ChunkLocation::from(location + corner);
+
+        (min_x..=max_x)
+            .flat_map(move |x| (min_z..=max_z).map(move |z| ChunkLocation(x, z)))
+            .filter_map(|chunk| self.chunks.get(&chunk))
+            .flatten()
+            .filter_map(|id| Some((*id, self.entities.get(id)?)))
+            .filter(move |(_, data)| data.location.dist2(location) <= radius * radius)
     }
 
+    /// The closest entity within `radius` of `location` which matches `filter`
+    pub fn nearest(
+        &self,
+        location: Location,
+        radius: f64,
+        mut filter: impl FnMut(u32, &EntityData) -> bool,
+    ) -> Option<(u32, &EntityData)> {
+        self.within(location, radius)
+            .filter(|(id, data)| filter(*id, data))
+            .min_by_key(|(_, data)| FloatOrd(data.location.dist2(location)))
+    }
+
+    /// The players whose UUIDs are not in `swarm`
+    pub fn players_not_in(
+        &'a self,
+        swarm: &'a HashSet<u128>,
+    ) -> impl Iterator<Item = (u32, &EntityData)> + 'a {
+        self.players
+            .iter()
+            .filter(move |(uuid, _)| !swarm.contains(uuid))
+            .filter_map(|(_, id)| Some((*id, self.entities.get(id)?)))
+    }
+
+    /// An item or XP orb was picked up, so it no longer exists for any bot
+    pub fn collect(&mut self, entity_id: u32) {
+        self.delete(entity_id);
+    }
+
+    /// Bot `bot_id` no longer sees the entity. It is removed if no bot sees it
     pub fn remove_entity(&mut self, entity_id: u32, bot_id: u32) {
-        let entity = self.entities.get_mut(&entity_id);
-        let entity = match entity {
+        let entity = match self.entities.get_mut(&entity_id) {
             None => return,
             Some(inner) => inner,
         };
 
-        if entity.owner == Some(bot_id) {
-            entity.owner = None;
+        entity.seen_by.remove(&bot_id);
+        if entity.seen_by.is_empty() {
+            self.delete(entity_id);
+        } else if entity.owner == Some(bot_id) {
+            // another bot which sees it has received the same updates
+            entity.owner = entity.seen_by.iter().next().copied();
+        }
+    }
+
+    /// Bot `bot_id` no longer sees any entity, i.e., when it disconnects
+    pub fn remove_bot(&mut self, bot_id: u32) {
+        let ids: Vec<_> = self
+            .entities
+            .iter()
+            .filter(|(_, entity)| entity.seen_by.contains(&bot_id))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in ids {
+            self.remove_entity(id, bot_id);
         }
     }
 
@@ -79,13 +386,151 @@ impl WorldEntities {
         location: Location,
         kind: EntityKind,
     ) {
+        // another bot sees it already
+        if let Some(entity) = self.entities.get_mut(&entity_id) {
+            entity.seen_by.insert(bot_id);
+            return;
+        }
+
+        if let EntityKind::Player { uuid } = kind {
+            self.players.insert(uuid, entity_id);
+        }
+        self.index(entity_id, location);
+
         self.entities.insert(
             entity_id,
             EntityData {
                 location,
                 owner: Some(bot_id),
+                seen_by: HashSet::from([bot_id]),
                 kind,
+                health: None,
+                equipment: Equipment::default(),
+                velocity: Displacement::default(),
+                flags: EntityFlags::default(),
+                effects: Vec::new(),
+                item: None,
             },
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use interfaces::types::entity_types::{EntityCategory, EntityTypes};
+
+    use crate::{
+        storage::entities::{Effect, EntityKind, EntityMetadata, WorldEntities},
+        types::Location,
+    };
+
+    #[test]
+    fn test_only_owner_updates() {
+        let mut entities = WorldEntities::default();
+        entities.put_entity(1, 0, Location::default(), EntityKind::Normal);
+
+        let metadata = |health| EntityMetadata {
+            health: Some(health),
+            ..EntityMetadata::default()
+        };
+
+        entities.update_metadata(1, 1, metadata(5.0));
+        entities.update_metadata(1, 0, metadata(10.0));
+
+        let effect = |amplifier| Effect {
+            id: 1,
+            amplifier,
+            duration: 100,
+        };
+        entities.add_effect(1, 0, effect(0));
+        entities.add_effect(1, 0, effect(1));
+
+        let (_, entity) = entities.iter().next().unwrap();
+        assert_eq!(entity.health, Some(10.0));
+        assert_eq!(entity.effects, vec![effect(1)]);
+
+        entities.remove_effect(1, 0, 1);
+        let (_, entity) = entities.iter().next().unwrap();
+        assert!(entity.effects.is_empty());
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut entities = WorldEntities::default();
+        entities.put_entity(1, 0, Location::default(), EntityKind::Normal);
+        entities.put_entity(1, 1, Location::default(), EntityKind::Normal);
+
+        // bot 1 takes over when bot 0 no longer sees it
+        entities.remove_entity(1, 0);
+        entities.update_entity(1, 1, Location::new(20.0, 0.0, 0.0).into());
+        assert_eq!(entities.by_id(1).unwrap().owner, Some(1));
+        assert_eq!(
+            entities.within(Location::new(20.0, 0.0, 0.0), 1.0).count(),
+            1
+        );
+
+        entities.remove_bot(1);
+        assert!(entities.by_id(1).is_none());
+        assert_eq!(entities.within(Location::default(), 100.0).count(), 0);
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut entities = WorldEntities::default();
+        let item = EntityKind::Object {
+            type_id: EntityKind::ITEM_TYPE,
+        };
+        entities.put_entity(1, 0, Location::new(40.0, 0.0, 0.0), item);
+        entities.put_entity(2, 0, Location::new(5.0, 0.0, 0.0), EntityKind::Normal);
+
+        let player = |uuid| EntityKind::Player { uuid };
+        entities.put_entity(3, 0, Location::new(-20.0, 0.0, 0.0), player(10));
+        entities.put_entity(4, 0, Location::default(), player(11));
+
+        let mut near: Vec<_> = entities
+            .within(Location::default(), 10.0)
+            .map(|(id, _)| id)
+            .collect();
+        near.sort_unstable();
+        assert_eq!(near, vec![2, 4]);
+
+        let item = entities.nearest(Location::default(), 50.0, |_, e| e.kind.is_item());
+        assert_eq!(item.map(|(id, _)| id), Some(1));
+        assert!(entities
+            .nearest(Location::default(), 30.0, |_, e| e.kind.is_item())
+            .is_none());
+
+        assert_eq!(entities.by_player_uuid(10), Some(3));
+        let swarm = HashSet::from([11]);
+        let others: Vec<_> = entities.players_not_in(&swarm).map(|(id, _)| id).collect();
+        assert_eq!(others, vec![3]);
+
+        entities.collect(1);
+        assert!(entities.by_id(1).is_none());
+    }
+
+    #[test]
+    fn test_types() {
+        let types = EntityTypes::read().unwrap();
+
+        let mob = |type_id| EntityKind::Mob { type_id };
+        let zombie = mob(types.by_name("zombie").unwrap().id);
+        assert!(zombie.is("zombie", &types));
+        assert_eq!(zombie.category(&types), EntityCategory::Hostile);
+        assert_eq!(mob(50).category(&types), EntityCategory::Hostile);
+        assert_eq!(mob(95).category(&types), EntityCategory::Neutral);
+        assert_eq!(mob(90).category(&types), EntityCategory::Passive);
+
+        // object 50 is primed TNT, not a creeper
+        let object = |type_id| EntityKind::Object { type_id };
+        assert!(object(50).is("tnt", &types));
+        assert_eq!(object(60).category(&types), EntityCategory::Projectile);
+        assert_eq!(
+            object(EntityKind::ITEM_TYPE).category(&types),
+            EntityCategory::Other
+        );
+        assert_eq!(EntityKind::Normal.category(&types), EntityCategory::Other);
+    }
+}
@@ -14,4 +14,5 @@
 
 pub mod blocks;
 pub mod chunk;
+pub mod dimensions;
 pub mod entities;